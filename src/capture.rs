@@ -0,0 +1,438 @@
+//! # Capture ring
+//!
+//! Stores captured frames in a ring of storage pages, for long unattended
+//! captures into a large storage such as a QSPI flash. The oldest page is
+//! erased and reused when the ring is full. The capture is read back as
+//! pcap records, link type IEEE 802.15.4 without FCS, to be written after
+//! `PCAP_HEADER` into a pcap file.
+//!
+//! The first word of each page holds the page sequence number, the page
+//! with the highest number is the one being written. Records follow, each
+//! record is a header word with the frame length, the timestamp in
+//! microseconds in two words and the frame, padded to whole words.
+//!
+//! ```notrust
+//! | sequence | length | time low | time high | frame ... | length | ...
+//! ```
+//!
+//! The ring is restored from the storage on creation, so that capturing
+//! continues after a reboot.
+//!
+
+use crate::phy::MAX_FRAME_LENGTH;
+use crate::storage::{WordStorage, ERASED};
+
+/// pcap file header, little-endian, microsecond timestamps, link type
+/// IEEE 802.15.4 without FCS (230)
+pub const PCAP_HEADER: [u8; 24] = [
+    0xd4, 0xc3, 0xb2, 0xa1, // Magic number
+    0x02, 0x00, 0x04, 0x00, // Version 2.4
+    0x00, 0x00, 0x00, 0x00, // Time zone
+    0x00, 0x00, 0x00, 0x00, // Timestamp accuracy
+    0x7d, 0x00, 0x00, 0x00, // Snapshot length, 125
+    0xe6, 0x00, 0x00, 0x00, // Link type
+];
+
+/// Length of a pcap record header
+pub const PCAP_RECORD_HEADER_LENGTH: usize = 16;
+
+/// Marks a record header word, the frame length is in the low half
+const RECORD_MARK: u32 = 0x5043_0000;
+/// Mask of the mark in a record header word
+const RECORD_MARK_MASK: u32 = 0xffff_0000;
+/// Words of a record before the frame
+const RECORD_HEADER_WORDS: usize = 3;
+
+/// Words used by a frame of `length` octets
+fn frame_words(length: usize) -> usize {
+    length.div_ceil(4)
+}
+
+/// Errors returned by the capture ring
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CaptureError<E> {
+    /// Storage error
+    Storage(E),
+    /// The storage has less than two pages, or pages too small for a frame
+    InvalidStorage,
+    /// The frame is empty or longer than 125 octets
+    InvalidLength,
+    /// The buffer is too small for the record
+    BufferTooSmall,
+}
+
+#[cfg(feature = "fmt")]
+impl<E: core::fmt::Display> core::fmt::Display for CaptureError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CaptureError::Storage(error) => write!(f, "storage error, {}", error),
+            CaptureError::InvalidStorage => f.write_str("invalid storage"),
+            CaptureError::InvalidLength => f.write_str("invalid frame length"),
+            CaptureError::BufferTooSmall => f.write_str("buffer too small"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl<E: core::fmt::Debug + core::fmt::Display> core::error::Error for CaptureError<E> {}
+
+/// Position of the next record to read
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Cursor {
+    page: usize,
+    index: usize,
+    /// Pages left to read after the current page
+    pages_left: usize,
+}
+
+/// Ring of captured frames stored in `S`
+pub struct CaptureRing<S: WordStorage> {
+    storage: S,
+    /// Page being written and its sequence number, None if empty
+    head: Option<(usize, u32)>,
+    /// Next free word in the head page
+    index: usize,
+}
+
+impl<S: WordStorage> CaptureRing<S> {
+    /// Restore the ring from storage
+    ///
+    /// A new storage, with all pages erased, starts empty.
+    pub fn new(storage: S) -> Result<Self, CaptureError<S::Error>> {
+        let largest = RECORD_HEADER_WORDS + frame_words(MAX_FRAME_LENGTH);
+        if storage.pages() < 2 || storage.page_words() <= largest {
+            return Err(CaptureError::InvalidStorage);
+        }
+        let mut ring = Self {
+            storage,
+            head: None,
+            index: 0,
+        };
+        ring.restore();
+        Ok(ring)
+    }
+
+    fn restore(&mut self) {
+        let head = (0..self.storage.pages())
+            .map(|page| (page, self.storage.read(page, 0)))
+            .filter(|(_, sequence)| *sequence != ERASED)
+            .max_by_key(|(_, sequence)| *sequence);
+        if let Some((page, _)) = head {
+            self.head = head;
+            let mut index = 1;
+            while let Some(next) = self.next_index(page, index) {
+                index = next;
+            }
+            // A damaged record ends the page
+            self.index = match self.storage.read(page, index) {
+                ERASED => index,
+                _ => self.storage.page_words(),
+            };
+        }
+    }
+
+    /// Index of the record following the record at `index`, or None if there
+    /// is no valid record at `index`
+    fn next_index(&self, page: usize, index: usize) -> Option<usize> {
+        let words = self.storage.page_words();
+        if index >= words {
+            return None;
+        }
+        let header = self.storage.read(page, index);
+        if header & RECORD_MARK_MASK != RECORD_MARK {
+            return None;
+        }
+        let length = (header & !RECORD_MARK_MASK) as usize;
+        let next = index + RECORD_HEADER_WORDS + frame_words(length);
+        if length == 0 || length > MAX_FRAME_LENGTH || next > words {
+            return None;
+        }
+        Some(next)
+    }
+
+    /// Store a frame received at `timestamp` microseconds
+    ///
+    /// `frame` is the frame without FCS. The oldest page is erased when the
+    /// ring is full.
+    pub fn append(&mut self, frame: &[u8], timestamp: u64) -> Result<(), CaptureError<S::Error>> {
+        if frame.is_empty() || frame.len() > MAX_FRAME_LENGTH {
+            return Err(CaptureError::InvalidLength);
+        }
+        let needed = RECORD_HEADER_WORDS + frame_words(frame.len());
+        let page = match self.head {
+            Some((page, _)) if self.index + needed <= self.storage.page_words() => page,
+            _ => self.advance()?,
+        };
+        let header = RECORD_MARK | frame.len() as u32;
+        self.write(page, header)?;
+        self.write(page, timestamp as u32)?;
+        self.write(page, (timestamp >> 32) as u32)?;
+        for chunk in frame.chunks(4) {
+            let mut word = [0xffu8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.write(page, u32::from_le_bytes(word))?;
+        }
+        Ok(())
+    }
+
+    /// Start writing the next page, erasing the oldest records
+    fn advance(&mut self) -> Result<usize, CaptureError<S::Error>> {
+        let (page, sequence) = match self.head {
+            Some((page, sequence)) => ((page + 1) % self.storage.pages(), sequence + 1),
+            None => (0, 0),
+        };
+        self.storage.erase(page).map_err(CaptureError::Storage)?;
+        self.head = Some((page, sequence));
+        self.index = 0;
+        self.write(page, sequence)?;
+        Ok(page)
+    }
+
+    fn write(&mut self, page: usize, value: u32) -> Result<(), CaptureError<S::Error>> {
+        self.storage
+            .write(page, self.index, value)
+            .map_err(CaptureError::Storage)?;
+        self.index += 1;
+        Ok(())
+    }
+
+    /// Erase all records
+    pub fn clear(&mut self) -> Result<(), CaptureError<S::Error>> {
+        for page in 0..self.storage.pages() {
+            if self.storage.read(page, 0) != ERASED {
+                self.storage.erase(page).map_err(CaptureError::Storage)?;
+            }
+        }
+        self.head = None;
+        self.index = 0;
+        Ok(())
+    }
+
+    /// Cursor at the oldest record
+    pub fn cursor(&self) -> Cursor {
+        let pages = self.storage.pages();
+        match self.head {
+            Some((head, _)) => {
+                // The page after the head is the oldest if it has been written
+                let oldest = (1..=pages)
+                    .map(|offset| (head + offset) % pages)
+                    .find(|page| self.storage.read(*page, 0) != ERASED)
+                    .unwrap_or(head);
+                Cursor {
+                    page: oldest,
+                    index: 1,
+                    pages_left: (head + pages - oldest) % pages,
+                }
+            }
+            None => Cursor {
+                page: 0,
+                index: self.storage.page_words(),
+                pages_left: 0,
+            },
+        }
+    }
+
+    /// Read the record at `cursor` as a pcap record into `buffer` and advance
+    /// the cursor
+    ///
+    /// Records in a page erased by `append` while reading are lost, the
+    /// cursor continues with the records written in their place.
+    ///
+    /// # Return
+    ///
+    /// Returns the length of the pcap record, or None when all records have
+    /// been read.
+    ///
+    pub fn read_record(
+        &self,
+        cursor: &mut Cursor,
+        buffer: &mut [u8],
+    ) -> Result<Option<usize>, CaptureError<S::Error>> {
+        let next = loop {
+            if let Some(next) = self.next_index(cursor.page, cursor.index) {
+                break next;
+            }
+            if cursor.pages_left == 0 {
+                return Ok(None);
+            }
+            cursor.page = (cursor.page + 1) % self.storage.pages();
+            cursor.index = 1;
+            cursor.pages_left -= 1;
+        };
+        let header = self.storage.read(cursor.page, cursor.index);
+        let length = (header & !RECORD_MARK_MASK) as usize;
+        if buffer.len() < PCAP_RECORD_HEADER_LENGTH + length {
+            return Err(CaptureError::BufferTooSmall);
+        }
+        let low = u64::from(self.storage.read(cursor.page, cursor.index + 1));
+        let high = u64::from(self.storage.read(cursor.page, cursor.index + 2));
+        let timestamp = high << 32 | low;
+        let seconds = (timestamp / 1_000_000) as u32;
+        let microseconds = (timestamp % 1_000_000) as u32;
+        buffer[0..4].copy_from_slice(&seconds.to_le_bytes());
+        buffer[4..8].copy_from_slice(&microseconds.to_le_bytes());
+        buffer[8..12].copy_from_slice(&(length as u32).to_le_bytes());
+        buffer[12..16].copy_from_slice(&(length as u32).to_le_bytes());
+        let frame = &mut buffer[PCAP_RECORD_HEADER_LENGTH..PCAP_RECORD_HEADER_LENGTH + length];
+        for (n, chunk) in frame.chunks_mut(4).enumerate() {
+            let word = self
+                .storage
+                .read(cursor.page, cursor.index + RECORD_HEADER_WORDS + n);
+            chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+        }
+        cursor.index = next;
+        Ok(Some(PCAP_RECORD_HEADER_LENGTH + length))
+    }
+
+    /// Release the storage
+    pub fn free(self) -> S {
+        self.storage
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::storage::MockStorage;
+
+    /// Three pages of twelve 8 octet frames each
+    type Storage = MockStorage<3, 64>;
+    const FRAMES_PER_PAGE: u8 = 12;
+
+    fn frame(n: u8) -> [u8; 8] {
+        [n, 1, 2, 3, 4, 5, 6, 7]
+    }
+
+    /// Read all records, check their content and return the first frame
+    /// number and the number of records
+    fn read_all(ring: &CaptureRing<Storage>) -> (Option<u8>, usize) {
+        let mut cursor = ring.cursor();
+        let mut buffer = [0u8; PCAP_RECORD_HEADER_LENGTH + 8];
+        let mut first = None;
+        let mut count = 0;
+        while let Some(length) = ring.read_record(&mut cursor, &mut buffer).unwrap() {
+            assert_eq!(length, PCAP_RECORD_HEADER_LENGTH + 8);
+            let n = buffer[PCAP_RECORD_HEADER_LENGTH];
+            assert_eq!(buffer[PCAP_RECORD_HEADER_LENGTH..], frame(n));
+            let expected = first.unwrap_or(n) as usize + count;
+            assert_eq!(n as usize, expected);
+            first.get_or_insert(n);
+            count += 1;
+        }
+        (first, count)
+    }
+
+    #[test]
+    fn invalid_storage() {
+        assert_eq!(
+            CaptureRing::new(MockStorage::<1, 64>::new()).err(),
+            Some(CaptureError::InvalidStorage)
+        );
+        assert_eq!(
+            CaptureRing::new(MockStorage::<2, 35>::new()).err(),
+            Some(CaptureError::InvalidStorage)
+        );
+        assert!(CaptureRing::new(MockStorage::<2, 36>::new()).is_ok());
+    }
+
+    #[test]
+    fn frame_length_boundaries() {
+        let mut ring = CaptureRing::new(Storage::new()).unwrap();
+        assert_eq!(ring.append(&[], 0), Err(CaptureError::InvalidLength));
+        assert_eq!(
+            ring.append(&[0; MAX_FRAME_LENGTH + 1], 0),
+            Err(CaptureError::InvalidLength)
+        );
+        assert_eq!(ring.append(&[0xa5; MAX_FRAME_LENGTH], 0), Ok(()));
+        let mut cursor = ring.cursor();
+        let mut buffer = [0u8; PCAP_RECORD_HEADER_LENGTH + MAX_FRAME_LENGTH];
+        assert_eq!(
+            ring.read_record(&mut cursor, &mut buffer[..PCAP_RECORD_HEADER_LENGTH + 124]),
+            Err(CaptureError::BufferTooSmall)
+        );
+        assert_eq!(
+            ring.read_record(&mut cursor, &mut buffer),
+            Ok(Some(PCAP_RECORD_HEADER_LENGTH + MAX_FRAME_LENGTH))
+        );
+        assert!(buffer[PCAP_RECORD_HEADER_LENGTH..]
+            .iter()
+            .all(|b| *b == 0xa5));
+        assert_eq!(ring.read_record(&mut cursor, &mut buffer), Ok(None));
+    }
+
+    #[test]
+    fn read_record_order() {
+        let mut ring = CaptureRing::new(Storage::new()).unwrap();
+        let mut cursor = ring.cursor();
+        let mut buffer = [0u8; 32];
+        assert_eq!(ring.read_record(&mut cursor, &mut buffer), Ok(None));
+        ring.append(&[1, 2, 3], 1_500_000).unwrap();
+        ring.append(&[4, 5, 6, 7, 8], 0x1_0000_0000).unwrap();
+        let mut cursor = ring.cursor();
+        assert_eq!(ring.read_record(&mut cursor, &mut buffer), Ok(Some(19)));
+        assert_eq!(
+            buffer[..19],
+            [1, 0, 0, 0, 0x20, 0xa1, 0x07, 0, 3, 0, 0, 0, 3, 0, 0, 0, 1, 2, 3]
+        );
+        assert_eq!(ring.read_record(&mut cursor, &mut buffer), Ok(Some(21)));
+        // 4294 s and 967296 us
+        assert_eq!(
+            buffer[..21],
+            [0xc6, 0x10, 0, 0, 0x80, 0xc2, 0x0e, 0, 5, 0, 0, 0, 5, 0, 0, 0, 4, 5, 6, 7, 8]
+        );
+        assert_eq!(ring.read_record(&mut cursor, &mut buffer), Ok(None));
+        // Records appended after reaching the end are read by the same cursor
+        ring.append(&[9], 0).unwrap();
+        assert_eq!(ring.read_record(&mut cursor, &mut buffer), Ok(Some(17)));
+        assert_eq!(buffer[16], 9);
+    }
+
+    #[test]
+    fn restore_after_reboot() {
+        let mut ring = CaptureRing::new(Storage::new()).unwrap();
+        for n in 0..FRAMES_PER_PAGE + 3 {
+            ring.append(&frame(n), u64::from(n)).unwrap();
+        }
+        let storage = ring.free();
+        let mut ring = CaptureRing::new(storage).unwrap();
+        assert_eq!(read_all(&ring), (Some(0), 15));
+        ring.append(&frame(15), 15).unwrap();
+        assert_eq!(read_all(&ring), (Some(0), 16));
+        let storage = ring.free();
+        assert_eq!(storage.erases(0), 1);
+        assert_eq!(storage.erases(1), 1);
+        assert_eq!(storage.erases(2), 0);
+    }
+
+    #[test]
+    fn wrap_around_erases_oldest() {
+        let mut ring = CaptureRing::new(Storage::new()).unwrap();
+        for n in 0..3 * FRAMES_PER_PAGE {
+            ring.append(&frame(n), u64::from(n)).unwrap();
+        }
+        assert_eq!(read_all(&ring), (Some(0), 36));
+        ring.append(&frame(36), 36).unwrap();
+        assert_eq!(read_all(&ring), (Some(FRAMES_PER_PAGE), 25));
+        // The wrapped ring is restored with the oldest page following the head
+        let storage = ring.free();
+        assert_eq!(storage.erases(0), 2);
+        let mut ring = CaptureRing::new(storage).unwrap();
+        assert_eq!(read_all(&ring), (Some(FRAMES_PER_PAGE), 25));
+        for n in 37..4 * FRAMES_PER_PAGE + 1 {
+            ring.append(&frame(n), u64::from(n)).unwrap();
+        }
+        assert_eq!(read_all(&ring), (Some(2 * FRAMES_PER_PAGE), 25));
+    }
+
+    #[test]
+    fn clear() {
+        let mut ring = CaptureRing::new(Storage::new()).unwrap();
+        ring.append(&frame(0), 0).unwrap();
+        ring.clear().unwrap();
+        assert_eq!(read_all(&ring), (None, 0));
+        let ring = CaptureRing::new(ring.free()).unwrap();
+        assert_eq!(read_all(&ring), (None, 0));
+    }
+}
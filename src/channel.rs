@@ -0,0 +1,187 @@
+//! # Channel selection
+//!
+//! Rank the 802.15.4 channels for forming a network. Each channel is rated by
+//! the energy level from a energy detect sweep and by the number of PANs
+//! found on it by a active scan.
+//!
+//! Channels with a energy level at or below the threshold are preferred, then
+//! channels with the fewest PANs and last the channels with the lowest
+//! energy level.
+//!
+//! The active scan is done by the MAC layer, the number of PANs found on
+//! each channel is added with `ChannelSurvey::add_pan`.
+//!
+
+use crate::radio::{is_valid_channel, Radio, ScanDuration};
+
+/// First 802.15.4 channel
+const FIRST_CHANNEL: u8 = 11;
+/// Number of 802.15.4 channels
+pub const CHANNEL_COUNT: usize = 16;
+/// Channel mask with all 802.15.4 channels
+pub const ALL_CHANNELS: u32 = 0x07ff_f800;
+/// Number of polls waiting for the energy detect result, for each energy
+/// detect iteration of 128 μs
+const ED_ATTEMPTS_PER_ITERATION: u32 = 1_000;
+
+/// Rating of a channel
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelRating {
+    /// Channel, 11 to 26
+    pub channel: u8,
+    /// Highest energy level detected
+    pub energy: u8,
+    /// Number of PANs found by active scan
+    pub pans: u8,
+}
+
+/// Energy levels and PAN counts of the surveyed channels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelSurvey {
+    ratings: [ChannelRating; CHANNEL_COUNT],
+    channels: u32,
+}
+
+impl ChannelSurvey {
+    /// Create a empty survey
+    pub fn new() -> Self {
+        let mut ratings = [ChannelRating::default(); CHANNEL_COUNT];
+        for (index, rating) in ratings.iter_mut().enumerate() {
+            rating.channel = FIRST_CHANNEL + index as u8;
+        }
+        Self {
+            ratings,
+            channels: 0,
+        }
+    }
+
+    /// Record the energy level of a channel
+    ///
+    /// The highest level recorded for a channel is kept.
+    pub fn set_energy(&mut self, channel: u8, level: u8) {
+        if is_valid_channel(channel) {
+            let rating = &mut self.ratings[(channel - FIRST_CHANNEL) as usize];
+            rating.energy = rating.energy.max(level);
+            self.channels |= 1 << channel;
+        }
+    }
+
+    /// Record a PAN found on a channel by active scan
+    pub fn add_pan(&mut self, channel: u8) {
+        if is_valid_channel(channel) {
+            let rating = &mut self.ratings[(channel - FIRST_CHANNEL) as usize];
+            rating.pans = rating.pans.saturating_add(1);
+        }
+    }
+
+    /// Rating of a channel, or None if the channel has not been surveyed
+    pub fn rating(&self, channel: u8) -> Option<ChannelRating> {
+        if is_valid_channel(channel) && self.channels & (1 << channel) != 0 {
+            Some(self.ratings[(channel - FIRST_CHANNEL) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Rank the surveyed channels, best channel first
+    ///
+    /// Channels with a energy level above `threshold` are ranked last.
+    pub fn rank(&self, threshold: u8) -> ChannelRanking {
+        let mut ranking = ChannelRanking {
+            ratings: [ChannelRating::default(); CHANNEL_COUNT],
+            count: 0,
+        };
+        for rating in self.ratings.iter() {
+            if self.channels & (1 << rating.channel) != 0 {
+                ranking.ratings[ranking.count] = *rating;
+                ranking.count += 1;
+            }
+        }
+        ranking.ratings[..ranking.count]
+            .sort_unstable_by_key(|r| (r.energy > threshold, r.pans, r.energy, r.channel));
+        ranking
+    }
+}
+
+impl Default for ChannelSurvey {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Ranked channels, best channel first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelRanking {
+    ratings: [ChannelRating; CHANNEL_COUNT],
+    count: usize,
+}
+
+impl ChannelRanking {
+    /// The ranked channels
+    pub fn as_slice(&self) -> &[ChannelRating] {
+        &self.ratings[..self.count]
+    }
+
+    /// The best channel, or None if no channel was surveyed
+    pub fn best(&self) -> Option<u8> {
+        self.as_slice().first().map(|rating| rating.channel)
+    }
+}
+
+impl<const RX: usize> Radio<RX> {
+    /// Run a energy detect sweep over the channels in `channels`
+    ///
+    /// `channels` is a bit mask of channels, bit 11 to 26. `duration` is the
+    /// scan duration for each channel, see `start_energy_detect`. The sweep
+    /// is blocking and the radio events must not be handled elsewhere while
+    /// sweeping. A channel without a result in time is skipped. The radio is
+    /// put back in receive mode on the original channel.
+    pub fn energy_sweep(
+        &mut self,
+        channels: u32,
+        duration: ScanDuration,
+        survey: &mut ChannelSurvey,
+    ) {
+        let attempts = match duration.iterations() {
+            Some(iterations) => iterations.saturating_mul(ED_ATTEMPTS_PER_ITERATION),
+            None => return,
+        };
+        let restore = self.get_channel();
+        let mut channels = channels & ALL_CHANNELS;
+        while channels != 0 {
+            let channel = channels.trailing_zeros() as u8;
+            channels &= !(1 << channel);
+            let _ = self.set_channel(channel);
+            if !self.start_energy_detect(duration) {
+                break;
+            }
+            match (0..attempts).find_map(|_| self.report_energy_detect()) {
+                Some(level) => survey.set_energy(channel, level),
+                None => self.disable(),
+            }
+        }
+        let _ = self.set_channel(restore);
+        self.receive_prepare();
+    }
+
+    /// Find the best channels for forming a network
+    ///
+    /// Runs a energy detect sweep, see `energy_sweep`, and ranks the
+    /// channels. `pans` holds the channels of PANs found by a active scan,
+    /// one entry for each PAN.
+    pub fn select_channel(
+        &mut self,
+        channels: u32,
+        duration: ScanDuration,
+        pans: &[u8],
+        threshold: u8,
+    ) -> ChannelRanking {
+        let mut survey = ChannelSurvey::new();
+        self.energy_sweep(channels, duration, &mut survey);
+        for channel in pans {
+            survey.add_pan(*channel);
+        }
+        survey.rank(threshold)
+    }
+}
@@ -0,0 +1,256 @@
+//! # Clock calibration
+//!
+//! Calibration of the LFRC, the internal 32.768 kHz RC oscillator, for
+//! devices without a 32 kHz crystal. The uncalibrated LFRC is off by up to
+//! ±5 %, calibrated it stays within ±500 ppm, so that the RTC-based MAC
+//! timeouts hold.
+//!
+//! The calibration is driven by the calibration timer of the CLOCK
+//! peripheral. Every calibration interval the temperature is measured using
+//! the TEMP peripheral, and the LFRC is calibrated if the temperature has
+//! changed by the temperature threshold since the last calibration, or if
+//! `max_skip` intervals have passed without a calibration.
+//!
+//! ```notrust
+//! CTTO -> TEMP start -> DATARDY -> HFCLK start -> HFCLKSTARTED -> CAL -> DONE
+//!                               `-> skip, restart the calibration timer
+//! ```
+//!
+//! Calibration needs the HFXO, it is started when needed and stopped after
+//! the calibration unless it was already running. Call
+//! `Calibration::on_interrupt` from the POWER_CLOCK and TEMP interrupts. The
+//! LFCLK shall be running from the LFRC.
+//!
+
+use crate::pac::{CLOCK, TEMP};
+
+/// Longest calibration interval in 0.25 s units
+pub const MAX_INTERVAL: u8 = 127;
+
+/// Calibration configuration
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CalibrationConfig {
+    /// Interval between temperature measurements in 0.25 s units, 1 to
+    /// `MAX_INTERVAL`
+    pub interval: u8,
+    /// Temperature change triggering a calibration in 0.25 °C units
+    pub temperature_threshold: u16,
+    /// Number of intervals skipped at most before a calibration regardless
+    /// of the temperature
+    pub max_skip: u8,
+}
+
+impl CalibrationConfig {
+    /// Check that the interval is within range
+    pub fn is_valid(&self) -> bool {
+        self.interval > 0 && self.interval <= MAX_INTERVAL
+    }
+}
+
+impl Default for CalibrationConfig {
+    /// Measure every 4 s, calibrate on a 0.5 °C change or every 32 s
+    fn default() -> Self {
+        Self {
+            interval: 16,
+            temperature_threshold: 2,
+            max_skip: 7,
+        }
+    }
+}
+
+/// Event reported by the calibration
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CalibrationEvent {
+    /// The LFRC has been calibrated at the temperature, in 0.25 °C units
+    Calibrated(i32),
+}
+
+/// Calibration state
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    /// Not running
+    Stopped,
+    /// Waiting for the calibration timer
+    Waiting,
+    /// Measuring the temperature
+    Measuring,
+    /// Waiting for the HFXO to start
+    Starting,
+    /// Calibrating the LFRC
+    Calibrating,
+}
+
+/// LFRC calibration loop
+pub struct Calibration {
+    config: CalibrationConfig,
+    state: State,
+    /// Temperature of the last calibration
+    temperature: Option<i32>,
+    /// Temperature of the ongoing calibration
+    measured: i32,
+    /// Intervals since the last calibration
+    skipped: u8,
+    /// The HFXO was started for the calibration
+    hfxo_started: bool,
+}
+
+impl Calibration {
+    /// Create a stopped calibration loop
+    ///
+    /// # Return
+    ///
+    /// Returns None if the configuration is not valid.
+    ///
+    pub fn new(config: CalibrationConfig) -> Option<Self> {
+        if !config.is_valid() {
+            return None;
+        }
+        Some(Self {
+            config,
+            state: State::Stopped,
+            temperature: None,
+            measured: 0,
+            skipped: 0,
+            hfxo_started: false,
+        })
+    }
+
+    /// Start the loop, the first calibration starts immediately
+    pub fn start(&mut self, clock: &CLOCK, temp: &TEMP) {
+        clock
+            .ctiv
+            .write(|w| unsafe { w.ctiv().bits(self.config.interval) });
+        clock
+            .intenset
+            .write(|w| w.ctto().set().done().set().hfclkstarted().set());
+        temp.intenset.write(|w| w.datardy().set());
+        self.temperature = None;
+        self.skipped = 0;
+        self.measure(temp);
+    }
+
+    /// Stop the loop, a ongoing calibration is completed by the hardware
+    pub fn stop(&mut self, clock: &CLOCK, temp: &TEMP) {
+        clock.tasks_ctstop.write(|w| w.tasks_ctstop().set_bit());
+        clock
+            .intenclr
+            .write(|w| w.ctto().clear().done().clear().hfclkstarted().clear());
+        temp.tasks_stop.write(|w| w.tasks_stop().set_bit());
+        temp.intenclr.write(|w| w.datardy().clear());
+        if self.hfxo_started {
+            clock
+                .tasks_hfclkstop
+                .write(|w| w.tasks_hfclkstop().set_bit());
+            self.hfxo_started = false;
+        }
+        self.state = State::Stopped;
+    }
+
+    /// The loop is running
+    pub fn is_running(&self) -> bool {
+        self.state != State::Stopped
+    }
+
+    /// Temperature of the last calibration in 0.25 °C units
+    pub fn temperature(&self) -> Option<i32> {
+        self.temperature
+    }
+
+    /// Handle the CLOCK and TEMP events, call this from the POWER_CLOCK and
+    /// TEMP interrupts
+    ///
+    /// # Return
+    ///
+    /// Returns `CalibrationEvent::Calibrated` when a calibration completes.
+    ///
+    pub fn on_interrupt(&mut self, clock: &CLOCK, temp: &TEMP) -> Option<CalibrationEvent> {
+        if clock.events_ctto.read().events_ctto().bit_is_set() {
+            clock.events_ctto.reset();
+            if self.state == State::Waiting {
+                self.measure(temp);
+            }
+        }
+        if temp.events_datardy.read().events_datardy().bit_is_set() {
+            temp.events_datardy.reset();
+            if self.state == State::Measuring {
+                let measured = temp.temp.read().bits() as i32;
+                temp.tasks_stop.write(|w| w.tasks_stop().set_bit());
+                self.measured(clock, measured);
+            }
+        }
+        if clock
+            .events_hfclkstarted
+            .read()
+            .events_hfclkstarted()
+            .bit_is_set()
+        {
+            clock.events_hfclkstarted.reset();
+            if self.state == State::Starting {
+                self.calibrate(clock);
+            }
+        }
+        if clock.events_done.read().events_done().bit_is_set() {
+            clock.events_done.reset();
+            if self.state == State::Calibrating {
+                if self.hfxo_started {
+                    clock
+                        .tasks_hfclkstop
+                        .write(|w| w.tasks_hfclkstop().set_bit());
+                    self.hfxo_started = false;
+                }
+                self.temperature = Some(self.measured);
+                self.skipped = 0;
+                self.wait(clock);
+                return Some(CalibrationEvent::Calibrated(self.measured));
+            }
+        }
+        None
+    }
+
+    fn measure(&mut self, temp: &TEMP) {
+        temp.events_datardy.reset();
+        temp.tasks_start.write(|w| w.tasks_start().set_bit());
+        self.state = State::Measuring;
+    }
+
+    fn measured(&mut self, clock: &CLOCK, measured: i32) {
+        let due = match self.temperature {
+            Some(temperature) => {
+                (measured - temperature).unsigned_abs()
+                    >= u32::from(self.config.temperature_threshold)
+                    || self.skipped >= self.config.max_skip
+            }
+            None => true,
+        };
+        if !due {
+            self.skipped = self.skipped.saturating_add(1);
+            self.wait(clock);
+            return;
+        }
+        self.measured = measured;
+        let hfclk = clock.hfclkstat.read();
+        if hfclk.src().is_xtal() && hfclk.state().is_running() {
+            self.calibrate(clock);
+        } else {
+            clock.events_hfclkstarted.reset();
+            clock
+                .tasks_hfclkstart
+                .write(|w| w.tasks_hfclkstart().set_bit());
+            self.hfxo_started = true;
+            self.state = State::Starting;
+        }
+    }
+
+    fn calibrate(&mut self, clock: &CLOCK) {
+        clock.events_done.reset();
+        clock.tasks_cal.write(|w| w.tasks_cal().set_bit());
+        self.state = State::Calibrating;
+    }
+
+    fn wait(&mut self, clock: &CLOCK) {
+        clock.tasks_ctstart.write(|w| w.tasks_ctstart().set_bit());
+        self.state = State::Waiting;
+    }
+}
@@ -0,0 +1,490 @@
+//! # Serial command protocol
+//!
+//! A small protocol for controlling the radio from a host, turning a board
+//! into a host-controlled 802.15.4 adapter. The protocol does not depend on
+//! the transport, packets can be carried over UARTE, USB or anything else
+//! capable of moving bytes.
+//!
+//! ## Packet layout
+//!
+//! ```notrust
+//! ---------------------------------
+//! | length | identifier | payload |
+//! ---------------------------------
+//!     1          1           *       octets
+//! ```
+//!
+//! The length octet holds the number of octets following it, the identifier
+//! and the payload.
+//!
+//! ## Commands
+//!
+//! | Identifier | Command       | Payload                                   |
+//! |------------|---------------|-------------------------------------------|
+//! | 0x01       | Set channel   | channel (1 octet)                         |
+//! | 0x02       | Set power     | power in dBm (1 octet, signed)            |
+//! | 0x03       | Transmit      | flags (1 octet), frame without FCS        |
+//! | 0x04       | Energy scan   | channel mask (4 octets), count (4 octets) |
+//! | 0x05       | Receive       | none                                      |
+//!
+//! Multi-octet fields are little endian. Bit 0 of the transmit flags selects
+//! clear channel assessment (CCA) before transmission. Energy scan requires
+//! the `energy-detect` feature.
+//!
+//! ## Responses
+//!
+//! | Identifier | Response      | Payload                                               |
+//! |------------|---------------|-------------------------------------------------------|
+//! | 0x80       | Ok            | command identifier (1 octet)                          |
+//! | 0x81       | Error         | command identifier (1 octet), status (1 octet)        |
+//! | 0x82       | Received      | channel, LQI (1 octet each), timestamp (4), frame     |
+//! | 0x83       | Energy detect | channel (1 octet), level (1 octet)                    |
+//!
+
+use crate::phy::MAX_FRAME_LENGTH;
+#[cfg(feature = "energy-detect")]
+use crate::radio::ScanDuration;
+use crate::radio::{is_valid_channel, is_valid_transmission_power, Error, PacketBuffer, Radio};
+
+/// Set channel command identifier
+pub const COMMAND_SET_CHANNEL: u8 = 0x01;
+/// Set transmission power command identifier
+pub const COMMAND_SET_POWER: u8 = 0x02;
+/// Transmit frame command identifier
+pub const COMMAND_TRANSMIT: u8 = 0x03;
+/// Energy detect scan command identifier
+pub const COMMAND_ENERGY_SCAN: u8 = 0x04;
+/// Receive command identifier
+pub const COMMAND_RECEIVE: u8 = 0x05;
+
+/// Ok response identifier
+pub const RESPONSE_OK: u8 = 0x80;
+/// Error response identifier
+pub const RESPONSE_ERROR: u8 = 0x81;
+/// Received frame response identifier
+pub const RESPONSE_RECEIVED: u8 = 0x82;
+/// Energy detect response identifier
+pub const RESPONSE_ENERGY_DETECT: u8 = 0x83;
+
+/// Transmit flag, use clear channel assessment before transmitting
+pub const TRANSMIT_FLAG_CCA: u8 = 0x01;
+
+/// Largest packet, excluding the length octet
+pub const MAX_COMMAND_LENGTH: usize = MAX_FRAME_LENGTH + 7;
+
+/// Protocol errors
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProtocolError {
+    /// The packet does not contain a identifier
+    Empty,
+    /// The identifier is not a known command
+    UnknownCommand(u8),
+    /// The payload has the wrong size for the command
+    InvalidLength,
+    /// The provided buffer is too small
+    BufferTooSmall,
+}
+
+/// Status reported in error responses
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Status {
+    /// A parameter was out of range
+    InvalidParameter = 1,
+    /// The channel was busy, nothing was sent
+    ChannelBusy = 2,
+    /// A energy scan is in progress
+    Busy = 3,
+    /// The command is not supported by this build
+    Unsupported = 4,
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ProtocolError::Empty => f.write_str("empty packet"),
+            ProtocolError::UnknownCommand(identifier) => {
+                write!(f, "unknown command {:#04x}", identifier)
+            }
+            ProtocolError::InvalidLength => f.write_str("invalid payload length"),
+            ProtocolError::BufferTooSmall => f.write_str("buffer too small"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::error::Error for ProtocolError {}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for Status {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Status::InvalidParameter => f.write_str("invalid parameter"),
+            Status::ChannelBusy => f.write_str("channel busy"),
+            Status::Busy => f.write_str("busy"),
+            Status::Unsupported => f.write_str("unsupported"),
+        }
+    }
+}
+
+/// Commands sent from the host
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command<'a> {
+    /// Set the channel
+    SetChannel(u8),
+    /// Set transmission power in dBm
+    SetPower(i8),
+    /// Transmit a frame
+    Transmit {
+        /// Use clear channel assessment before transmitting
+        cca: bool,
+        /// Frame to send, without FCS
+        data: &'a [u8],
+    },
+    /// Run energy detection on the channels in the mask
+    EnergyScan {
+        /// Bit mask of channels to scan, bit 11 to 26
+        channels: u32,
+        /// Number of energy detect iterations for each channel
+        count: u32,
+    },
+    /// Enter receive
+    Receive,
+}
+
+impl<'a> Command<'a> {
+    /// Decode a command from a packet, excluding the length octet
+    pub fn decode(packet: &'a [u8]) -> Result<Self, ProtocolError> {
+        let (identifier, payload) = match packet.split_first() {
+            Some((identifier, payload)) => (*identifier, payload),
+            None => return Err(ProtocolError::Empty),
+        };
+        match identifier {
+            COMMAND_SET_CHANNEL => {
+                if payload.len() != 1 {
+                    return Err(ProtocolError::InvalidLength);
+                }
+                Ok(Command::SetChannel(payload[0]))
+            }
+            COMMAND_SET_POWER => {
+                if payload.len() != 1 {
+                    return Err(ProtocolError::InvalidLength);
+                }
+                Ok(Command::SetPower(payload[0] as i8))
+            }
+            COMMAND_TRANSMIT => {
+                if payload.is_empty() || payload.len() > MAX_FRAME_LENGTH + 1 {
+                    return Err(ProtocolError::InvalidLength);
+                }
+                Ok(Command::Transmit {
+                    cca: payload[0] & TRANSMIT_FLAG_CCA == TRANSMIT_FLAG_CCA,
+                    data: &payload[1..],
+                })
+            }
+            COMMAND_ENERGY_SCAN => {
+                if payload.len() != 8 {
+                    return Err(ProtocolError::InvalidLength);
+                }
+                Ok(Command::EnergyScan {
+                    channels: u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]),
+                    count: u32::from_le_bytes([payload[4], payload[5], payload[6], payload[7]]),
+                })
+            }
+            COMMAND_RECEIVE => {
+                if !payload.is_empty() {
+                    return Err(ProtocolError::InvalidLength);
+                }
+                Ok(Command::Receive)
+            }
+            _ => Err(ProtocolError::UnknownCommand(identifier)),
+        }
+    }
+
+    /// Identifier of the command
+    pub fn identifier(&self) -> u8 {
+        match self {
+            Command::SetChannel(_) => COMMAND_SET_CHANNEL,
+            Command::SetPower(_) => COMMAND_SET_POWER,
+            Command::Transmit { .. } => COMMAND_TRANSMIT,
+            Command::EnergyScan { .. } => COMMAND_ENERGY_SCAN,
+            Command::Receive => COMMAND_RECEIVE,
+        }
+    }
+}
+
+/// Responses sent to the host
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Response<'a> {
+    /// Command with identifier was executed
+    Ok(u8),
+    /// Command with identifier failed
+    Error(u8, Status),
+    /// A frame was received
+    Received {
+        /// Channel the frame was received on
+        channel: u8,
+        /// Link quality indicator
+        lqi: u8,
+        /// Time of reception, as provided to the handler
+        timestamp: u32,
+        /// Received frame, without FCS
+        data: &'a [u8],
+    },
+    /// Energy detect result for a channel
+    EnergyDetect {
+        /// Scanned channel
+        channel: u8,
+        /// Energy level
+        level: u8,
+    },
+}
+
+impl<'a> Response<'a> {
+    /// Encode the response into a packet, including the length octet
+    ///
+    /// # Return
+    ///
+    /// Returns the number of octets written to the buffer.
+    ///
+    pub fn encode(&self, buffer: &mut [u8]) -> Result<usize, ProtocolError> {
+        let length = match self {
+            Response::Ok(_) => 2,
+            Response::Error(_, _) => 3,
+            Response::Received { data, .. } => 7 + data.len(),
+            Response::EnergyDetect { .. } => 3,
+        };
+        if buffer.len() < length + 1 {
+            return Err(ProtocolError::BufferTooSmall);
+        }
+        buffer[0] = length as u8;
+        match *self {
+            Response::Ok(command) => {
+                buffer[1] = RESPONSE_OK;
+                buffer[2] = command;
+            }
+            Response::Error(command, status) => {
+                buffer[1] = RESPONSE_ERROR;
+                buffer[2] = command;
+                buffer[3] = status as u8;
+            }
+            Response::Received {
+                channel,
+                lqi,
+                timestamp,
+                data,
+            } => {
+                buffer[1] = RESPONSE_RECEIVED;
+                buffer[2] = channel;
+                buffer[3] = lqi;
+                buffer[4..8].copy_from_slice(&timestamp.to_le_bytes());
+                buffer[8..8 + data.len()].copy_from_slice(data);
+            }
+            Response::EnergyDetect { channel, level } => {
+                buffer[1] = RESPONSE_ENERGY_DETECT;
+                buffer[2] = channel;
+                buffer[3] = level;
+            }
+        }
+        Ok(length + 1)
+    }
+}
+
+/// Collects packets from a byte stream, such as a UART
+pub struct PacketReader {
+    buffer: [u8; MAX_COMMAND_LENGTH + 1],
+    position: usize,
+}
+
+impl PacketReader {
+    /// Create a new packet reader
+    pub fn new() -> Self {
+        Self {
+            buffer: [0u8; MAX_COMMAND_LENGTH + 1],
+            position: 0,
+        }
+    }
+
+    /// Discard any partially received packet
+    pub fn reset(&mut self) {
+        self.position = 0;
+    }
+
+    /// Add a octet from the stream
+    ///
+    /// # Return
+    ///
+    /// Returns the packet, excluding the length octet, when complete.
+    ///
+    pub fn push(&mut self, byte: u8) -> Option<&[u8]> {
+        if self.position == 0 && (byte == 0 || byte as usize > MAX_COMMAND_LENGTH) {
+            // Not a valid length, skip octet
+            return None;
+        }
+        self.buffer[self.position] = byte;
+        self.position += 1;
+        let length = self.buffer[0] as usize;
+        if self.position == length + 1 {
+            self.position = 0;
+            Some(&self.buffer[1..=length])
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for PacketReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Executes commands on the radio and produces responses
+pub struct CommandHandler {
+    /// Channels left to scan
+    #[cfg(feature = "energy-detect")]
+    scan_channels: u32,
+    /// Energy detect iterations per channel
+    #[cfg(feature = "energy-detect")]
+    scan_count: u32,
+    /// Channel used before the scan started
+    #[cfg(feature = "energy-detect")]
+    scan_restore: u8,
+    /// Channel currently scanned
+    scan_current: u8,
+}
+
+impl CommandHandler {
+    /// Create a new command handler
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "energy-detect")]
+            scan_channels: 0,
+            #[cfg(feature = "energy-detect")]
+            scan_count: 0,
+            #[cfg(feature = "energy-detect")]
+            scan_restore: 0,
+            scan_current: 0,
+        }
+    }
+
+    /// Energy scan in progress
+    pub fn is_scanning(&self) -> bool {
+        self.scan_current != 0
+    }
+
+    /// Execute a command
+    pub fn execute<const RX: usize>(
+        &mut self,
+        radio: &mut Radio<RX>,
+        command: &Command,
+    ) -> Response<'static> {
+        let identifier = command.identifier();
+        if self.is_scanning() {
+            return Response::Error(identifier, Status::Busy);
+        }
+        match *command {
+            Command::SetChannel(channel) => {
+                if !is_valid_channel(channel) {
+                    return Response::Error(identifier, Status::InvalidParameter);
+                }
+                let _ = radio.set_channel(channel);
+            }
+            Command::SetPower(power) => {
+                if !is_valid_transmission_power(power) {
+                    return Response::Error(identifier, Status::InvalidParameter);
+                }
+                let _ = radio.set_transmission_power(power);
+            }
+            Command::Transmit { cca, data } => {
+                let queued = if cca {
+                    radio.queue_transmission(data)
+                } else {
+                    radio.queue_transmission_no_cca(data)
+                };
+                if queued.is_err() {
+                    return Response::Error(identifier, Status::InvalidParameter);
+                }
+            }
+            #[cfg(not(feature = "energy-detect"))]
+            Command::EnergyScan { .. } => {
+                return Response::Error(identifier, Status::Unsupported);
+            }
+            #[cfg(feature = "energy-detect")]
+            Command::EnergyScan { channels, count } => {
+                let channels = channels & 0x07ff_f800;
+                if channels == 0 || ScanDuration::Iterations(count).iterations().is_none() {
+                    return Response::Error(identifier, Status::InvalidParameter);
+                }
+                self.scan_channels = channels;
+                self.scan_count = count;
+                self.scan_restore = radio.get_channel();
+                self.scan_next(radio);
+            }
+            Command::Receive => {
+                radio.receive_prepare();
+            }
+        }
+        Response::Ok(identifier)
+    }
+
+    #[cfg(feature = "energy-detect")]
+    fn scan_next<const RX: usize>(&mut self, radio: &mut Radio<RX>) {
+        if self.scan_channels == 0 {
+            self.scan_current = 0;
+            let _ = radio.set_channel(self.scan_restore);
+            radio.receive_prepare();
+        } else {
+            let channel = self.scan_channels.trailing_zeros() as u8;
+            self.scan_channels &= !(1 << channel);
+            self.scan_current = channel;
+            let _ = radio.set_channel(channel);
+            radio.start_energy_detect(ScanDuration::Iterations(self.scan_count));
+        }
+    }
+
+    /// Process radio events, call this from the radio interrupt
+    ///
+    /// `timestamp` is reported with received frames.
+    ///
+    /// # Return
+    ///
+    /// Returns a response to forward to the host, if any.
+    ///
+    pub fn poll<'b, const RX: usize>(
+        &mut self,
+        radio: &mut Radio<RX>,
+        buffer: &'b mut PacketBuffer,
+        timestamp: u32,
+    ) -> Option<Response<'b>> {
+        #[cfg(feature = "energy-detect")]
+        if self.is_scanning() {
+            return match radio.report_energy_detect() {
+                Some(level) => {
+                    let channel = self.scan_current;
+                    self.scan_next(radio);
+                    Some(Response::EnergyDetect { channel, level })
+                }
+                None => None,
+            };
+        }
+        match radio.receive(buffer) {
+            Ok(length) if length > 2 => Some(Response::Received {
+                channel: radio.get_channel(),
+                lqi: buffer[length - 1],
+                timestamp,
+                data: &buffer[1..length - 1],
+            }),
+            Ok(_) => None,
+            Err(Error::CcaBusy) => Some(Response::Error(COMMAND_TRANSMIT, Status::ChannelBusy)),
+            Err(_) => None,
+        }
+    }
+}
+
+impl Default for CommandHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,62 @@
+//! # Cryptography
+//!
+//! AES-128 block ciphers, the CCM* mode used by 802.15.4 and Zigbee security
+//! and AES-CMAC.
+//!
+//! The modes are built on the `BlockCipher` trait so that the same code runs
+//! on any AES-128 backend. The `ecb` module provides the hardware backend on
+//! the ECB peripheral. With the `soft-crypto` feature the `soft` module
+//! provides a software backend, for host side tests or when the hardware is
+//! used by another stack.
+//!
+
+pub mod ccm;
+pub mod cmac;
+#[cfg(feature = "device")]
+pub mod ecb;
+#[cfg(feature = "soft-crypto")]
+pub mod soft;
+
+/// AES block length in octets
+pub const BLOCK_LENGTH: usize = 16;
+/// AES-128 key length in octets
+pub const KEY_LENGTH: usize = 16;
+
+/// A AES block
+pub type Block = [u8; BLOCK_LENGTH];
+/// A AES-128 key
+pub type Key = [u8; KEY_LENGTH];
+
+/// Errors returned by the cryptographic functions
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CryptoError {
+    /// A input or output has a invalid length
+    InvalidLength,
+    /// The message integrity code (MIC) did not match
+    Authentication,
+    /// The cipher backend failed, for example the hardware was busy
+    Backend,
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CryptoError::InvalidLength => f.write_str("invalid length"),
+            CryptoError::Authentication => f.write_str("authentication failed"),
+            CryptoError::Backend => f.write_str("cipher backend failure"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::error::Error for CryptoError {}
+
+/// AES-128 block cipher, encryption only
+pub trait BlockCipher {
+    /// Set the key used for the following operations
+    fn set_key(&mut self, key: &Key);
+    /// Encrypt a block in place
+    fn encrypt_block(&mut self, block: &mut Block) -> Result<(), CryptoError>;
+}
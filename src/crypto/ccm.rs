@@ -0,0 +1,524 @@
+//! CCM* mode
+//!
+//! As specified in IEEE 802.15.4 annex B, with a 13 octet nonce and a two
+//! octet length field. The message integrity code (MIC) is 0, 4, 8 or 16
+//! octets, a zero length MIC gives encryption without authentication.
+//!
+//! `encrypt_in_place` and `decrypt_in_place` take the associated data and
+//! the payload as lists of slices, so that scattered frame parts need not be
+//! copied together. `encrypt_frame` and `decrypt_frame` process a frame in
+//! place, such as in the receive buffer of the radio.
+//!
+
+use super::{Block, BlockCipher, CryptoError, BLOCK_LENGTH};
+
+/// Nonce length in octets
+pub const NONCE_LENGTH: usize = 13;
+
+/// CCM* nonce
+pub type Nonce = [u8; NONCE_LENGTH];
+
+/// Size of the length field in octets, L
+const LENGTH_SIZE: u8 = 2;
+
+/// Largest associated data length encoded with two octets
+const MAX_AAD_LENGTH: usize = 0xfeff;
+
+fn is_valid_mic_length(length: usize) -> bool {
+    matches!(length, 0 | 4 | 8 | 16)
+}
+
+/// CBC-MAC calculation
+struct CbcMac {
+    x: Block,
+    fill: usize,
+}
+
+impl CbcMac {
+    fn new<C: BlockCipher>(cipher: &mut C, b0: Block) -> Result<Self, CryptoError> {
+        let mut x = b0;
+        cipher.encrypt_block(&mut x)?;
+        Ok(Self { x, fill: 0 })
+    }
+
+    fn update<C: BlockCipher>(&mut self, cipher: &mut C, data: &[u8]) -> Result<(), CryptoError> {
+        for byte in data {
+            self.x[self.fill] ^= *byte;
+            self.fill += 1;
+            if self.fill == BLOCK_LENGTH {
+                cipher.encrypt_block(&mut self.x)?;
+                self.fill = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Pad the current block with zeros
+    fn pad<C: BlockCipher>(&mut self, cipher: &mut C) -> Result<(), CryptoError> {
+        if self.fill > 0 {
+            cipher.encrypt_block(&mut self.x)?;
+            self.fill = 0;
+        }
+        Ok(())
+    }
+}
+
+/// The first authentication block, B0
+fn block_b0(nonce: &Nonce, aad_length: usize, mic_length: usize, length: usize) -> Block {
+    let mut block = [0u8; BLOCK_LENGTH];
+    let mut flags = LENGTH_SIZE - 1;
+    if aad_length > 0 {
+        flags |= 0x40;
+    }
+    if mic_length > 0 {
+        flags |= (((mic_length - 2) / 2) as u8) << 3;
+    }
+    block[0] = flags;
+    block[1..=NONCE_LENGTH].copy_from_slice(nonce);
+    block[14..16].copy_from_slice(&(length as u16).to_be_bytes());
+    block
+}
+
+/// Encrypted counter block, A_i
+fn key_stream<C: BlockCipher>(
+    cipher: &mut C,
+    nonce: &Nonce,
+    counter: u16,
+) -> Result<Block, CryptoError> {
+    let mut block = [0u8; BLOCK_LENGTH];
+    block[0] = LENGTH_SIZE - 1;
+    block[1..=NONCE_LENGTH].copy_from_slice(nonce);
+    block[14..16].copy_from_slice(&counter.to_be_bytes());
+    cipher.encrypt_block(&mut block)?;
+    Ok(block)
+}
+
+/// Total length of the slices
+fn total_length<T: AsRef<[u8]>>(slices: &[T]) -> usize {
+    slices.iter().map(|slice| slice.as_ref().len()).sum()
+}
+
+/// Calculate the unencrypted authentication tag, T
+fn authenticate<C: BlockCipher, T: AsRef<[u8]>>(
+    cipher: &mut C,
+    nonce: &Nonce,
+    aad: &[&[u8]],
+    payload: &[T],
+    mic_length: usize,
+) -> Result<Block, CryptoError> {
+    let aad_length = total_length(aad);
+    let b0 = block_b0(nonce, aad_length, mic_length, total_length(payload));
+    let mut mac = CbcMac::new(cipher, b0)?;
+    if aad_length > 0 {
+        mac.update(cipher, &(aad_length as u16).to_be_bytes())?;
+        for part in aad {
+            mac.update(cipher, part)?;
+        }
+        mac.pad(cipher)?;
+    }
+    for part in payload {
+        mac.update(cipher, part.as_ref())?;
+    }
+    mac.pad(cipher)?;
+    Ok(mac.x)
+}
+
+/// Counter mode key stream, starting at counter 1
+struct KeyStream {
+    counter: u16,
+    block: Block,
+    used: usize,
+}
+
+impl KeyStream {
+    fn new() -> Self {
+        Self {
+            counter: 0,
+            block: [0u8; BLOCK_LENGTH],
+            used: BLOCK_LENGTH,
+        }
+    }
+
+    /// Apply the key stream to `data` in place, continuing where the
+    /// previous slice ended
+    fn apply<C: BlockCipher>(
+        &mut self,
+        cipher: &mut C,
+        nonce: &Nonce,
+        data: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        for octet in data.iter_mut() {
+            if self.used == BLOCK_LENGTH {
+                self.counter += 1;
+                self.block = key_stream(cipher, nonce, self.counter)?;
+                self.used = 0;
+            }
+            *octet ^= self.block[self.used];
+            self.used += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Apply the counter mode key stream to the payload slices in place
+fn transform<C: BlockCipher>(
+    cipher: &mut C,
+    nonce: &Nonce,
+    payload: &mut [&mut [u8]],
+) -> Result<(), CryptoError> {
+    let mut stream = KeyStream::new();
+    for part in payload.iter_mut() {
+        stream.apply(cipher, nonce, part)?;
+    }
+    Ok(())
+}
+
+fn check_lengths(
+    aad: &[&[u8]],
+    payload: &[&mut [u8]],
+    mic_length: usize,
+) -> Result<(), CryptoError> {
+    if total_length(payload) > usize::from(u16::MAX)
+        || total_length(aad) > MAX_AAD_LENGTH
+        || !is_valid_mic_length(mic_length)
+    {
+        Err(CryptoError::InvalidLength)
+    } else {
+        Ok(())
+    }
+}
+
+/// Encrypt and authenticate
+///
+/// `plaintext` is encrypted into `ciphertext`, which must have the same
+/// length. `aad` is authenticated but not encrypted. The MIC is written to
+/// `mic`, the length of `mic` selects the MIC length.
+pub fn encrypt<C: BlockCipher>(
+    cipher: &mut C,
+    nonce: &Nonce,
+    aad: &[u8],
+    plaintext: &[u8],
+    ciphertext: &mut [u8],
+    mic: &mut [u8],
+) -> Result<(), CryptoError> {
+    if plaintext.len() != ciphertext.len() {
+        return Err(CryptoError::InvalidLength);
+    }
+    ciphertext.copy_from_slice(plaintext);
+    encrypt_in_place(cipher, nonce, &[aad], &mut [ciphertext], mic)
+}
+
+/// Decrypt and verify
+///
+/// `ciphertext` is decrypted into `plaintext`, which must have the same
+/// length. The plaintext is cleared if the MIC does not match.
+pub fn decrypt<C: BlockCipher>(
+    cipher: &mut C,
+    nonce: &Nonce,
+    aad: &[u8],
+    ciphertext: &[u8],
+    plaintext: &mut [u8],
+    mic: &[u8],
+) -> Result<(), CryptoError> {
+    if ciphertext.len() != plaintext.len() {
+        return Err(CryptoError::InvalidLength);
+    }
+    plaintext.copy_from_slice(ciphertext);
+    decrypt_in_place(cipher, nonce, &[aad], &mut [plaintext], mic)
+}
+
+/// Encrypt and authenticate in place, scatter-gather
+///
+/// The associated data and the payload are given as lists of slices, which
+/// are processed as if concatenated. The payload slices are encrypted in
+/// place. The MIC is written to `mic`, the length of `mic` selects the MIC
+/// length.
+pub fn encrypt_in_place<C: BlockCipher>(
+    cipher: &mut C,
+    nonce: &Nonce,
+    aad: &[&[u8]],
+    payload: &mut [&mut [u8]],
+    mic: &mut [u8],
+) -> Result<(), CryptoError> {
+    check_lengths(aad, payload, mic.len())?;
+    if !mic.is_empty() {
+        let tag = authenticate(cipher, nonce, aad, payload, mic.len())?;
+        let stream = key_stream(cipher, nonce, 0)?;
+        for ((mic, tag), stream) in mic.iter_mut().zip(tag.iter()).zip(stream.iter()) {
+            *mic = tag ^ stream;
+        }
+    }
+    transform(cipher, nonce, payload)
+}
+
+/// Decrypt and verify in place, scatter-gather
+///
+/// The associated data and the payload are given as lists of slices, which
+/// are processed as if concatenated. The payload slices are decrypted in
+/// place, and cleared if the MIC does not match.
+pub fn decrypt_in_place<C: BlockCipher>(
+    cipher: &mut C,
+    nonce: &Nonce,
+    aad: &[&[u8]],
+    payload: &mut [&mut [u8]],
+    mic: &[u8],
+) -> Result<(), CryptoError> {
+    check_lengths(aad, payload, mic.len())?;
+    transform(cipher, nonce, payload)?;
+    if mic.is_empty() {
+        return Ok(());
+    }
+    let tag = authenticate(cipher, nonce, aad, payload, mic.len())?;
+    let stream = key_stream(cipher, nonce, 0)?;
+    let difference = mic
+        .iter()
+        .zip(tag.iter())
+        .zip(stream.iter())
+        .fold(0u8, |difference, ((mic, tag), stream)| {
+            difference | (mic ^ tag ^ stream)
+        });
+    if difference != 0 {
+        for part in payload.iter_mut() {
+            part.iter_mut().for_each(|octet| *octet = 0);
+        }
+        return Err(CryptoError::Authentication);
+    }
+    Ok(())
+}
+
+/// Parts of a secured frame
+struct FrameParts<'a> {
+    /// Associated data
+    aad: &'a [u8],
+    /// Payload, encrypted in place
+    payload: &'a mut [u8],
+    /// Message integrity code
+    mic: &'a mut [u8],
+}
+
+/// Split a frame into the associated data, the payload and the MIC
+fn split_frame(
+    frame: &mut [u8],
+    aad_length: usize,
+    mic_length: usize,
+) -> Result<FrameParts<'_>, CryptoError> {
+    if aad_length + mic_length > frame.len() {
+        return Err(CryptoError::InvalidLength);
+    }
+    let (aad, rest) = frame.split_at_mut(aad_length);
+    let payload_length = rest.len() - mic_length;
+    let (payload, mic) = rest.split_at_mut(payload_length);
+    Ok(FrameParts { aad, payload, mic })
+}
+
+/// Encrypt and authenticate a frame in place
+///
+/// `frame` holds the `aad_length` octets of associated data, the payload and
+/// room for the `mic_length` octet MIC, in that order. The payload is
+/// encrypted in place and the MIC is written at the end of the frame.
+pub fn encrypt_frame<C: BlockCipher>(
+    cipher: &mut C,
+    nonce: &Nonce,
+    frame: &mut [u8],
+    aad_length: usize,
+    mic_length: usize,
+) -> Result<(), CryptoError> {
+    let parts = split_frame(frame, aad_length, mic_length)?;
+    encrypt_in_place(cipher, nonce, &[parts.aad], &mut [parts.payload], parts.mic)
+}
+
+/// Decrypt and verify a frame in place, for example in the receive buffer
+///
+/// `frame` holds the `aad_length` octets of associated data, the encrypted
+/// payload and the `mic_length` octet MIC, in that order. The payload is
+/// decrypted in place, and cleared if the MIC does not match.
+pub fn decrypt_frame<C: BlockCipher>(
+    cipher: &mut C,
+    nonce: &Nonce,
+    frame: &mut [u8],
+    aad_length: usize,
+    mic_length: usize,
+) -> Result<(), CryptoError> {
+    let parts = split_frame(frame, aad_length, mic_length)?;
+    decrypt_in_place(cipher, nonce, &[parts.aad], &mut [parts.payload], parts.mic)
+}
+
+#[cfg(all(test, feature = "soft-crypto"))]
+mod tests {
+    use super::*;
+    use crate::crypto::soft::SoftAes;
+
+    /// Key of the IEEE 802.15.4 annex C test vectors
+    const KEY: [u8; 16] = [
+        0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xcb, 0xcc, 0xcd, 0xce,
+        0xcf,
+    ];
+
+    /// Nonce of the annex C test vectors with security level `level`
+    fn nonce(level: u8) -> Nonce {
+        [
+            0xac, 0xde, 0x48, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x05, level,
+        ]
+    }
+
+    /// Beacon frame of annex C.2.1 with security level `level`
+    fn beacon(level: u8) -> [u8; 26] {
+        [
+            0x08, 0xd0, 0x84, 0x21, 0x43, 0x01, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, level,
+            0x05, 0x00, 0x00, 0x00, 0x55, 0xcf, 0x00, 0x00, 0x51, 0x52, 0x53, 0x54,
+        ]
+    }
+
+    /// Header of the command frame of annex C.2.3 with security level `level`
+    fn command(level: u8) -> [u8; 29] {
+        [
+            0x2b, 0xdc, 0x84, 0x21, 0x43, 0x02, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0xff,
+            0xff, 0x01, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, level, 0x05, 0x00, 0x00, 0x00,
+            0x01,
+        ]
+    }
+
+    /// Encrypt and decrypt, checking the ciphertext and the MIC
+    fn check(level: u8, aad: &[u8], plaintext: &[u8], ciphertext: &[u8], mic: &[u8]) {
+        let mut cipher = SoftAes::new(&KEY);
+        let nonce = nonce(level);
+        let mut encrypted = [0u8; 16];
+        let mut tag = [0u8; 16];
+        let encrypted = &mut encrypted[..plaintext.len()];
+        let tag = &mut tag[..mic.len()];
+        encrypt(&mut cipher, &nonce, aad, plaintext, encrypted, tag).unwrap();
+        assert_eq!(encrypted, ciphertext);
+        assert_eq!(tag, mic);
+        let mut decrypted = [0u8; 16];
+        let decrypted = &mut decrypted[..ciphertext.len()];
+        decrypt(&mut cipher, &nonce, aad, ciphertext, decrypted, mic).unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn mic_32() {
+        check(1, &beacon(1), &[], &[], &[0xcb, 0xff, 0xc2, 0xd9]);
+    }
+
+    #[test]
+    fn mic_64() {
+        // Annex C.2.1
+        check(
+            2,
+            &beacon(2),
+            &[],
+            &[],
+            &[0x22, 0x3b, 0xc1, 0xec, 0x84, 0x1a, 0xb5, 0x53],
+        );
+    }
+
+    #[test]
+    fn mic_128() {
+        check(
+            3,
+            &beacon(3),
+            &[],
+            &[],
+            &[
+                0x49, 0x0e, 0xd6, 0x1d, 0xdc, 0xf0, 0x8d, 0xb5, 0x26, 0x12, 0xc4, 0x37, 0x4b, 0xea,
+                0x9c, 0x68,
+            ],
+        );
+    }
+
+    #[test]
+    fn enc() {
+        // Annex C.2.2
+        let aad = [
+            0x69, 0xdc, 0x84, 0x21, 0x43, 0x02, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0x04, 0x05, 0x00, 0x00, 0x00,
+        ];
+        check(
+            4,
+            &aad,
+            &[0x61, 0x62, 0x63, 0x64],
+            &[0xd4, 0x3e, 0x02, 0x2b],
+            &[],
+        );
+    }
+
+    #[test]
+    fn enc_mic_32() {
+        check(5, &command(5), &[0xce], &[0x9a], &[0x4f, 0x26, 0x35, 0x6b]);
+    }
+
+    #[test]
+    fn enc_mic_64() {
+        // Annex C.2.3
+        check(
+            6,
+            &command(6),
+            &[0xce],
+            &[0xd8],
+            &[0x4f, 0xde, 0x52, 0x90, 0x61, 0xf9, 0xc6, 0xf1],
+        );
+    }
+
+    #[test]
+    fn enc_mic_128() {
+        check(
+            7,
+            &command(7),
+            &[0xce],
+            &[0xe1],
+            &[
+                0x6d, 0x45, 0x11, 0x51, 0x56, 0x07, 0x33, 0xc6, 0x88, 0x13, 0x98, 0xaa, 0x83, 0x9a,
+                0x29, 0xc2,
+            ],
+        );
+    }
+
+    #[test]
+    fn frame_in_place() {
+        let mut cipher = SoftAes::new(&KEY);
+        let mut frame = [0u8; 38];
+        frame[..29].copy_from_slice(&command(6));
+        frame[29] = 0xce;
+        encrypt_frame(&mut cipher, &nonce(6), &mut frame, 29, 8).unwrap();
+        assert_eq!(
+            frame[29..],
+            [0xd8, 0x4f, 0xde, 0x52, 0x90, 0x61, 0xf9, 0xc6, 0xf1]
+        );
+        decrypt_frame(&mut cipher, &nonce(6), &mut frame, 29, 8).unwrap();
+        assert_eq!(frame[29], 0xce);
+    }
+
+    #[test]
+    fn scattered_associated_data() {
+        let mut cipher = SoftAes::new(&KEY);
+        let frame = beacon(2);
+        let (header, auxiliary) = frame.split_at(13);
+        let mut mic = [0u8; 8];
+        encrypt_in_place(
+            &mut cipher,
+            &nonce(2),
+            &[header, auxiliary],
+            &mut [],
+            &mut mic,
+        )
+        .unwrap();
+        assert_eq!(mic, [0x22, 0x3b, 0xc1, 0xec, 0x84, 0x1a, 0xb5, 0x53]);
+    }
+
+    #[test]
+    fn authentication_failure() {
+        let mut cipher = SoftAes::new(&KEY);
+        let mut plaintext = [0xffu8; 1];
+        let mic = [0x4f, 0xde, 0x52, 0x90, 0x61, 0xf9, 0xc6, 0xf0];
+        let result = decrypt(
+            &mut cipher,
+            &nonce(6),
+            &command(6),
+            &[0xd8],
+            &mut plaintext,
+            &mic,
+        );
+        assert_eq!(result, Err(CryptoError::Authentication));
+        assert_eq!(plaintext, [0x00]);
+    }
+}
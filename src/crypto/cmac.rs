@@ -0,0 +1,269 @@
+//! AES-CMAC
+//!
+//! As specified in RFC 4493 (NIST SP 800-38B). The tag is 16 octets and may
+//! be truncated, the leftmost octets are used.
+//!
+
+use super::{Block, BlockCipher, CryptoError, BLOCK_LENGTH};
+
+/// Constant for the subkey generation, R_128
+const RB: u8 = 0x87;
+
+/// Multiply by x in GF(2^128), the subkey doubling
+fn double(block: &Block) -> Block {
+    let mut output = [0u8; BLOCK_LENGTH];
+    let mut carry = 0;
+    for (output, input) in output.iter_mut().zip(block.iter()).rev() {
+        *output = (input << 1) | carry;
+        carry = input >> 7;
+    }
+    if carry != 0 {
+        output[BLOCK_LENGTH - 1] ^= RB;
+    }
+    output
+}
+
+/// Incremental CMAC calculation
+///
+/// The key is set on the cipher before `Cmac::new`, and the same cipher is
+/// passed to each of the following calls.
+pub struct Cmac {
+    /// Subkey for a complete last block, K1
+    k1: Block,
+    /// Subkey for a padded last block, K2
+    k2: Block,
+    /// Chaining value
+    x: Block,
+    /// The last, possibly incomplete, block
+    last: Block,
+    fill: usize,
+}
+
+impl Cmac {
+    /// Start a calculation, derives the subkeys
+    pub fn new<C: BlockCipher>(cipher: &mut C) -> Result<Self, CryptoError> {
+        let mut l = [0u8; BLOCK_LENGTH];
+        cipher.encrypt_block(&mut l)?;
+        let k1 = double(&l);
+        let k2 = double(&k1);
+        Ok(Self {
+            k1,
+            k2,
+            x: [0u8; BLOCK_LENGTH],
+            last: [0u8; BLOCK_LENGTH],
+            fill: 0,
+        })
+    }
+
+    /// Add `data` to the message
+    pub fn update<C: BlockCipher>(
+        &mut self,
+        cipher: &mut C,
+        data: &[u8],
+    ) -> Result<(), CryptoError> {
+        for byte in data {
+            // A complete block is only processed when more data follows
+            if self.fill == BLOCK_LENGTH {
+                for (x, last) in self.x.iter_mut().zip(self.last.iter()) {
+                    *x ^= last;
+                }
+                cipher.encrypt_block(&mut self.x)?;
+                self.fill = 0;
+            }
+            self.last[self.fill] = *byte;
+            self.fill += 1;
+        }
+        Ok(())
+    }
+
+    /// Finish the calculation
+    ///
+    /// # Return
+    ///
+    /// Returns the 16 octet tag.
+    ///
+    pub fn finalize<C: BlockCipher>(mut self, cipher: &mut C) -> Result<Block, CryptoError> {
+        let subkey = if self.fill == BLOCK_LENGTH {
+            &self.k1
+        } else {
+            self.last[self.fill] = 0x80;
+            self.last[self.fill + 1..]
+                .iter_mut()
+                .for_each(|octet| *octet = 0);
+            &self.k2
+        };
+        for ((x, last), subkey) in self.x.iter_mut().zip(self.last.iter()).zip(subkey.iter()) {
+            *x ^= last ^ subkey;
+        }
+        cipher.encrypt_block(&mut self.x)?;
+        Ok(self.x)
+    }
+}
+
+/// Calculate the CMAC of `message` into `tag`
+///
+/// The length of `tag`, 1 to 16 octets, selects the tag length.
+pub fn authenticate<C: BlockCipher>(
+    cipher: &mut C,
+    message: &[u8],
+    tag: &mut [u8],
+) -> Result<(), CryptoError> {
+    if tag.is_empty() || tag.len() > BLOCK_LENGTH {
+        return Err(CryptoError::InvalidLength);
+    }
+    let mut cmac = Cmac::new(cipher)?;
+    cmac.update(cipher, message)?;
+    let full = cmac.finalize(cipher)?;
+    tag.copy_from_slice(&full[..tag.len()]);
+    Ok(())
+}
+
+/// Verify the CMAC `tag` of `message`
+///
+/// The length of `tag`, 1 to 16 octets, selects the tag length.
+pub fn verify<C: BlockCipher>(
+    cipher: &mut C,
+    message: &[u8],
+    tag: &[u8],
+) -> Result<(), CryptoError> {
+    if tag.is_empty() || tag.len() > BLOCK_LENGTH {
+        return Err(CryptoError::InvalidLength);
+    }
+    let mut cmac = Cmac::new(cipher)?;
+    cmac.update(cipher, message)?;
+    let full = cmac.finalize(cipher)?;
+    let difference = tag
+        .iter()
+        .zip(full.iter())
+        .fold(0u8, |difference, (tag, full)| difference | (tag ^ full));
+    if difference != 0 {
+        return Err(CryptoError::Authentication);
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "soft-crypto"))]
+mod tests {
+    use super::*;
+    use crate::crypto::soft::SoftAes;
+
+    /// Key of the RFC 4493 test vectors
+    const KEY: [u8; 16] = [
+        0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f,
+        0x3c,
+    ];
+
+    /// Message of the RFC 4493 test vectors, the examples use prefixes
+    const MESSAGE: [u8; 64] = [
+        0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17,
+        0x2a, 0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac, 0x45, 0xaf,
+        0x8e, 0x51, 0x30, 0xc8, 0x1c, 0x46, 0xa3, 0x5c, 0xe4, 0x11, 0xe5, 0xfb, 0xc1, 0x19, 0x1a,
+        0x0a, 0x52, 0xef, 0xf6, 0x9f, 0x24, 0x45, 0xdf, 0x4f, 0x9b, 0x17, 0xad, 0x2b, 0x41, 0x7b,
+        0xe6, 0x6c, 0x37, 0x10,
+    ];
+
+    fn check(length: usize, expected: &Block) {
+        let mut cipher = SoftAes::new(&KEY);
+        let mut tag = [0u8; BLOCK_LENGTH];
+        authenticate(&mut cipher, &MESSAGE[..length], &mut tag).unwrap();
+        assert_eq!(&tag, expected);
+        assert_eq!(verify(&mut cipher, &MESSAGE[..length], expected), Ok(()));
+    }
+
+    #[test]
+    fn subkeys() {
+        let mut cipher = SoftAes::new(&KEY);
+        let cmac = Cmac::new(&mut cipher).unwrap();
+        assert_eq!(
+            cmac.k1,
+            [
+                0xfb, 0xee, 0xd6, 0x18, 0x35, 0x71, 0x33, 0x66, 0x7c, 0x85, 0xe0, 0x8f, 0x72, 0x36,
+                0xa8, 0xde,
+            ]
+        );
+        assert_eq!(
+            cmac.k2,
+            [
+                0xf7, 0xdd, 0xac, 0x30, 0x6a, 0xe2, 0x66, 0xcc, 0xf9, 0x0b, 0xc1, 0x1e, 0xe4, 0x6d,
+                0x51, 0x3b,
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_message() {
+        check(
+            0,
+            &[
+                0xbb, 0x1d, 0x69, 0x29, 0xe9, 0x59, 0x37, 0x28, 0x7f, 0xa3, 0x7d, 0x12, 0x9b, 0x75,
+                0x67, 0x46,
+            ],
+        );
+    }
+
+    #[test]
+    fn message_16() {
+        check(
+            16,
+            &[
+                0x07, 0x0a, 0x16, 0xb4, 0x6b, 0x4d, 0x41, 0x44, 0xf7, 0x9b, 0xdd, 0x9d, 0xd0, 0x4a,
+                0x28, 0x7c,
+            ],
+        );
+    }
+
+    #[test]
+    fn message_40() {
+        check(
+            40,
+            &[
+                0xdf, 0xa6, 0x67, 0x47, 0xde, 0x9a, 0xe6, 0x30, 0x30, 0xca, 0x32, 0x61, 0x14, 0x97,
+                0xc8, 0x27,
+            ],
+        );
+    }
+
+    #[test]
+    fn message_64() {
+        check(
+            64,
+            &[
+                0x51, 0xf0, 0xbe, 0xbf, 0x7e, 0x3b, 0x9d, 0x92, 0xfc, 0x49, 0x74, 0x17, 0x79, 0x36,
+                0x3c, 0xfe,
+            ],
+        );
+    }
+
+    #[test]
+    fn incremental_update() {
+        let mut cipher = SoftAes::new(&KEY);
+        let mut cmac = Cmac::new(&mut cipher).unwrap();
+        for chunk in MESSAGE[..40].chunks(7) {
+            cmac.update(&mut cipher, chunk).unwrap();
+        }
+        let tag = cmac.finalize(&mut cipher).unwrap();
+        assert_eq!(
+            tag,
+            [
+                0xdf, 0xa6, 0x67, 0x47, 0xde, 0x9a, 0xe6, 0x30, 0x30, 0xca, 0x32, 0x61, 0x14, 0x97,
+                0xc8, 0x27,
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_tag() {
+        let mut cipher = SoftAes::new(&KEY);
+        let mut tag = [0u8; 4];
+        authenticate(&mut cipher, &MESSAGE[..16], &mut tag).unwrap();
+        assert_eq!(tag, [0x07, 0x0a, 0x16, 0xb4]);
+        assert_eq!(
+            verify(&mut cipher, &MESSAGE[..16], &[0x07, 0x0a, 0x16, 0xb5]),
+            Err(CryptoError::Authentication)
+        );
+        assert_eq!(
+            authenticate(&mut cipher, &MESSAGE, &mut []),
+            Err(CryptoError::InvalidLength)
+        );
+    }
+}
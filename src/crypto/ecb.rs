@@ -0,0 +1,88 @@
+//! AES-128 on the ECB peripheral
+//!
+//! Blocking encryption of single blocks. The ECB peripheral has the lowest
+//! priority of the AES users, a block is aborted if the CCM or AAR
+//! peripherals start, which is reported as a backend error.
+//!
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use crate::pac::ECB;
+
+use super::{Block, BlockCipher, CryptoError, Key, BLOCK_LENGTH, KEY_LENGTH};
+
+/// ECB data structure, read and written by the peripheral
+#[repr(C)]
+struct EcbData {
+    key: Key,
+    cleartext: Block,
+    ciphertext: Block,
+}
+
+/// Hardware AES-128 block cipher
+pub struct Ecb {
+    ecb: ECB,
+    data: EcbData,
+}
+
+impl Ecb {
+    /// Create a cipher using the ECB peripheral, the key is all zeros
+    pub fn new(ecb: ECB) -> Self {
+        ecb.intenclr
+            .write(|w| w.endecb().clear().errorecb().clear());
+        Self {
+            ecb,
+            data: EcbData {
+                key: [0u8; KEY_LENGTH],
+                cleartext: [0u8; BLOCK_LENGTH],
+                ciphertext: [0u8; BLOCK_LENGTH],
+            },
+        }
+    }
+
+    /// Release the ECB peripheral
+    pub fn free(self) -> ECB {
+        self.ecb
+    }
+}
+
+impl BlockCipher for Ecb {
+    fn set_key(&mut self, key: &Key) {
+        self.data.key = *key;
+    }
+
+    fn encrypt_block(&mut self, block: &mut Block) -> Result<(), CryptoError> {
+        self.data.cleartext = *block;
+        self.ecb.events_endecb.reset();
+        self.ecb.events_errorecb.reset();
+        // The data structure does not move while the peripheral runs, self
+        // is borrowed until the block is done
+        let pointer = &mut self.data as *mut EcbData as u32;
+        self.ecb
+            .ecbdataptr
+            .write(|w| unsafe { w.ecbdataptr().bits(pointer) });
+        compiler_fence(Ordering::Release);
+        self.ecb
+            .tasks_startecb
+            .write(|w| w.tasks_startecb().set_bit());
+        loop {
+            if self.ecb.events_endecb.read().events_endecb().bit_is_set() {
+                self.ecb.events_endecb.reset();
+                break;
+            }
+            if self
+                .ecb
+                .events_errorecb
+                .read()
+                .events_errorecb()
+                .bit_is_set()
+            {
+                self.ecb.events_errorecb.reset();
+                return Err(CryptoError::Backend);
+            }
+        }
+        compiler_fence(Ordering::Acquire);
+        *block = self.data.ciphertext;
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+//! Software AES-128
+//!
+//! Runs anywhere, including on the host. Slower than the ECB peripheral but
+//! leaves the hardware free for other stacks.
+//!
+
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes128;
+
+use super::{Block, BlockCipher, CryptoError, Key};
+
+/// Software AES-128 block cipher
+pub struct SoftAes {
+    cipher: Aes128,
+}
+
+impl SoftAes {
+    /// Create a cipher using `key`
+    pub fn new(key: &Key) -> Self {
+        Self {
+            cipher: Aes128::new(GenericArray::from_slice(key)),
+        }
+    }
+}
+
+impl BlockCipher for SoftAes {
+    fn set_key(&mut self, key: &Key) {
+        self.cipher = Aes128::new(GenericArray::from_slice(key));
+    }
+
+    fn encrypt_block(&mut self, block: &mut Block) -> Result<(), CryptoError> {
+        self.cipher.encrypt_block(GenericArray::from_mut_slice(block));
+        Ok(())
+    }
+}
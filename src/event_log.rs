@@ -0,0 +1,335 @@
+//! # Radio event log
+//!
+//! A compact binary log of radio events kept in a ring buffer. The log can be
+//! drained over RTT, a serial port or stored for post-mortem analysis of link
+//! problems. The radio reports its events to the hook set with
+//! `Radio::set_event_hook`, which adds them to the log with a timestamp.
+//!
+//! Each event is stored as a record of eight octets,
+//!
+//! ```notrust
+//! ---------------------------------
+//! | kind | timestamp | parameters |
+//! ---------------------------------
+//!    1        4            3         octets
+//! ```
+//!
+//! The timestamp and parameters are little endian.
+//!
+
+/// Size of a encoded event record in octets
+pub const RECORD_SIZE: usize = 8;
+
+/// Radio events
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// The driver entered a new state, the value of a `DriverState`
+    State(u8),
+    /// Clear channel assessment result
+    Cca {
+        /// The channel was busy
+        busy: bool,
+    },
+    /// A frame was transmitted
+    Transmit {
+        /// Frame length
+        length: u8,
+    },
+    /// A frame was received
+    Receive {
+        /// Frame length
+        length: u8,
+        /// Link quality indicator
+        lqi: u8,
+    },
+    /// A frame was retransmitted
+    Retransmission {
+        /// Sequence number of the frame
+        sequence: u8,
+        /// Transmission attempt
+        attempt: u8,
+    },
+    /// Energy detection result
+    EnergyDetect {
+        /// Channel
+        channel: u8,
+        /// Energy level
+        level: u8,
+    },
+    /// A error occured, with the `Error::code` of a radio error or a
+    /// application defined code above 255
+    Error(u16),
+    /// The radio remained in a state beyond the deadline and was recovered
+    Stuck {
+        /// The value of the STATE register
+        state: u8,
+        /// Time spent in the state in milliseconds
+        elapsed: u16,
+    },
+    /// Received frames were lost because the receive buffer or queue was full
+    RxOverflow {
+        /// Number of frames lost
+        dropped: u16,
+    },
+}
+
+const KIND_STATE: u8 = 1;
+const KIND_CCA: u8 = 2;
+const KIND_TRANSMIT: u8 = 3;
+const KIND_RECEIVE: u8 = 4;
+const KIND_RETRANSMISSION: u8 = 5;
+const KIND_ENERGY_DETECT: u8 = 6;
+const KIND_ERROR: u8 = 7;
+const KIND_STUCK: u8 = 8;
+const KIND_RX_OVERFLOW: u8 = 9;
+
+impl Event {
+    fn kind(&self) -> u8 {
+        match self {
+            Event::State(_) => KIND_STATE,
+            Event::Cca { .. } => KIND_CCA,
+            Event::Transmit { .. } => KIND_TRANSMIT,
+            Event::Receive { .. } => KIND_RECEIVE,
+            Event::Retransmission { .. } => KIND_RETRANSMISSION,
+            Event::EnergyDetect { .. } => KIND_ENERGY_DETECT,
+            Event::Error(_) => KIND_ERROR,
+            Event::Stuck { .. } => KIND_STUCK,
+            Event::RxOverflow { .. } => KIND_RX_OVERFLOW,
+        }
+    }
+
+    fn parameters(&self) -> [u8; 3] {
+        match *self {
+            Event::State(state) => [state, 0, 0],
+            Event::Cca { busy } => [busy as u8, 0, 0],
+            Event::Transmit { length } => [length, 0, 0],
+            Event::Receive { length, lqi } => [length, lqi, 0],
+            Event::Retransmission { sequence, attempt } => [sequence, attempt, 0],
+            Event::EnergyDetect { channel, level } => [channel, level, 0],
+            Event::Error(code) => {
+                let code = code.to_le_bytes();
+                [code[0], code[1], 0]
+            }
+            Event::Stuck { state, elapsed } => {
+                let elapsed = elapsed.to_le_bytes();
+                [state, elapsed[0], elapsed[1]]
+            }
+            Event::RxOverflow { dropped } => {
+                let dropped = dropped.to_le_bytes();
+                [dropped[0], dropped[1], 0]
+            }
+        }
+    }
+
+    fn from_parts(kind: u8, parameters: [u8; 3]) -> Option<Self> {
+        match kind {
+            KIND_STATE => Some(Event::State(parameters[0])),
+            KIND_CCA => Some(Event::Cca {
+                busy: parameters[0] != 0,
+            }),
+            KIND_TRANSMIT => Some(Event::Transmit {
+                length: parameters[0],
+            }),
+            KIND_RECEIVE => Some(Event::Receive {
+                length: parameters[0],
+                lqi: parameters[1],
+            }),
+            KIND_RETRANSMISSION => Some(Event::Retransmission {
+                sequence: parameters[0],
+                attempt: parameters[1],
+            }),
+            KIND_ENERGY_DETECT => Some(Event::EnergyDetect {
+                channel: parameters[0],
+                level: parameters[1],
+            }),
+            KIND_ERROR => Some(Event::Error(u16::from_le_bytes([
+                parameters[0],
+                parameters[1],
+            ]))),
+            KIND_STUCK => Some(Event::Stuck {
+                state: parameters[0],
+                elapsed: u16::from_le_bytes([parameters[1], parameters[2]]),
+            }),
+            KIND_RX_OVERFLOW => Some(Event::RxOverflow {
+                dropped: u16::from_le_bytes([parameters[0], parameters[1]]),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for Event {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Event::State(state) => write!(f, "state {}", state),
+            Event::Cca { busy } => {
+                if *busy {
+                    f.write_str("CCA busy")
+                } else {
+                    f.write_str("CCA idle")
+                }
+            }
+            Event::Transmit { length } => write!(f, "transmit {} octets", length),
+            Event::Receive { length, lqi } => write!(f, "receive {} octets LQI {}", length, lqi),
+            Event::Retransmission { sequence, attempt } => {
+                write!(f, "retransmission {} attempt {}", sequence, attempt)
+            }
+            Event::EnergyDetect { channel, level } => {
+                write!(f, "energy detect channel {} level {}", channel, level)
+            }
+            Event::Error(code) => write!(f, "error {}", code),
+            Event::Stuck { state, elapsed } => {
+                write!(f, "stuck in state {} for {} ms", state, elapsed)
+            }
+            Event::RxOverflow { dropped } => write!(f, "receive overflow, {} frames lost", dropped),
+        }
+    }
+}
+
+/// A logged event with timestamp
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Record {
+    /// Time of the event
+    pub timestamp: u32,
+    /// The event
+    pub event: Event,
+}
+
+impl Record {
+    /// Encode the record
+    pub fn encode(&self) -> [u8; RECORD_SIZE] {
+        let timestamp = self.timestamp.to_le_bytes();
+        let parameters = self.event.parameters();
+        [
+            self.event.kind(),
+            timestamp[0],
+            timestamp[1],
+            timestamp[2],
+            timestamp[3],
+            parameters[0],
+            parameters[1],
+            parameters[2],
+        ]
+    }
+
+    /// Decode a record
+    pub fn decode(data: &[u8; RECORD_SIZE]) -> Option<Self> {
+        let event = Event::from_parts(data[0], [data[5], data[6], data[7]])?;
+        Some(Self {
+            timestamp: u32::from_le_bytes([data[1], data[2], data[3], data[4]]),
+            event,
+        })
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for Record {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} {}", self.timestamp, self.event)
+    }
+}
+
+/// Ring buffer holding up to `N` records
+///
+/// When full the oldest record is overwritten.
+pub struct EventLog<const N: usize> {
+    records: [[u8; RECORD_SIZE]; N],
+    head: usize,
+    count: usize,
+    lost: u32,
+}
+
+impl<const N: usize> EventLog<N> {
+    /// Create a empty log
+    pub const fn new() -> Self {
+        Self {
+            records: [[0u8; RECORD_SIZE]; N],
+            head: 0,
+            count: 0,
+            lost: 0,
+        }
+    }
+
+    /// Add a event to the log
+    pub fn record(&mut self, timestamp: u32, event: Event) {
+        if N == 0 {
+            self.lost = self.lost.wrapping_add(1);
+            return;
+        }
+        let index = (self.head + self.count) % N;
+        self.records[index] = Record { timestamp, event }.encode();
+        if self.count == N {
+            self.head = (self.head + 1) % N;
+            self.lost = self.lost.wrapping_add(1);
+        } else {
+            self.count += 1;
+        }
+    }
+
+    /// Number of records in the log
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// The log is empty
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Number of records overwritten before being drained
+    pub fn lost(&self) -> u32 {
+        self.lost
+    }
+
+    /// Remove the oldest record
+    pub fn pop(&mut self) -> Option<Record> {
+        self.pop_encoded()
+            .and_then(|encoded| Record::decode(&encoded))
+    }
+
+    /// Remove the oldest record in encoded form
+    pub fn pop_encoded(&mut self) -> Option<[u8; RECORD_SIZE]> {
+        if self.count == 0 {
+            return None;
+        }
+        let encoded = self.records[self.head];
+        self.head = (self.head + 1) % N;
+        self.count -= 1;
+        Some(encoded)
+    }
+
+    /// Drain encoded records into the buffer, for writing to RTT or a serial port
+    ///
+    /// # Return
+    ///
+    /// Returns the number of octets written, always a multiple of the record size.
+    ///
+    pub fn drain_into(&mut self, buffer: &mut [u8]) -> usize {
+        let mut written = 0;
+        while buffer.len() - written >= RECORD_SIZE {
+            match self.pop_encoded() {
+                Some(encoded) => {
+                    buffer[written..written + RECORD_SIZE].copy_from_slice(&encoded);
+                    written += RECORD_SIZE;
+                }
+                None => break,
+            }
+        }
+        written
+    }
+
+    /// Remove all records
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.count = 0;
+    }
+}
+
+impl<const N: usize> Default for EventLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
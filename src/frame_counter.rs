@@ -0,0 +1,276 @@
+//! # Persistent frame counters
+//!
+//! Outgoing security frame counters must never be reused, also not after a
+//! reboot. The counter reserves a block of values ahead by writing the end
+//! of the block to storage, only every `reserve` frames. On boot counting
+//! resumes at the last written value, values reserved but not used before the
+//! reboot are skipped.
+//!
+//! Two storage pages are used alternately. When a page is full the other page
+//! is erased and written, the full page is kept until then so a valid value is
+//! always stored. The first word of the newest page is larger than any value
+//! in the other page.
+//!
+//! With `power_fail::EmergencySave` the next unused value is written on a
+//! power-failure warning, so fewer values are skipped.
+//!
+
+use crate::power_fail::EmergencySave;
+use crate::storage::{WordStorage, ERASED};
+
+/// Errors returned by the frame counter
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CounterError<E> {
+    /// Storage error
+    Storage(E),
+    /// The storage has less than two pages
+    InvalidStorage,
+    /// All frame counter values have been used
+    Exhausted,
+}
+
+#[cfg(feature = "fmt")]
+impl<E: core::fmt::Display> core::fmt::Display for CounterError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CounterError::Storage(error) => write!(f, "storage error, {}", error),
+            CounterError::InvalidStorage => f.write_str("invalid storage"),
+            CounterError::Exhausted => f.write_str("frame counter exhausted"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl<E: core::fmt::Debug + core::fmt::Display> core::error::Error for CounterError<E> {}
+
+/// Frame counter persisted in two pages of `S`
+pub struct FrameCounter<S: WordStorage> {
+    storage: S,
+    /// Number of values reserved with each write
+    reserve: u32,
+    /// Next value to hand out
+    next: u32,
+    /// End of the reserved values, written to storage
+    reserved: u32,
+    /// Page in use
+    page: usize,
+    /// Next free word in the page
+    index: usize,
+}
+
+impl<S: WordStorage> FrameCounter<S> {
+    /// Restore the counter from storage and reserve values ahead
+    ///
+    /// A new storage, with both pages erased, starts counting at zero.
+    pub fn new(storage: S, reserve: u32) -> Result<Self, CounterError<S::Error>> {
+        if storage.pages() < 2 {
+            return Err(CounterError::InvalidStorage);
+        }
+        let mut counter = Self {
+            storage,
+            reserve: reserve.max(1),
+            next: 0,
+            reserved: 0,
+            page: 0,
+            index: 0,
+        };
+        counter.restore();
+        counter.reserve_ahead()?;
+        Ok(counter)
+    }
+
+    /// Number of written words in the page
+    fn written(&self, page: usize) -> usize {
+        (0..self.storage.page_words())
+            .find(|index| self.storage.read(page, *index) == ERASED)
+            .unwrap_or_else(|| self.storage.page_words())
+    }
+
+    fn restore(&mut self) {
+        let written = [self.written(0), self.written(1)];
+        let first = [self.storage.read(0, 0), self.storage.read(1, 0)];
+        let page = match (written[0], written[1]) {
+            (0, 0) => return,
+            (_, 0) => 0,
+            (0, _) => 1,
+            _ if first[1] > first[0] => 1,
+            _ => 0,
+        };
+        self.page = page;
+        self.index = written[page];
+        self.next = self.storage.read(page, written[page] - 1);
+        self.reserved = self.next;
+    }
+
+    /// Append a value, switching page when full
+    fn append(&mut self, value: u32) -> Result<(), CounterError<S::Error>> {
+        if self.index >= self.storage.page_words() {
+            let page = 1 - self.page;
+            self.storage.erase(page).map_err(CounterError::Storage)?;
+            self.page = page;
+            self.index = 0;
+        }
+        self.storage
+            .write(self.page, self.index, value)
+            .map_err(CounterError::Storage)?;
+        self.index += 1;
+        Ok(())
+    }
+
+    fn reserve_ahead(&mut self) -> Result<(), CounterError<S::Error>> {
+        // The erased value can not be stored
+        let reserved = match self.next.checked_add(self.reserve) {
+            Some(reserved) if reserved != ERASED => reserved,
+            _ if self.next < ERASED - 1 => ERASED - 1,
+            _ => return Err(CounterError::Exhausted),
+        };
+        self.append(reserved)?;
+        self.reserved = reserved;
+        Ok(())
+    }
+
+    /// Take the next frame counter value
+    pub fn allocate(&mut self) -> Result<u32, CounterError<S::Error>> {
+        if self.next >= self.reserved {
+            self.reserve_ahead()?;
+        }
+        let value = self.next;
+        self.next += 1;
+        Ok(value)
+    }
+
+    /// The next value that will be handed out
+    pub fn peek(&self) -> u32 {
+        self.next
+    }
+
+    /// Release the storage
+    pub fn free(self) -> S {
+        self.storage
+    }
+}
+
+impl<S: WordStorage> EmergencySave for FrameCounter<S> {
+    /// Write the next unused value, if the page has room
+    fn save(&mut self) {
+        if self.next < self.reserved && self.index < self.storage.page_words() {
+            let next = self.next;
+            if self.storage.write(self.page, self.index, next).is_ok() {
+                self.index += 1;
+                self.reserved = next;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::storage::MockStorage;
+
+    #[test]
+    fn invalid_storage() {
+        let storage = MockStorage::<1, 4>::new();
+        assert!(matches!(
+            FrameCounter::new(storage, 8),
+            Err(CounterError::InvalidStorage)
+        ));
+    }
+
+    #[test]
+    fn reserve_ahead() {
+        let mut counter = FrameCounter::new(MockStorage::<2, 16>::new(), 8).unwrap();
+        for value in 0..9 {
+            assert_eq!(counter.allocate(), Ok(value));
+        }
+        // Values are reserved once at creation and once after eight values
+        let storage = counter.free();
+        assert_eq!(storage.read(0, 0), 8);
+        assert_eq!(storage.read(0, 1), 16);
+        assert_eq!(storage.read(0, 2), ERASED);
+    }
+
+    #[test]
+    fn restore_mid_reservation() {
+        let mut counter = FrameCounter::new(MockStorage::<2, 16>::new(), 8).unwrap();
+        for value in 0..3 {
+            assert_eq!(counter.allocate(), Ok(value));
+        }
+        // Reset, the values reserved but not used are skipped
+        let mut counter = FrameCounter::new(counter.free(), 8).unwrap();
+        assert_eq!(counter.peek(), 8);
+        assert_eq!(counter.allocate(), Ok(8));
+        let storage = counter.free();
+        assert_eq!(storage.read(0, 1), 16);
+    }
+
+    #[test]
+    fn restore_new_storage() {
+        let mut counter = FrameCounter::new(MockStorage::<2, 16>::new(), 8).unwrap();
+        assert_eq!(counter.allocate(), Ok(0));
+        // Reset before any value was used after the reservation
+        let counter = FrameCounter::new(counter.free(), 8).unwrap();
+        assert_eq!(counter.peek(), 8);
+    }
+
+    #[test]
+    fn alternate_pages() {
+        let mut counter = FrameCounter::new(MockStorage::<2, 4>::new(), 1).unwrap();
+        for value in 0..5 {
+            assert_eq!(counter.allocate(), Ok(value));
+        }
+        // The first page is full, the second page is erased and used
+        let storage = counter.free();
+        assert_eq!(storage.erases(0), 0);
+        assert_eq!(storage.erases(1), 1);
+        assert_eq!(storage.read(0, 3), 4);
+        assert_eq!(storage.read(1, 0), 5);
+        // The newest page is restored
+        let mut counter = FrameCounter::new(storage, 1).unwrap();
+        assert_eq!(counter.peek(), 5);
+        for value in 5..9 {
+            assert_eq!(counter.allocate(), Ok(value));
+        }
+        // Back to the first page
+        let storage = counter.free();
+        assert_eq!(storage.erases(0), 1);
+        assert_eq!(storage.read(0, 0), 9);
+        let counter = FrameCounter::new(storage, 1).unwrap();
+        assert_eq!(counter.peek(), 9);
+    }
+
+    #[test]
+    fn exhausted() {
+        let mut storage = MockStorage::<2, 4>::new();
+        storage.write(0, 0, ERASED - 3).unwrap();
+        let mut counter = FrameCounter::new(storage, 8).unwrap();
+        assert_eq!(counter.allocate(), Ok(ERASED - 3));
+        assert_eq!(counter.allocate(), Ok(ERASED - 2));
+        // The erased value can not be stored, so it is never handed out
+        assert_eq!(counter.allocate(), Err(CounterError::Exhausted));
+        assert_eq!(counter.allocate(), Err(CounterError::Exhausted));
+    }
+
+    #[test]
+    fn emergency_save() {
+        let mut counter = FrameCounter::new(MockStorage::<2, 16>::new(), 8).unwrap();
+        for value in 0..3 {
+            assert_eq!(counter.allocate(), Ok(value));
+        }
+        counter.save();
+        // Counting resumes at the next unused value
+        let mut counter = FrameCounter::new(counter.free(), 8).unwrap();
+        assert_eq!(counter.allocate(), Ok(3));
+    }
+
+    #[test]
+    fn emergency_save_full_page() {
+        let mut counter = FrameCounter::new(MockStorage::<2, 1>::new(), 8).unwrap();
+        assert_eq!(counter.allocate(), Ok(0));
+        // No room without erasing, the reservation still holds
+        counter.save();
+        let counter = FrameCounter::new(counter.free(), 8).unwrap();
+        assert_eq!(counter.peek(), 8);
+    }
+}
@@ -5,9 +5,86 @@
 //! The `radio` module contains a 802.15.4 implementation of the
 //! nRF52 RADIO peripheral.
 //!
+//! The `phy` module contains the `Ieee802154Phy` trait, the PHY operations
+//! implemented by the radio. The `sim` module, enabled with the `mock` feature, contains a
+//! simulated radio implementing the trait.
+//!
 //! The `timer` module contains a timer implementations using the
 //! nRF52 TIMER peripheral.
 //!
+//! The `capture` module stores captured frames in a ring of storage pages,
+//! read back as pcap records.
+//!
+//! The `channel` module, enabled with the `energy-detect` feature, ranks
+//! channels for forming a network.
+//!
+//! The `clocks` module contains a calibration loop for the internal RC
+//! oscillator clocking the LFCLK.
+//!
+//! The `crypto` module contains the CCM* and CMAC modes on AES-128 block
+//! cipher backends, including the ECB peripheral.
+//!
+//! The `command` module contains a serial command protocol for controlling
+//! the radio from a host.
+//!
+//! The `ncp` module carries the command protocol in framed packets with
+//! asynchronous notifications, for running as a network co-processor.
+//!
+//! The `shell` module, enabled with the `shell` feature, contains a
+//! interactive test shell for bring-up and RF lab work.
+//!
+//! The `sniffer` module, enabled with the `sniffer` feature, contains the
+//! capture protocol used by the nRF Sniffer for 802.15.4 Wireshark plug-in.
+//!
+//! The `event_log` module contains a compact binary log of radio events.
+//!
+//! The `pool` module contains a fixed capacity packet buffer pool.
+//!
+//! The `storage` module contains flash storage and the `frame_counter`
+//! module persistent security frame counters.
+//!
+//! The `permit_join` module contains the permit-join window of a
+//! coordinator.
+//!
+//! The `power_fail` module contains a power-failure warning used to save
+//! volatile state before a brown-out.
+//!
+//! The `qspi` module, on the nRF52840, contains a driver for a external NOR
+//! flash on the QSPI peripheral, usable as storage.
+//!
+//! The `reset` module captures the reset reason and the last state of the
+//! radio driver before the reset.
+//!
+//! The `rng` module contains a random number generator using the nRF52 RNG
+//! peripheral.
+//!
+//! The `self_test` module contains a boot-time self test of the radio,
+//! timer and AES backend.
+//!
+//! The `sleepy` module contains a scheduler for sleepy end devices, polling
+//! the parent and sleeping between RTC wakeups.
+//!
+//! The `statistics` module contains the counters kept by the radio.
+//!
+//! The `superframe` module contains a superframe scheduler for
+//! beacon-enabled operation.
+//!
+//! The `supervisor` module contains a deadline supervisor recovering the
+//! radio when it does not progress.
+//!
+//! The `wpanusb` module, enabled with the `wpanusb` feature, contains a USB
+//! class making the radio usable by the Linux wpan stack.
+//!
+//! The `monotonic` module, enabled with the `rtic-monotonics` feature,
+//! contains RTIC monotonics using the nRF52 TIMER peripheral.
+//!
+//! Without a chip feature only the hardware independent modules, `capture`,
+//! `crypto`, `event_log`, `frame_counter`, `permit_join`, `phy`, `pool`,
+//! `power_fail`, `storage` and `timer`, are built, without the peripheral
+//! drivers.
+//! Together with the `mock` feature this allows testing on the host with
+//! `cargo test`.
+//!
 
 #![no_std]
 #![warn(missing_docs)]
@@ -21,5 +98,59 @@ pub use nrf52840_pac as pac;
 #[cfg(feature = "microbit")]
 pub use microbit::pac;
 
+#[cfg(feature = "hal-52833")]
+pub use nrf52833_hal as hal;
+
+#[cfg(feature = "hal-52840")]
+pub use nrf52840_hal as hal;
+
+#[cfg(feature = "microbit")]
+pub use microbit::hal;
+
+pub mod capture;
+#[cfg(all(feature = "device", feature = "energy-detect"))]
+pub mod channel;
+#[cfg(feature = "device")]
+pub mod clocks;
+#[cfg(feature = "device")]
+pub mod command;
+pub mod crypto;
+pub mod event_log;
+pub mod frame_counter;
+#[cfg(feature = "device")]
+pub mod ncp;
+#[cfg(all(feature = "device", feature = "rtic-monotonics"))]
+pub mod monotonic;
+pub mod permit_join;
+pub mod phy;
+pub mod pool;
+pub mod power_fail;
+#[cfg(feature = "52840")]
+pub mod qspi;
+#[cfg(feature = "device")]
 pub mod radio;
+#[cfg(feature = "device")]
+pub mod reset;
+#[cfg(feature = "device")]
+pub mod rng;
+pub mod security;
+#[cfg(feature = "device")]
+pub mod self_test;
+#[cfg(feature = "mock")]
+pub mod sim;
+#[cfg(feature = "device")]
+pub mod sleepy;
+#[cfg(all(feature = "device", feature = "shell"))]
+pub mod shell;
+#[cfg(all(feature = "device", feature = "sniffer"))]
+pub mod sniffer;
+#[cfg(feature = "device")]
+pub mod statistics;
+pub mod storage;
+#[cfg(feature = "device")]
+pub mod superframe;
+#[cfg(feature = "device")]
+pub mod supervisor;
 pub mod timer;
+#[cfg(all(feature = "device", feature = "wpanusb"))]
+pub mod wpanusb;
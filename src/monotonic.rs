@@ -0,0 +1,114 @@
+//! # RTIC monotonics
+//!
+//! Monotonic implementations for use with `rtic-monotonics` and RTIC 2,
+//! backed by the nRF52 TIMER peripheral.
+//!
+//! The monotonic shares the free-running timer of the `timer` module so async
+//! delays and the radio's own deadlines use the same timebase. The TIMER shall
+//! be initialised using `Timer::init` before the monotonic is started.
+//!
+//! CC3 is reserved for the monotonic, CC1 and CC2 can still be used through
+//! the `Timer` trait.
+//!
+//! The timer is 32-bit with 1 μs resolution, it wraps around after about 71
+//! minutes. Delays must be shorter than half of that.
+//!
+//! ```notrust
+//! #[interrupt]
+//! fn TIMER0() {
+//!     Timer0Monotonic::on_interrupt();
+//! }
+//! ```
+//!
+
+use rtic_time::{Monotonic, TimerQueue};
+
+use crate::pac::{Interrupt, NVIC, TIMER0, TIMER1};
+
+/// Instant with 1 μs resolution
+pub type Instant = fugit::TimerInstantU32<1_000_000>;
+/// Duration with 1 μs resolution
+pub type Duration = fugit::TimerDurationU32<1_000_000>;
+
+/// Compare channel used by the monotonic
+const MONOTONIC_CC: usize = 3;
+
+macro_rules! impl_monotonic {
+    ($name:ident, $queue:ident, $timer:ident) => {
+        /// Monotonic using the
+        #[doc = stringify!($timer)]
+        /// peripheral
+        pub struct $name;
+
+        static $queue: TimerQueue<$name> = TimerQueue::new();
+
+        impl $name {
+            /// Start the monotonic and unmask the TIMER interrupt
+            ///
+            /// The TIMER shall have been initialised using `Timer::init`.
+            pub fn start(_timer: &mut $timer) {
+                Self::clear_compare_flag();
+                $queue.initialize(Self);
+                unsafe {
+                    NVIC::unmask(Interrupt::$timer);
+                }
+            }
+
+            /// Handle the TIMER interrupt, call this from the interrupt handler
+            pub fn on_interrupt() {
+                unsafe {
+                    $queue.on_monotonic_interrupt();
+                }
+            }
+
+            /// Wait for the duration
+            pub async fn delay(duration: Duration) {
+                $queue.delay(duration).await;
+            }
+
+            /// Wait until the instant
+            pub async fn delay_until(instant: Instant) {
+                $queue.delay_until(instant).await;
+            }
+
+            fn timer() -> &'static crate::pac::timer0::RegisterBlock {
+                unsafe { &*$timer::ptr() }
+            }
+        }
+
+        impl Monotonic for $name {
+            const ZERO: Self::Instant = Instant::from_ticks(0);
+            const TICK_PERIOD: Self::Duration = Duration::from_ticks(1);
+
+            type Instant = Instant;
+            type Duration = Duration;
+
+            fn now() -> Self::Instant {
+                let timer = Self::timer();
+                timer.tasks_capture[0].write(|w| w.tasks_capture().set_bit());
+                Instant::from_ticks(timer.cc[0].read().bits())
+            }
+
+            fn set_compare(instant: Self::Instant) {
+                let timer = Self::timer();
+                timer.cc[MONOTONIC_CC].write(|w| unsafe { w.bits(instant.ticks()) });
+                timer.intenset.write(|w| w.compare3().set());
+            }
+
+            fn clear_compare_flag() {
+                Self::timer().events_compare[MONOTONIC_CC].reset();
+            }
+
+            fn pend_interrupt() {
+                NVIC::pend(Interrupt::$timer);
+            }
+
+            fn disable_timer() {
+                Self::timer().intenclr.write(|w| w.compare3().clear());
+            }
+        }
+    };
+}
+
+impl_monotonic!(Timer0Monotonic, TIMER0_QUEUE, TIMER0);
+impl_monotonic!(Timer1Monotonic, TIMER1_QUEUE, TIMER1);
@@ -0,0 +1,279 @@
+//! # Network co-processor protocol
+//!
+//! Carries the `command` protocol over a serial line in HDLC-like frames,
+//! with transaction identifiers and asynchronous notifications. A host MCU
+//! or PC runs the upper layers while the nRF52 handles the timing critical
+//! radio work.
+//!
+//! ## Frame layout
+//!
+//! ```notrust
+//! ------------------------------------------
+//! | 0x7e | header | packet | FCS  | 0x7e |
+//! ------------------------------------------
+//!    1       1        *       2      1     octets
+//! ```
+//!
+//! The packet is a command or response of the `command` protocol without the
+//! length octet. The FCS is the CRC-16/X.25 of the header and the packet,
+//! little endian. The octets 0x7e and 0x7d between the flags are escaped as
+//! 0x7d followed by the octet XOR 0x20.
+//!
+//! The low four bits of the header are the transaction identifier, a
+//! response has the identifier of the command. Bit 7 is set for
+//! notifications, such as received frames and energy detect results, which
+//! are sent without a command.
+//!
+//! This crate has no MAC layer, MAC operations such as association are
+//! implemented on the host on top of the frame transmit and receive
+//! commands.
+//!
+
+use crate::command::{Command, CommandHandler, ProtocolError, Response, Status};
+use crate::radio::{PacketBuffer, Radio, MAX_PACKET_LENGHT};
+
+/// Frame delimiter
+pub const FLAG: u8 = 0x7e;
+/// Escape octet
+pub const ESCAPE: u8 = 0x7d;
+/// Notification bit of the header
+pub const HEADER_NOTIFICATION: u8 = 0x80;
+/// Transaction identifier bits of the header
+pub const HEADER_TRANSACTION_MASK: u8 = 0x0f;
+
+/// Largest packet, a received frame notification without the length octet
+const MAX_PACKET_LENGTH: usize = MAX_PACKET_LENGHT + 5;
+
+/// Largest unescaped frame content, header, packet and FCS
+const MAX_CONTENT_LENGTH: usize = 1 + MAX_PACKET_LENGTH + 2;
+
+/// Largest escaped frame, including flags
+pub const MAX_FRAME_LENGTH: usize = MAX_CONTENT_LENGTH * 2 + 2;
+
+/// CRC-16/X.25
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for byte in data {
+        crc ^= u16::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Write a octet, escaped if needed, returns the new position
+fn put_escaped(output: &mut [u8], position: usize, byte: u8) -> Result<usize, ProtocolError> {
+    if byte == FLAG || byte == ESCAPE {
+        if output.len() < position + 2 {
+            return Err(ProtocolError::BufferTooSmall);
+        }
+        output[position] = ESCAPE;
+        output[position + 1] = byte ^ 0x20;
+        Ok(position + 2)
+    } else {
+        if output.len() < position + 1 {
+            return Err(ProtocolError::BufferTooSmall);
+        }
+        output[position] = byte;
+        Ok(position + 1)
+    }
+}
+
+/// Encode a frame
+///
+/// # Return
+///
+/// Returns the number of octets written to `output`.
+///
+pub fn encode_frame(header: u8, packet: &[u8], output: &mut [u8]) -> Result<usize, ProtocolError> {
+    if packet.len() > MAX_PACKET_LENGTH {
+        return Err(ProtocolError::InvalidLength);
+    }
+    if output.is_empty() {
+        return Err(ProtocolError::BufferTooSmall);
+    }
+    let mut content = [0u8; MAX_CONTENT_LENGTH];
+    content[0] = header;
+    content[1..=packet.len()].copy_from_slice(packet);
+    let content = &content[..=packet.len()];
+    let fcs = crc16(content).to_le_bytes();
+    output[0] = FLAG;
+    let mut position = 1;
+    for byte in content.iter().chain(fcs.iter()) {
+        position = put_escaped(output, position, *byte)?;
+    }
+    if output.len() < position + 1 {
+        return Err(ProtocolError::BufferTooSmall);
+    }
+    output[position] = FLAG;
+    Ok(position + 1)
+}
+
+/// Decodes frames from a byte stream
+pub struct FrameDecoder {
+    buffer: [u8; MAX_CONTENT_LENGTH],
+    position: usize,
+    escape: bool,
+    overflow: bool,
+    errors: u32,
+}
+
+impl FrameDecoder {
+    /// Create a new decoder
+    pub fn new() -> Self {
+        Self {
+            buffer: [0u8; MAX_CONTENT_LENGTH],
+            position: 0,
+            escape: false,
+            overflow: false,
+            errors: 0,
+        }
+    }
+
+    /// Number of frames discarded, because of a bad FCS or length
+    pub fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Add a octet from the stream
+    ///
+    /// # Return
+    ///
+    /// Returns the header and packet, without FCS, of a valid frame.
+    ///
+    pub fn push(&mut self, byte: u8) -> Option<&[u8]> {
+        match byte {
+            FLAG => {
+                let length = self.position;
+                let overflow = self.overflow;
+                self.position = 0;
+                self.escape = false;
+                self.overflow = false;
+                if length == 0 && !overflow {
+                    return None;
+                }
+                if overflow || length < 4 {
+                    self.errors = self.errors.wrapping_add(1);
+                    return None;
+                }
+                let content = &self.buffer[..length - 2];
+                let fcs = u16::from_le_bytes([self.buffer[length - 2], self.buffer[length - 1]]);
+                if crc16(content) != fcs {
+                    self.errors = self.errors.wrapping_add(1);
+                    return None;
+                }
+                Some(&self.buffer[..length - 2])
+            }
+            ESCAPE => {
+                self.escape = true;
+                None
+            }
+            _ => {
+                let byte = if self.escape { byte ^ 0x20 } else { byte };
+                self.escape = false;
+                if self.position < self.buffer.len() {
+                    self.buffer[self.position] = byte;
+                    self.position += 1;
+                } else {
+                    self.overflow = true;
+                }
+                None
+            }
+        }
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Network co-processor, executing framed commands on the radio
+pub struct Ncp {
+    decoder: FrameDecoder,
+    handler: CommandHandler,
+}
+
+impl Ncp {
+    /// Create a new co-processor
+    pub fn new() -> Self {
+        Self {
+            decoder: FrameDecoder::new(),
+            handler: CommandHandler::new(),
+        }
+    }
+
+    /// Number of frames from the host discarded
+    pub fn errors(&self) -> u32 {
+        self.decoder.errors()
+    }
+
+    /// Add a octet received from the host
+    ///
+    /// A complete command is executed and the response frame written to
+    /// `output`.
+    ///
+    /// # Return
+    ///
+    /// Returns the number of octets written to `output`.
+    ///
+    pub fn receive<const RX: usize>(
+        &mut self,
+        radio: &mut Radio<RX>,
+        byte: u8,
+        output: &mut [u8],
+    ) -> Option<usize> {
+        let content = self.decoder.push(byte)?;
+        let header = content[0] & HEADER_TRANSACTION_MASK;
+        let packet = &content[1..];
+        let response = match Command::decode(packet) {
+            Ok(command) => self.handler.execute(radio, &command),
+            Err(ProtocolError::UnknownCommand(identifier)) => {
+                Response::Error(identifier, Status::Unsupported)
+            }
+            Err(ProtocolError::InvalidLength) => {
+                Response::Error(packet[0], Status::InvalidParameter)
+            }
+            Err(_) => return None,
+        };
+        encode_response(header, &response, output)
+    }
+
+    /// Process radio events, call this from the radio interrupt
+    ///
+    /// Notifications are written to `output`.
+    ///
+    /// # Return
+    ///
+    /// Returns the number of octets written to `output`.
+    ///
+    pub fn poll<const RX: usize>(
+        &mut self,
+        radio: &mut Radio<RX>,
+        buffer: &mut PacketBuffer,
+        timestamp: u32,
+        output: &mut [u8],
+    ) -> Option<usize> {
+        let response = self.handler.poll(radio, buffer, timestamp)?;
+        encode_response(HEADER_NOTIFICATION, &response, output)
+    }
+}
+
+impl Default for Ncp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn encode_response(header: u8, response: &Response, output: &mut [u8]) -> Option<usize> {
+    let mut packet = [0u8; MAX_PACKET_LENGTH + 1];
+    let length = response.encode(&mut packet).ok()?;
+    // Skip the length octet
+    encode_frame(header, &packet[1..length], output).ok()
+}
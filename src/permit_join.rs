@@ -0,0 +1,145 @@
+//! # Permit-join window
+//!
+//! Coordinator-side handling of the window in which devices may join the
+//! network. While the window is open association requests are accepted and
+//! beacons are sent with the association permit flag set.
+//!
+//! The window is opened for a number of seconds, or until closed, and can be
+//! extended while open. Expiry is driven by a compare channel of a `Timer`,
+//! call `PermitJoin::on_timer` from the timer interrupt. The window must be
+//! the only user of the compare channel.
+//!
+
+use crate::timer::Timer;
+
+/// Longest timed window in seconds
+pub const MAX_DURATION: u16 = 1800;
+
+/// Microseconds in a second
+const SECOND: u32 = 1_000_000;
+
+/// Event reported by the permit-join window
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum JoinEvent {
+    /// The window has expired, joining is no longer permitted
+    Expired,
+}
+
+/// Permit-join window
+pub struct PermitJoin {
+    /// Timer compare channel
+    channel: usize,
+    /// The window is open
+    open: bool,
+    /// End of a timed window in microseconds
+    end: Option<u32>,
+    /// Compare value of the channel
+    compare: u32,
+}
+
+impl PermitJoin {
+    /// Create a closed window using the timer compare channel `channel`, 1
+    /// to 3
+    pub const fn new(channel: usize) -> Self {
+        Self {
+            channel,
+            open: false,
+            end: None,
+            compare: 0,
+        }
+    }
+
+    /// Open the window for `seconds`, at most `MAX_DURATION`, or until closed
+    /// if None
+    ///
+    /// A open window is restarted with the new duration. Zero seconds closes
+    /// the window.
+    pub fn open<T: Timer>(&mut self, timer: &mut T, seconds: Option<u16>) {
+        match seconds {
+            Some(0) => self.close(timer),
+            Some(seconds) => {
+                let duration = u32::from(seconds.min(MAX_DURATION)) * SECOND;
+                self.open = true;
+                self.schedule(timer, timer.now().wrapping_add(duration));
+            }
+            None => {
+                self.open = true;
+                self.end = None;
+                timer.stop(self.channel);
+            }
+        }
+    }
+
+    /// Close the window, no event is reported
+    pub fn close<T: Timer>(&mut self, timer: &mut T) {
+        self.open = false;
+        self.end = None;
+        timer.stop(self.channel);
+    }
+
+    /// Extend a timed window by `seconds`, the remaining time is limited to
+    /// `MAX_DURATION`
+    ///
+    /// A closed window is opened for `seconds`, a window open until closed
+    /// is left as is.
+    pub fn extend<T: Timer>(&mut self, timer: &mut T, seconds: u16) {
+        if !self.open {
+            self.open(timer, Some(seconds));
+        } else if let Some(remaining) = self.remaining(timer) {
+            let remaining = remaining.saturating_add(u32::from(seconds) * SECOND);
+            let duration = remaining.min(u32::from(MAX_DURATION) * SECOND);
+            self.schedule(timer, timer.now().wrapping_add(duration));
+        }
+    }
+
+    /// Joining is permitted, association requests shall be accepted
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Time left of a timed window in microseconds
+    ///
+    /// Returns None if the window is closed or open until closed.
+    pub fn remaining<T: Timer>(&self, timer: &T) -> Option<u32> {
+        match self.end {
+            Some(end) if self.open => {
+                let remaining = end.wrapping_sub(timer.now()) as i32;
+                Some(remaining.max(0) as u32)
+            }
+            _ => None,
+        }
+    }
+
+    /// Set the association permit flag of `beacon` from the window
+    #[cfg(feature = "device")]
+    pub fn beacon<'a>(&self, beacon: crate::radio::Beacon<'a>) -> crate::radio::Beacon<'a> {
+        beacon.association_permit(self.open)
+    }
+
+    /// Handle the timer compare event, call this from the timer interrupt
+    ///
+    /// # Return
+    ///
+    /// Returns `JoinEvent::Expired` when a timed window closes.
+    ///
+    pub fn on_timer<T: Timer>(&mut self, timer: &mut T) -> Option<JoinEvent> {
+        if !timer.is_compare_event(self.channel) {
+            return None;
+        }
+        timer.ack_compare_event(self.channel);
+        if self.open && self.end.is_some() {
+            self.close(timer);
+            Some(JoinEvent::Expired)
+        } else {
+            None
+        }
+    }
+
+    fn schedule<T: Timer>(&mut self, timer: &mut T, end: u32) {
+        // fire_in counts from the previous compare value
+        timer.fire_in(self.channel, end.wrapping_sub(self.compare));
+        self.compare = end;
+        self.end = Some(end);
+    }
+}
@@ -0,0 +1,299 @@
+//! # IEEE 802.15.4 PHY abstraction
+//!
+//! The `Ieee802154Phy` trait holds the PHY operations, channel and power
+//! selection, transmission, reception, energy detection and clear channel
+//! assessment. It is implemented by `radio::Radio` and, with the `mock`
+//! feature, by the simulated `sim::SimRadio`. Code written against the
+//! trait can use either, or another radio.
+//!
+//! Received frames use the buffer layout of `Radio::receive_slice`,
+//!
+//! ```notrust
+//! ------------------------
+//! | size | payload | LQI |
+//! ------------------------
+//!    1        *       1     octets
+//! ```
+//!
+//! `check_frame_length` and `check_received_length` validate frame sizes
+//! for radio implementations, so that oversized and undersized frames are
+//! refused instead of overrunning a buffer.
+//!
+
+/// Errors returned by the radio
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// Clear channel assesment returned that the channel is busy
+    CcaBusy,
+    /// The channel is not a 802.15.4 channel
+    InvalidChannel,
+    /// The radio does not support the transmission power
+    InvalidPower,
+    /// The provided buffer is too small
+    BufferTooSmall,
+    /// The frame is empty or longer than a 802.15.4 frame
+    InvalidLength,
+    /// The PPI channels or channel groups do not exist
+    InvalidResources,
+    /// The regulatory region requires clear channel assessment
+    CcaRequired,
+    /// The operation is not possible in the current mode of the radio
+    InvalidState,
+}
+
+impl Error {
+    /// Code of the error in a `Event::Error`, 1 to 255
+    pub fn code(&self) -> u16 {
+        match self {
+            Error::CcaBusy => 1,
+            Error::InvalidChannel => 2,
+            Error::InvalidPower => 3,
+            Error::BufferTooSmall => 4,
+            Error::InvalidLength => 5,
+            Error::InvalidResources => 6,
+            Error::CcaRequired => 7,
+            Error::InvalidState => 8,
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::CcaBusy => f.write_str("channel busy"),
+            Error::InvalidChannel => f.write_str("invalid channel"),
+            Error::InvalidPower => f.write_str("invalid transmission power"),
+            Error::BufferTooSmall => f.write_str("buffer too small"),
+            Error::InvalidLength => f.write_str("invalid frame length"),
+            Error::InvalidResources => f.write_str("invalid PPI resources"),
+            Error::CcaRequired => f.write_str("clear channel assessment required"),
+            Error::InvalidState => f.write_str("invalid radio state"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::error::Error for Error {}
+
+/// Largest PSDU, aMaxPhyPacketSize
+pub const MAX_PSDU_LENGTH: usize = 127;
+/// Octets of FCS
+pub const FCS_LENGTH: usize = 2;
+/// Largest frame without FCS
+pub const MAX_FRAME_LENGTH: usize = MAX_PSDU_LENGTH - FCS_LENGTH;
+/// Set in the PHR for reserved lengths
+const PHR_RESERVED: u8 = 0x80;
+
+/// Check if the channel is a valid 802.15.4 channel, 11 to 26
+pub fn is_valid_channel(channel: u8) -> bool {
+    (11..=26).contains(&channel)
+}
+
+/// Check if the transmission power level is supported by the radio
+pub fn is_valid_transmission_power(power: i8) -> bool {
+    matches!(power, -40 | -20 | -16 | -12 | -8 | -4 | 0 | 2..=8)
+}
+
+/// Check that a frame of `length` octets, without FCS, can be transmitted
+///
+/// Returns `Error::InvalidLength` for empty frames and frames longer than
+/// `MAX_FRAME_LENGTH`.
+pub fn check_frame_length(length: usize) -> Result<(), Error> {
+    if length == 0 || length > MAX_FRAME_LENGTH {
+        Err(Error::InvalidLength)
+    } else {
+        Ok(())
+    }
+}
+
+/// Check the PHR of a received packet against a buffer of `capacity` octets
+///
+/// The buffer holds the PHR followed by the PSDU.
+///
+/// # Return
+///
+/// Returns the PSDU length, `Error::InvalidLength` for a reserved length or
+/// a PSDU too short to hold the FCS, or `Error::BufferTooSmall` if the
+/// packet does not fit in the buffer.
+///
+pub fn check_received_length(phr: u8, capacity: usize) -> Result<usize, Error> {
+    let length = usize::from(phr);
+    if phr & PHR_RESERVED != 0 || length < FCS_LENGTH {
+        Err(Error::InvalidLength)
+    } else if length >= capacity {
+        Err(Error::BufferTooSmall)
+    } else {
+        Ok(length)
+    }
+}
+
+/// Result of a clear channel assessment
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CcaResult {
+    /// The channel is busy
+    pub busy: bool,
+    /// Energy level measured on the channel, in the scale of the energy
+    /// detect results
+    pub level: u8,
+}
+
+/// Symbols in a superframe with superframe order 0, aBaseSuperframeDuration
+#[cfg(feature = "energy-detect")]
+const BASE_SUPERFRAME_DURATION_SYMBOLS: u32 = 960;
+/// Symbols in a energy detect iteration
+#[cfg(feature = "energy-detect")]
+const ED_ITERATION_SYMBOLS: u32 = 8;
+/// Largest number of energy detect iterations
+#[cfg(feature = "energy-detect")]
+const ED_ITERATIONS_MAX: u32 = 0x10_0000;
+
+/// Duration of a energy detect scan on a channel
+#[cfg(feature = "energy-detect")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScanDuration {
+    /// aBaseSuperframeDuration × (2^n + 1) symbols, the scan duration of the
+    /// MLME-SCAN primitive. The radio limits n to 0 to 13
+    Exponent(u8),
+    /// Number of energy detect iterations of 8 symbols, 1 to 0x100000
+    Iterations(u32),
+}
+
+#[cfg(feature = "energy-detect")]
+impl ScanDuration {
+    /// Number of energy detect iterations, or None if out of range
+    pub fn iterations(&self) -> Option<u32> {
+        let iterations = match *self {
+            ScanDuration::Exponent(exponent) if exponent <= 14 => {
+                BASE_SUPERFRAME_DURATION_SYMBOLS * ((1 << exponent) + 1) / ED_ITERATION_SYMBOLS
+            }
+            ScanDuration::Exponent(_) => return None,
+            ScanDuration::Iterations(iterations) => iterations,
+        };
+        if iterations > 0 && iterations <= ED_ITERATIONS_MAX {
+            Some(iterations)
+        } else {
+            None
+        }
+    }
+}
+
+/// Operations of a IEEE 802.15.4 radio
+pub trait Ieee802154Phy {
+    /// Set the channel, 11 to 26
+    ///
+    /// Returns `Error::InvalidChannel` if the channel is not a 802.15.4
+    /// channel.
+    fn set_channel(&mut self, channel: u8) -> Result<(), Error>;
+
+    /// Get the configured channel
+    fn get_channel(&mut self) -> u8;
+
+    /// Busy sending
+    fn is_tx_busy(&self) -> bool;
+
+    /// Configure transmission power in dBm
+    ///
+    /// Returns `Error::InvalidPower` if the radio does not support the power
+    /// level.
+    fn set_transmission_power(&mut self, power: i8) -> Result<(), Error>;
+
+    /// Queue a transmission of `data`, without the PHR and FCS
+    ///
+    /// With `cca` the transmission is preceded by a clear channel assessment,
+    /// a busy channel is reported by the next call to `receive_slice`.
+    ///
+    /// # Return
+    ///
+    /// Returns the number of bytes queued for transmission. Frames refused by
+    /// `check_frame_length` return `Error::InvalidLength`.
+    ///
+    fn transmit(&mut self, data: &[u8], cca: bool) -> Result<usize, Error>;
+
+    /// Handle radio events and read received data into `buffer`
+    ///
+    /// Returns `Error::BufferTooSmall`, and drops the frame, if the received
+    /// packet does not fit in `buffer`.
+    ///
+    /// # Return
+    ///
+    /// Returns the number of bytes received, or zero if no data could be
+    /// received.
+    ///
+    fn receive_slice(&mut self, buffer: &mut [u8]) -> Result<usize, Error>;
+
+    /// Start a energy detect query on the current channel, for `duration`
+    ///
+    /// # Return
+    ///
+    /// Returns true if the energy detection query could be started.
+    ///
+    #[cfg(feature = "energy-detect")]
+    fn start_energy_detect(&mut self, duration: ScanDuration) -> bool;
+
+    /// Energy detect result
+    ///
+    /// # Return
+    ///
+    /// Returns the energy level, or None.
+    ///
+    #[cfg(feature = "energy-detect")]
+    fn report_energy_detect(&mut self) -> Option<u8>;
+
+    /// Start a clear channel assessment on the current channel
+    fn start_cca(&mut self);
+
+    /// Clear channel assessment result, with the measured energy level
+    ///
+    /// # Return
+    ///
+    /// Returns the result, or None if the assessment is not done.
+    ///
+    fn report_cca(&mut self) -> Option<CcaResult>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_length_boundaries() {
+        assert_eq!(check_frame_length(0), Err(Error::InvalidLength));
+        assert_eq!(check_frame_length(1), Ok(()));
+        assert_eq!(check_frame_length(125), Ok(()));
+        assert_eq!(check_frame_length(126), Err(Error::InvalidLength));
+        assert_eq!(check_frame_length(127), Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn received_length_boundaries() {
+        // PHR of a frame with `payload` octets and FCS
+        let phr = |payload: u8| payload + FCS_LENGTH as u8;
+        assert_eq!(check_received_length(0, 129), Err(Error::InvalidLength));
+        assert_eq!(check_received_length(1, 129), Err(Error::InvalidLength));
+        assert_eq!(check_received_length(phr(0), 129), Ok(2));
+        assert_eq!(check_received_length(phr(1), 129), Ok(3));
+        assert_eq!(check_received_length(phr(125), 129), Ok(127));
+        // Reserved lengths
+        assert_eq!(
+            check_received_length(phr(126), 129),
+            Err(Error::InvalidLength)
+        );
+        assert_eq!(
+            check_received_length(phr(127), 129),
+            Err(Error::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn received_length_larger_than_buffer() {
+        assert_eq!(check_received_length(127, 128), Ok(127));
+        assert_eq!(check_received_length(127, 127), Err(Error::BufferTooSmall));
+        assert_eq!(check_received_length(3, 4), Ok(3));
+        assert_eq!(check_received_length(3, 3), Err(Error::BufferTooSmall));
+        assert_eq!(check_received_length(3, 0), Err(Error::BufferTooSmall));
+    }
+}
@@ -0,0 +1,368 @@
+//! # Packet buffer pool
+//!
+//! A fixed capacity pool of packet buffers. Buffers are handed out as
+//! `PacketHandle`s which return the buffer to the pool when dropped, so the
+//! ownership of every buffer is explicit. The radio receive queue stores
+//! frames in buffers from a pool and never overwrites a buffer the
+//! application still holds.
+//!
+//! A pool holds at most 32 packets. Allocation and release are lock-free and
+//! can be done from interrupt context.
+//!
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::phy::MAX_PSDU_LENGTH;
+
+/// A frame stored in the pool
+pub struct Packet {
+    length: u8,
+    lqi: u8,
+    data: [u8; MAX_PSDU_LENGTH],
+}
+
+impl Packet {
+    const fn new() -> Self {
+        Self {
+            length: 0,
+            lqi: 0,
+            data: [0u8; MAX_PSDU_LENGTH],
+        }
+    }
+
+    /// Frame data
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.length as usize]
+    }
+
+    /// Mutable frame data
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data[..self.length as usize]
+    }
+
+    /// Replace the frame data
+    ///
+    /// # Return
+    ///
+    /// Returns false if the data does not fit.
+    ///
+    pub fn set_data(&mut self, data: &[u8]) -> bool {
+        if data.len() > MAX_PSDU_LENGTH {
+            return false;
+        }
+        self.data[..data.len()].copy_from_slice(data);
+        self.length = data.len() as u8;
+        true
+    }
+
+    /// Frame length
+    pub fn len(&self) -> usize {
+        self.length as usize
+    }
+
+    /// The packet holds no data
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Link quality indicator of a received frame
+    pub fn lqi(&self) -> u8 {
+        self.lqi
+    }
+
+    /// Set the link quality indicator
+    pub fn set_lqi(&mut self, lqi: u8) {
+        self.lqi = lqi;
+    }
+}
+
+/// Pool of `N` packets, `N` can be at most 32
+pub struct PacketPool<const N: usize> {
+    used: AtomicU32,
+    packets: [UnsafeCell<Packet>; N],
+}
+
+// Packets are only accessed through handles, which are unique
+unsafe impl<const N: usize> Sync for PacketPool<N> {}
+
+impl<const N: usize> PacketPool<N> {
+    /// Create a pool with all packets available
+    pub const fn new() -> Self {
+        assert!(N <= 32);
+        Self {
+            used: AtomicU32::new(0),
+            packets: [const { UnsafeCell::new(Packet::new()) }; N],
+        }
+    }
+
+    /// Get a reference to the pool, without the capacity in the type
+    pub fn pool(&self) -> Pool<'_> {
+        Pool {
+            used: &self.used,
+            packets: &self.packets,
+        }
+    }
+
+    /// Take a packet from the pool
+    pub fn allocate(&self) -> Option<PacketHandle<'_>> {
+        self.pool().allocate()
+    }
+
+    /// Number of packets available
+    pub fn available(&self) -> usize {
+        self.pool().available()
+    }
+}
+
+impl<const N: usize> Default for PacketPool<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reference to a packet pool
+#[derive(Clone, Copy)]
+pub struct Pool<'a> {
+    used: &'a AtomicU32,
+    packets: &'a [UnsafeCell<Packet>],
+}
+
+// SAFETY: A packet slot is only accessed through the `PacketHandle` which
+// claimed it by setting its bit in `used` with a atomic compare-exchange, and
+// the bit is only cleared when that handle is dropped. At any time each slot
+// has a single owner, moving the pool reference to another context only
+// moves the right to claim free slots.
+unsafe impl Send for Pool<'_> {}
+
+impl<'a> Pool<'a> {
+    fn mask(&self) -> u32 {
+        if self.packets.len() >= 32 {
+            u32::MAX
+        } else {
+            (1 << self.packets.len()) - 1
+        }
+    }
+
+    /// Take a packet from the pool
+    ///
+    /// The packet is empty.
+    pub fn allocate(&self) -> Option<PacketHandle<'a>> {
+        let mut used = self.used.load(Ordering::Acquire);
+        loop {
+            let free = !used & self.mask();
+            if free == 0 {
+                return None;
+            }
+            let index = free.trailing_zeros() as usize;
+            match self.used.compare_exchange_weak(
+                used,
+                used | 1 << index,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    let mut handle = PacketHandle {
+                        used: self.used,
+                        index: index as u8,
+                        packet: &self.packets[index],
+                    };
+                    handle.length = 0;
+                    handle.lqi = 0;
+                    return Some(handle);
+                }
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    /// Number of packets available
+    pub fn available(&self) -> usize {
+        (!self.used.load(Ordering::Relaxed) & self.mask()).count_ones() as usize
+    }
+}
+
+/// Exclusive handle to a packet in a pool
+///
+/// The packet is returned to the pool when the handle is dropped.
+pub struct PacketHandle<'a> {
+    used: &'a AtomicU32,
+    index: u8,
+    packet: &'a UnsafeCell<Packet>,
+}
+
+// The handle has exclusive access to the packet
+unsafe impl<'a> Send for PacketHandle<'a> {}
+
+impl<'a> Deref for PacketHandle<'a> {
+    type Target = Packet;
+
+    fn deref(&self) -> &Packet {
+        unsafe { &*self.packet.get() }
+    }
+}
+
+impl<'a> DerefMut for PacketHandle<'a> {
+    fn deref_mut(&mut self) -> &mut Packet {
+        unsafe { &mut *self.packet.get() }
+    }
+}
+
+impl<'a> Drop for PacketHandle<'a> {
+    fn drop(&mut self) {
+        self.used.fetch_and(!(1 << self.index), Ordering::Release);
+    }
+}
+
+/// First in, first out queue of up to `N` packet handles
+pub struct PacketQueue<'a, const N: usize> {
+    handles: [Option<PacketHandle<'a>>; N],
+    head: usize,
+    count: usize,
+}
+
+impl<'a, const N: usize> PacketQueue<'a, N> {
+    /// Create a empty queue
+    pub const fn new() -> Self {
+        Self {
+            handles: [const { None }; N],
+            head: 0,
+            count: 0,
+        }
+    }
+
+    /// Add a packet to the end of the queue
+    ///
+    /// # Return
+    ///
+    /// Returns the packet back if the queue is full.
+    ///
+    pub fn push(&mut self, packet: PacketHandle<'a>) -> Result<(), PacketHandle<'a>> {
+        if self.count == N {
+            return Err(packet);
+        }
+        self.handles[(self.head + self.count) % N] = Some(packet);
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Take the packet at the front of the queue
+    pub fn pop(&mut self) -> Option<PacketHandle<'a>> {
+        if self.count == 0 {
+            return None;
+        }
+        let packet = self.handles[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.count -= 1;
+        packet
+    }
+
+    /// Number of packets in the queue
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// The queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The queue is full
+    pub fn is_full(&self) -> bool {
+        self.count == N
+    }
+}
+
+impl<'a, const N: usize> Default for PacketQueue<'a, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocate_until_exhausted() {
+        let pool = PacketPool::<3>::new();
+        let a = pool.allocate().unwrap();
+        let b = pool.allocate().unwrap();
+        let c = pool.allocate().unwrap();
+        assert_eq!(pool.available(), 0);
+        assert!(pool.allocate().is_none());
+        drop(b);
+        assert_eq!(pool.available(), 1);
+        let d = pool.allocate().unwrap();
+        assert!(pool.allocate().is_none());
+        drop((a, c, d));
+        assert_eq!(pool.available(), 3);
+    }
+
+    #[test]
+    fn full_pool() {
+        let pool = PacketPool::<32>::new();
+        let handles: [PacketHandle; 32] = core::array::from_fn(|_| pool.allocate().unwrap());
+        assert!(pool.allocate().is_none());
+        drop(handles);
+        assert_eq!(pool.available(), 32);
+    }
+
+    #[test]
+    fn allocated_packet_is_empty() {
+        let pool = PacketPool::<1>::new();
+        let mut packet = pool.allocate().unwrap();
+        assert!(packet.set_data(&[1, 2, 3]));
+        packet.set_lqi(200);
+        drop(packet);
+        let packet = pool.allocate().unwrap();
+        assert!(packet.is_empty());
+        assert_eq!(packet.lqi(), 0);
+    }
+
+    #[test]
+    fn set_data_limit() {
+        let pool = PacketPool::<1>::new();
+        let mut packet = pool.allocate().unwrap();
+        assert!(packet.set_data(&[0xa5; MAX_PSDU_LENGTH]));
+        assert_eq!(packet.len(), MAX_PSDU_LENGTH);
+        assert!(!packet.set_data(&[0; MAX_PSDU_LENGTH + 1]));
+        assert_eq!(packet.data(), &[0xa5; MAX_PSDU_LENGTH][..]);
+    }
+
+    #[test]
+    fn queue_order() {
+        let pool = PacketPool::<4>::new();
+        let mut queue = PacketQueue::<3>::new();
+        for n in 0..3 {
+            let mut packet = pool.allocate().unwrap();
+            packet.set_data(&[n]);
+            assert!(queue.push(packet).is_ok());
+        }
+        assert!(queue.is_full());
+        let rejected = queue.push(pool.allocate().unwrap()).unwrap_err();
+        drop(rejected);
+        assert_eq!(queue.pop().unwrap().data(), &[0]);
+        let mut packet = pool.allocate().unwrap();
+        packet.set_data(&[3]);
+        assert!(queue.push(packet).is_ok());
+        for n in 1..4 {
+            assert_eq!(queue.pop().unwrap().data(), &[n]);
+        }
+        assert!(queue.pop().is_none());
+        assert!(queue.is_empty());
+        assert_eq!(pool.available(), 4);
+    }
+
+    #[test]
+    fn dropped_queue_returns_packets() {
+        let pool = PacketPool::<2>::new();
+        let mut queue = PacketQueue::<2>::new();
+        assert!(queue.push(pool.allocate().unwrap()).is_ok());
+        assert!(queue.push(pool.allocate().unwrap()).is_ok());
+        assert_eq!(pool.available(), 0);
+        drop(queue);
+        assert_eq!(pool.available(), 2);
+    }
+}
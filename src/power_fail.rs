@@ -0,0 +1,118 @@
+//! # Power-failure warning
+//!
+//! The power-failure comparator (POFCON) of the POWER peripheral signals
+//! when the supply voltage falls below a threshold. The warning is used to
+//! save volatile state, such as security frame counters, to flash before a
+//! brown-out reset.
+//!
+//! The time left between the warning and the reset depends on the supply
+//! decoupling, the save should only write a few words to already erased
+//! flash. Erasing a flash page takes far too long.
+//!
+
+#[cfg(feature = "device")]
+use crate::pac::POWER;
+
+/// Power-failure comparator threshold
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Threshold {
+    /// 1.7 V
+    V17,
+    /// 1.8 V
+    V18,
+    /// 1.9 V
+    V19,
+    /// 2.0 V
+    V20,
+    /// 2.1 V
+    V21,
+    /// 2.2 V
+    V22,
+    /// 2.3 V
+    V23,
+    /// 2.4 V
+    V24,
+    /// 2.5 V
+    V25,
+    /// 2.6 V
+    V26,
+    /// 2.7 V
+    V27,
+    /// 2.8 V
+    V28,
+}
+
+/// State saved on a power-failure warning
+pub trait EmergencySave {
+    /// Save the state, called from the POWER_CLOCK interrupt
+    fn save(&mut self);
+}
+
+/// Power-failure warning using the POFCON comparator
+#[cfg(feature = "device")]
+pub struct PowerFailWarning {
+    power: POWER,
+}
+
+#[cfg(feature = "device")]
+impl PowerFailWarning {
+    /// Enable the comparator with the given threshold and the POFWARN
+    /// interrupt
+    pub fn new(power: POWER, threshold: Threshold) -> Self {
+        power.pofcon.write(|w| {
+            let w = w.pof().enabled();
+            match threshold {
+                Threshold::V17 => w.threshold().v17(),
+                Threshold::V18 => w.threshold().v18(),
+                Threshold::V19 => w.threshold().v19(),
+                Threshold::V20 => w.threshold().v20(),
+                Threshold::V21 => w.threshold().v21(),
+                Threshold::V22 => w.threshold().v22(),
+                Threshold::V23 => w.threshold().v23(),
+                Threshold::V24 => w.threshold().v24(),
+                Threshold::V25 => w.threshold().v25(),
+                Threshold::V26 => w.threshold().v26(),
+                Threshold::V27 => w.threshold().v27(),
+                Threshold::V28 => w.threshold().v28(),
+            }
+        });
+        power.events_pofwarn.reset();
+        power.intenset.write(|w| w.pofwarn().set());
+        Self { power }
+    }
+
+    /// Check if the supply has fallen below the threshold
+    pub fn is_warning(&self) -> bool {
+        self.power
+            .events_pofwarn
+            .read()
+            .events_pofwarn()
+            .bit_is_set()
+    }
+
+    /// Handle the POFWARN event, call this from the POWER_CLOCK interrupt
+    ///
+    /// The state is saved once for each warning.
+    ///
+    /// # Return
+    ///
+    /// Returns true if the state was saved.
+    ///
+    pub fn handle<S: EmergencySave>(&mut self, state: &mut S) -> bool {
+        if self.is_warning() {
+            self.power.events_pofwarn.reset();
+            state.save();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Disable the comparator and release the POWER peripheral
+    pub fn free(self) -> POWER {
+        self.power.intenclr.write(|w| w.pofwarn().clear());
+        self.power.pofcon.write(|w| w.pof().disabled());
+        self.power
+    }
+}
@@ -0,0 +1,342 @@
+//! # QSPI external flash
+//!
+//! Driver for a NOR flash on the QSPI peripheral of the nRF52840, usable as
+//! a `WordStorage` backend with more capacity than the internal flash, for
+//! example for capture rings or larger network databases.
+//!
+//! The flash is accessed with the standard single-bit commands, fast read
+//! (0x0b) and page program (0x02), with 24-bit addresses, which all common
+//! serial NOR flashes support without configuration. The QSPI peripheral
+//! sends write enable before each program and erase, and waits for the flash
+//! to become ready. Storage pages are the 4 KiB sectors of the flash.
+//!
+//! Transfers are blocking. EasyDMA requires word aligned addresses and
+//! lengths, unaligned transfers are split through a buffer on the stack.
+//!
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use crate::pac::QSPI;
+use crate::storage::{WordStorage, ERASED};
+
+/// Sector size in octets, the smallest erasable unit
+pub const SECTOR_SIZE: u32 = 4096;
+/// Page size in octets, the largest single program operation
+pub const PAGE_SIZE: u32 = 256;
+/// Largest flash with 24-bit addresses, 16 MiB
+pub const MAX_SIZE: u32 = 1 << 24;
+
+/// Words in the transfer buffer, one flash page
+const BUFFER_WORDS: usize = (PAGE_SIZE / 4) as usize;
+/// PSEL value of a disconnected pin
+const PIN_DISCONNECTED: u32 = 0xffff_ffff;
+
+/// Errors returned by the QSPI flash
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum QspiError {
+    /// The address or length is outside the flash
+    OutOfBounds,
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for QspiError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            QspiError::OutOfBounds => f.write_str("outside flash"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::error::Error for QspiError {}
+
+/// QSPI pins, as PSEL values, `port << 5 | pin`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct QspiPins {
+    /// Serial clock
+    pub sck: u32,
+    /// Chip select
+    pub csn: u32,
+    /// Data out, IO0
+    pub io0: u32,
+    /// Data in, IO1
+    pub io1: u32,
+    /// Write protect, IO2, or None if not connected
+    pub io2: Option<u32>,
+    /// Hold, IO3, or None if not connected
+    pub io3: Option<u32>,
+}
+
+/// QSPI flash configuration
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct QspiConfig {
+    /// Pins
+    pub pins: QspiPins,
+    /// Flash size in octets, a multiple of `SECTOR_SIZE`, at most `MAX_SIZE`
+    pub size: u32,
+    /// Clock divider, the clock is 32 MHz / (`divider` + 1), 0 to 15
+    pub divider: u8,
+}
+
+impl QspiConfig {
+    /// Check that the size and the divider are within range
+    pub fn is_valid(&self) -> bool {
+        self.size > 0
+            && self.size <= MAX_SIZE
+            && self.size.is_multiple_of(SECTOR_SIZE)
+            && self.divider < 16
+    }
+}
+
+/// NOR flash on the QSPI peripheral
+pub struct Qspi {
+    qspi: QSPI,
+    size: u32,
+}
+
+impl Qspi {
+    /// Configure and activate the QSPI peripheral
+    ///
+    /// # Return
+    ///
+    /// Returns None if the configuration is not valid.
+    ///
+    pub fn new(qspi: QSPI, config: QspiConfig) -> Option<Self> {
+        if !config.is_valid() {
+            return None;
+        }
+        let pins = config.pins;
+        qspi.psel.sck.write(|w| unsafe { w.bits(pins.sck) });
+        qspi.psel.csn.write(|w| unsafe { w.bits(pins.csn) });
+        qspi.psel.io0.write(|w| unsafe { w.bits(pins.io0) });
+        qspi.psel.io1.write(|w| unsafe { w.bits(pins.io1) });
+        qspi.psel
+            .io2
+            .write(|w| unsafe { w.bits(pins.io2.unwrap_or(PIN_DISCONNECTED)) });
+        qspi.psel
+            .io3
+            .write(|w| unsafe { w.bits(pins.io3.unwrap_or(PIN_DISCONNECTED)) });
+        qspi.ifconfig0.write(|w| {
+            w.readoc()
+                .fastread()
+                .writeoc()
+                .pp()
+                .addrmode()
+                ._24bit()
+                .dpmenable()
+                .disable()
+                .ppsize()
+                ._256bytes()
+        });
+        qspi.ifconfig1.write(|w| unsafe {
+            w.sckdelay()
+                .bits(1)
+                .dpmen()
+                .exit()
+                .spimode()
+                .mode0()
+                .sckfreq()
+                .bits(config.divider)
+        });
+        qspi.intenclr.write(|w| w.ready().clear());
+        qspi.enable.write(|w| w.enable().enabled());
+        let flash = Self {
+            qspi,
+            size: config.size,
+        };
+        flash.qspi.events_ready.reset();
+        flash
+            .qspi
+            .tasks_activate
+            .write(|w| w.tasks_activate().set_bit());
+        flash.wait_ready();
+        Some(flash)
+    }
+
+    /// Deactivate the QSPI peripheral and release it
+    pub fn free(self) -> QSPI {
+        self.qspi.events_ready.reset();
+        self.qspi
+            .tasks_deactivate
+            .write(|w| w.tasks_deactivate().set_bit());
+        self.wait_ready();
+        self.qspi.enable.write(|w| w.enable().disabled());
+        self.qspi
+    }
+
+    /// Flash size in octets
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Read `buffer.len()` octets starting at `address`
+    pub fn read(&self, address: u32, buffer: &mut [u8]) -> Result<(), QspiError> {
+        self.check_bounds(address, buffer.len())?;
+        let mut words = [0u32; BUFFER_WORDS];
+        let mut address = address;
+        let mut buffer = buffer;
+        while !buffer.is_empty() {
+            // Read whole words from a aligned address
+            let offset = (address % 4) as usize;
+            let aligned = address - offset as u32;
+            let length = (offset + buffer.len()).min(PAGE_SIZE as usize);
+            let count = (length + 3) & !3;
+            self.transfer_read(aligned, &mut words[..count / 4]);
+            let octets = words_as_octets(&words[..count / 4]);
+            let (chunk, rest) = buffer.split_at_mut(length - offset);
+            chunk.copy_from_slice(&octets[offset..length]);
+            address += chunk.len() as u32;
+            buffer = rest;
+        }
+        Ok(())
+    }
+
+    /// Program `data` starting at `address`
+    ///
+    /// Programming can only clear bits, the area shall have been erased.
+    /// Writes are split at page boundaries.
+    pub fn write(&mut self, address: u32, data: &[u8]) -> Result<(), QspiError> {
+        self.check_bounds(address, data.len())?;
+        let mut words = [0u32; BUFFER_WORDS];
+        let mut address = address;
+        let mut data = data;
+        while !data.is_empty() {
+            // Pad to whole words with erased octets, which program nothing
+            let offset = (address % 4) as usize;
+            let aligned = address - offset as u32;
+            let page_left = (PAGE_SIZE - aligned % PAGE_SIZE) as usize;
+            let length = (offset + data.len()).min(page_left);
+            let count = (length + 3) & !3;
+            words[..count / 4]
+                .iter_mut()
+                .for_each(|word| *word = ERASED);
+            let (chunk, rest) = data.split_at(length - offset);
+            words_as_octets_mut(&mut words[..count / 4])[offset..length].copy_from_slice(chunk);
+            self.transfer_write(aligned, &words[..count / 4]);
+            address += chunk.len() as u32;
+            data = rest;
+        }
+        Ok(())
+    }
+
+    /// Erase the 4 KiB sector containing `address`
+    pub fn erase_sector(&mut self, address: u32) -> Result<(), QspiError> {
+        self.check_bounds(address, 1)?;
+        let address = address - address % SECTOR_SIZE;
+        self.qspi.events_ready.reset();
+        self.qspi
+            .erase
+            .ptr
+            .write(|w| unsafe { w.ptr().bits(address) });
+        self.qspi.erase.len.write(|w| w.len()._4kb());
+        self.qspi
+            .tasks_erasestart
+            .write(|w| w.tasks_erasestart().set_bit());
+        self.wait_ready();
+        Ok(())
+    }
+
+    fn check_bounds(&self, address: u32, length: usize) -> Result<(), QspiError> {
+        match address.checked_add(length as u32) {
+            Some(end) if end <= self.size => Ok(()),
+            _ => Err(QspiError::OutOfBounds),
+        }
+    }
+
+    fn transfer_read(&self, address: u32, words: &mut [u32]) {
+        self.qspi.events_ready.reset();
+        self.qspi
+            .read
+            .src
+            .write(|w| unsafe { w.src().bits(address) });
+        self.qspi
+            .read
+            .dst
+            .write(|w| unsafe { w.dst().bits(words.as_mut_ptr() as u32) });
+        self.qspi
+            .read
+            .cnt
+            .write(|w| unsafe { w.cnt().bits(words.len() as u32 * 4) });
+        compiler_fence(Ordering::Release);
+        self.qspi
+            .tasks_readstart
+            .write(|w| w.tasks_readstart().set_bit());
+        self.wait_ready();
+        compiler_fence(Ordering::Acquire);
+    }
+
+    fn transfer_write(&mut self, address: u32, words: &[u32]) {
+        self.qspi.events_ready.reset();
+        self.qspi
+            .write
+            .dst
+            .write(|w| unsafe { w.dst().bits(address) });
+        self.qspi
+            .write
+            .src
+            .write(|w| unsafe { w.src().bits(words.as_ptr() as u32) });
+        self.qspi
+            .write
+            .cnt
+            .write(|w| unsafe { w.cnt().bits(words.len() as u32 * 4) });
+        compiler_fence(Ordering::Release);
+        self.qspi
+            .tasks_writestart
+            .write(|w| w.tasks_writestart().set_bit());
+        self.wait_ready();
+    }
+
+    fn wait_ready(&self) {
+        while self.qspi.events_ready.read().events_ready().bit_is_clear() {}
+        self.qspi.events_ready.reset();
+    }
+}
+
+impl WordStorage for Qspi {
+    type Error = QspiError;
+
+    fn pages(&self) -> usize {
+        (self.size / SECTOR_SIZE) as usize
+    }
+
+    fn page_words(&self) -> usize {
+        (SECTOR_SIZE / 4) as usize
+    }
+
+    fn read(&self, page: usize, index: usize) -> u32 {
+        if page >= self.pages() || index >= self.page_words() {
+            return ERASED;
+        }
+        let mut word = [0u32; 1];
+        self.transfer_read(page as u32 * SECTOR_SIZE + index as u32 * 4, &mut word);
+        word[0]
+    }
+
+    fn write(&mut self, page: usize, index: usize, value: u32) -> Result<(), Self::Error> {
+        if page >= self.pages() || index >= self.page_words() {
+            return Err(QspiError::OutOfBounds);
+        }
+        self.transfer_write(page as u32 * SECTOR_SIZE + index as u32 * 4, &[value]);
+        Ok(())
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), Self::Error> {
+        if page >= self.pages() {
+            return Err(QspiError::OutOfBounds);
+        }
+        self.erase_sector(page as u32 * SECTOR_SIZE)
+    }
+}
+
+/// View words as octets, in memory order
+fn words_as_octets(words: &[u32]) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 4) }
+}
+
+/// View words as mutable octets, in memory order
+fn words_as_octets_mut(words: &mut [u32]) -> &mut [u8] {
+    unsafe { core::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, words.len() * 4) }
+}
@@ -26,32 +26,90 @@
 
 use core::sync::atomic::{compiler_fence, Ordering};
 
+use crate::event_log::Event;
 use crate::pac::{radio, RADIO};
+use crate::pool::{PacketHandle, PacketPool, PacketQueue, Pool};
+use crate::reset::{DriverState, StateRecord};
+use crate::statistics::Statistics;
+
+#[cfg(feature = "bcmatch")]
+mod ack;
+mod builder;
+mod chain;
+mod config;
+mod header;
+mod lbt;
+mod phy;
+#[cfg(any(feature = "bcmatch", feature = "tsch"))]
+mod timer;
+#[cfg(feature = "bcmatch")]
+pub use ack::AckResources;
+pub use builder::{build_ack, Beacon, EnhancedAck, HeaderIe};
+pub use chain::PpiChain;
+pub use config::{CcaConfig, CcaMode, RadioConfig};
+pub use header::{Address, BROADCAST};
+pub use lbt::{LbtConfig, Region};
+#[cfg(any(feature = "bcmatch", feature = "tsch"))]
+pub use timer::RadioTimer;
+
+#[cfg(feature = "energy-detect")]
+pub use crate::phy::ScanDuration;
+use crate::phy::{check_frame_length, check_received_length, FCS_LENGTH};
+pub(crate) use crate::phy::{is_valid_channel, is_valid_transmission_power};
+pub use crate::phy::{CcaResult, Error};
+
+#[cfg(feature = "smoltcp")]
+pub mod device;
+#[cfg(feature = "frames")]
+pub mod frame;
+#[cfg(feature = "radio-hal")]
+pub mod hal;
+#[cfg(feature = "trace")]
+mod trace;
+#[cfg(feature = "tsch")]
+mod tsch;
+#[cfg(feature = "trace")]
+pub use trace::{FrameMetadata, TraceHooks};
+#[cfg(feature = "tsch")]
+pub use tsch::{Link, LinkKind, Slotframe, Timeslot, TimeslotTemplate, TschConfig, TschResources};
+
+/// Transient RADIO states, with the names of the nRF52840 and nRF52833 PACs
+///
+/// The micro:bit PAC names the states without underscores.
+pub(crate) mod state {
+    #[cfg(feature = "microbit")]
+    pub(crate) use crate::pac::radio::state::STATE_A::{
+        RXDISABLE as RX_DISABLE, RXIDLE as RX_IDLE, RXRU as RX_RU, TXDISABLE as TX_DISABLE,
+        TXIDLE as TX_IDLE, TXRU as TX_RU,
+    };
+    #[cfg(not(feature = "microbit"))]
+    pub(crate) use crate::pac::radio::state::STATE_A::{
+        RX_DISABLE, RX_IDLE, RX_RU, TX_DISABLE, TX_IDLE, TX_RU,
+    };
+}
+
+/// Number of configurable PPI channels
+const PPI_CHANNELS: u8 = 20;
+/// Number of PPI channel groups
+const PPI_GROUPS: u8 = 6;
+
+/// Check that the PPI channels and channel groups exist
+fn is_valid_ppi(channels: &[u8], groups: &[u8]) -> bool {
+    channels.iter().all(|channel| *channel < PPI_CHANNELS)
+        && groups.iter().all(|group| *group < PPI_GROUPS)
+}
 
 /// RX-TX turn-around time in symbols
 const TURNAROUND_TIME_SYMBOLS: u32 = 12;
 
-/// Back-off time period in symbols
-const BACKOFF_PERIOD_SYMBOLS: u32 = 20;
-
-/// Number of short interframe spacing (SIFS) symbols
-const SIFS_SYMBOLS: u32 = 12;
 /// Number of acknowledge interframe spacing (AIFS) symbols
 const AIFS_SYMBOLS: u32 = TURNAROUND_TIME_SYMBOLS;
-/// Number of long interframe spacing (LIFS) symbols
-const LIFS_SYMBOLS: u32 = 40;
 
 /// Microseconds (μs) per symbol
 const MICROSECONDS_PER_SYMBOL: u32 = 16;
 
-/// Backoff period in microseconds
-const BACKOFF_PERIOD_MICROSECONDS: u32 = MICROSECONDS_PER_SYMBOL * BACKOFF_PERIOD_SYMBOLS;
 /// Acknowledge interframe spacing (AIFS) in microseconds
 const AIFS_MICROSECONDS: u32 = MICROSECONDS_PER_SYMBOL * AIFS_SYMBOLS;
-/// Short interframe spacing (SIFS) in microseconds
-const SIFS_MICROSECONDS: u32 = MICROSECONDS_PER_SYMBOL * SIFS_SYMBOLS;
-/// Long interframe spacing (LIFS) in microseconds
-const LIFS_MICROSECONDS: u32 = MICROSECONDS_PER_SYMBOL * LIFS_SYMBOLS;
 
 /// Maximum length of a 802.15.4 package
 const MAX_PACKET_LENGHT_REG: u8 = 129;
@@ -66,9 +124,147 @@ const CCA_CORR_THRESHOLD_DEFAULT: u8 = 20;
 const CCA_CORR_LIMIT_DEFAULT: u8 = 2;
 const SFD_DEFAULT: u8 = 0xA7;
 const MHMU_MASK: u32 = 0xff0_00700;
+/// Offset between the hardware energy level and dBm
+const ED_RSSIOFFS: i16 = -92;
+
+/// Number of polls waiting for a RSSI sample
+const RSSI_ATTEMPTS: u32 = 100;
+
+/// Number of STATE register reads while waiting for DISABLED in `recover`
+const RECOVER_DISABLE_ATTEMPTS: u32 = 10_000;
+
+/// Default number of received frames the receive queue can hold
+pub const RX_QUEUE_LENGTH: usize = 4;
 
 /// Byte array capable of holding a 802.15.4 package
-pub type PacketBuffer = [u8; MAX_PACKET_LENGHT as usize];
+pub type PacketBuffer = [u8; MAX_PACKET_LENGHT];
+
+/// Convert a energy level or LQI reported by the radio to dBm
+pub fn level_to_dbm(level: u8) -> i8 {
+    (ED_RSSIOFFS + level as i16) as i8
+}
+
+/// Convert dBm to a energy level, the inverse of `level_to_dbm`
+fn dbm_to_level(dbm: i8) -> u8 {
+    (i16::from(dbm) - ED_RSSIOFFS).clamp(0, 255) as u8
+}
+
+/// Check that a frame of `length` octets, without FCS, can be transmitted
+///
+/// Panics on a empty or too long frame with the `defensive-panic` feature.
+fn is_valid_frame_length(length: usize) -> bool {
+    let valid = check_frame_length(length).is_ok();
+    if !valid && cfg!(feature = "defensive-panic") {
+        panic!("Bad frame length");
+    }
+    valid
+}
+
+/// A received frame, referring to the radio's internal buffer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReceivedFrame<'a> {
+    /// Frame without PHR and FCS
+    pub data: &'a [u8],
+    /// Link quality indicator
+    pub lqi: u8,
+}
+
+impl<'a> ReceivedFrame<'a> {
+    /// Received signal strength in dBm, estimated from the LQI
+    pub fn rssi(&self) -> i8 {
+        level_to_dbm(self.lqi)
+    }
+}
+
+/// Handling of frames to the broadcast PAN identifier or short address
+///
+/// Coordinators and end devices need different behaviour.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BroadcastPolicy {
+    /// Accept frames to the broadcast PAN identifier
+    pub accept_pan: bool,
+    /// Accept frames to the broadcast short address
+    pub accept_address: bool,
+    /// Acknowledge frames to the broadcast PAN identifier and the address of
+    /// this device
+    pub ack_pan: bool,
+    /// Acknowledge frames to the broadcast short address, the standard does
+    /// not allow this
+    pub ack_address: bool,
+}
+
+impl Default for BroadcastPolicy {
+    /// Accept all broadcasts, acknowledge frames to the broadcast PAN
+    /// identifier only
+    fn default() -> Self {
+        Self {
+            accept_pan: true,
+            accept_address: true,
+            ack_pan: true,
+            ack_address: false,
+        }
+    }
+}
+
+/// Minimum signal quality of received frames
+///
+/// Frames below either threshold are dropped by the driver.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReceiveFilter {
+    /// Minimum link quality indicator
+    pub min_lqi: u8,
+    /// Minimum received signal strength in dBm
+    pub min_rssi: i8,
+}
+
+impl ReceiveFilter {
+    /// Check if a frame with the link quality indicator passes the filter
+    pub fn accepts(&self, lqi: u8) -> bool {
+        lqi >= self.min_lqi && level_to_dbm(lqi) >= self.min_rssi
+    }
+}
+
+impl Default for ReceiveFilter {
+    /// Accept all frames
+    fn default() -> Self {
+        Self {
+            min_lqi: 0,
+            min_rssi: i8::MIN,
+        }
+    }
+}
+
+/// Values of the RADIO registers, for diagnostics
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RegisterSnapshot {
+    /// STATE
+    pub state: u32,
+    /// FREQUENCY
+    pub frequency: u32,
+    /// TXPOWER
+    pub txpower: u32,
+    /// MODE
+    pub mode: u32,
+    /// PCNF0
+    pub pcnf0: u32,
+    /// PCNF1
+    pub pcnf1: u32,
+    /// CRCCNF
+    pub crccnf: u32,
+    /// CCACTRL
+    pub ccactrl: u32,
+    /// SFD
+    pub sfd: u32,
+    /// SHORTS
+    pub shorts: u32,
+    /// INTENSET
+    pub intenset: u32,
+    /// PACKETPTR
+    pub packetptr: u32,
+}
 
 /// Clear all interrupts on the radio
 fn clear_interrupts(radio: &mut RADIO) {
@@ -80,44 +276,152 @@ fn configure_interrupts(radio: &mut RADIO) {
     clear_interrupts(radio);
     // Enable interrupts for READY, DISABLED, CCABUSY and PHYEND
     radio.intenset.write(|w| {
-        w.ready()
+        let w = w
+            .ready()
             .set()
             .disabled()
             .set()
             .ccabusy()
             .set()
             .phyend()
-            .set()
-            .bcmatch()
-            .set()
+            .set();
+        #[cfg(feature = "bcmatch")]
+        let w = w.bcmatch().set();
+        w
     });
 }
 
 /// State flag for when the radio is transmitting
 pub const STATE_SEND: u32 = 1 << 0;
+/// State flag for when the radio is acknowledging a received frame
+pub const STATE_ACK: u32 = 1 << 1;
+/// TSCH mode, the radio is only enabled in active timeslots
+pub const STATE_TSCH: u32 = 1 << 2;
+
+/// Addresses of this device, used to recognise frames addressed to it
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceAddress {
+    /// PAN identifier
+    pub pan_id: u16,
+    /// Short address
+    pub short_address: u16,
+    /// Extended address
+    pub extended_address: u64,
+}
 
-/// Errors returned by Radio
-pub enum Error {
-    /// Clear channel assesment returned that the channel is busy
-    CcaBusy,
+impl Default for DeviceAddress {
+    /// No PAN and no short address
+    fn default() -> Self {
+        Self {
+            pan_id: 0xffff,
+            short_address: 0xffff,
+            extended_address: 0,
+        }
+    }
 }
 
 /// # 802.15.4 PHY layer implementation for nRF Radio
 ///
+/// The receive queue holds up to `RX` frames, see `Radio::receive_queued`.
+///
 /// This is work in progress.
 ///
-pub struct Radio {
+pub struct Radio<const RX: usize = RX_QUEUE_LENGTH> {
     /// The nRF52 radio peripheral
     radio: RADIO,
-    /// Internal buffer
-    buffer: PacketBuffer,
+    /// Internal buffers, the radio receives into and transmits from the
+    /// active one, the other holds the frame handed out by
+    /// `receive_in_place`
+    buffers: [PacketBuffer; 2],
+    /// Index of the active buffer
+    active: usize,
     /// Internal state
     state: u32,
+    /// PPI resources for the chained transmit path
+    chain: Option<PpiChain>,
+    /// Pool providing buffers for the receive queue
+    rx_pool: Option<Pool<'static>>,
+    /// Received frames
+    rx_queue: PacketQueue<'static, RX>,
+    /// Addresses of this device
+    address: DeviceAddress,
+    /// Signal quality filter for received frames
+    filter: ReceiveFilter,
+    /// Handling of broadcast frames
+    broadcast: BroadcastPolicy,
+    /// Counters
+    statistics: Statistics,
+    /// Sequence number and destination of the last transmitted frame
+    last_transmission: Option<(u8, Address)>,
+    /// Transmission attempts of the last transmitted frame
+    attempts: u8,
+    /// Received frames lost since the last call to `rx_overflow`
+    rx_overflows: u16,
+    /// Regulatory region
+    region: Region,
+    /// Transmission power in dBm
+    transmission_power: i8,
+    /// Interrupts are enabled, otherwise the radio is polled
+    interrupts: bool,
+    /// Energy level when the last CCA before a transmission failed
+    cca_level: Option<u8>,
+    /// Record of the driver state, kept through resets
+    state_record: Option<&'static mut StateRecord>,
+    /// Called with state changes, CCA results and retransmissions
+    event_hook: Option<fn(Event)>,
+    /// Automatic acknowledgement
+    #[cfg(feature = "bcmatch")]
+    ack: Option<ack::AutoAck>,
+    /// Trace hooks
+    #[cfg(feature = "trace")]
+    hooks: TraceHooks,
+    /// Time-slotted channel hopping
+    #[cfg(feature = "tsch")]
+    tsch: Option<tsch::Tsch>,
 }
 
+// The radio is moved into interrupt handlers and RTIC resources
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<Radio>();
+};
+
 impl Radio {
     /// Initialise the radio in 802.15.4 mode
-    pub fn new(mut radio: RADIO) -> Self {
+    pub fn new(radio: RADIO) -> Self {
+        Self::new_with_config(radio, RadioConfig::default())
+    }
+
+    /// Initialise the radio in 802.15.4 mode with the given configuration
+    ///
+    /// The radio is fully configured before any interrupt is enabled.
+    pub fn new_with_config(radio: RADIO, config: RadioConfig) -> Self {
+        Self::new_sized(radio, config)
+    }
+
+    /// Initialise the radio in 802.15.4 mode
+    ///
+    /// The nrf-hal clocks serve as proof that the high frequency crystal
+    /// oscillator (HFXO) is running, which the radio requires.
+    #[cfg(feature = "nrf-hal")]
+    pub fn new_with_clocks<L, LSTAT>(
+        radio: RADIO,
+        _clocks: &crate::hal::clocks::Clocks<crate::hal::clocks::ExternalOscillator, L, LSTAT>,
+    ) -> Self {
+        Self::new(radio)
+    }
+}
+
+impl<const RX: usize> Radio<RX> {
+    /// Initialise the radio in 802.15.4 mode with the given configuration
+    /// and a receive queue of `RX` frames
+    ///
+    /// ```notrust
+    /// let radio = Radio::<8>::new_sized(peripheral, config);
+    /// ```
+    pub fn new_sized(mut radio: RADIO, config: RadioConfig) -> Self {
+        clear_interrupts(&mut radio);
         // Enable 802.15.4 mode
         radio.mode.write(|w| w.mode().ieee802154_250kbit());
         // Configure CRC skip address
@@ -126,8 +430,10 @@ impl Radio {
             .write(|w| w.len().two().skipaddr().ieee802154());
         unsafe {
             // Configure CRC polynominal and init
-            radio.crcpoly.write(|w| w.crcpoly().bits(CRC_POLYNOMIAL));
-            radio.crcinit.write(|w| w.crcinit().bits(0));
+            radio
+                .crcpoly
+                .write(|w| w.crcpoly().bits(config.crc_polynomial));
+            radio.crcinit.write(|w| w.crcinit().bits(config.crc_init));
             // Configure packet layout
             // 8-bit on air length
             // S0 length, zero bytes
@@ -165,35 +471,76 @@ impl Radio {
                     .whiteen()
                     .clear_bit()
             });
-            // Configure clear channel assessment to sane default
+            // Configure clear channel assessment
             radio.ccactrl.write(|w| {
-                w.ccamode()
-                    .ed_mode()
-                    .ccaedthres()
-                    .bits(CCA_ED_THRESHOLD_DEFAULT)
-                    .ccacorrthres()
-                    .bits(CCA_CORR_THRESHOLD_DEFAULT)
+                let w = match config.cca.mode {
+                    CcaMode::EnergyDetect => w.ccamode().ed_mode(),
+                    CcaMode::Carrier => w.ccamode().carrier_mode(),
+                    CcaMode::CarrierAndEnergyDetect => w.ccamode().carrier_and_ed_mode(),
+                    CcaMode::CarrierOrEnergyDetect => w.ccamode().carrier_or_ed_mode(),
+                    CcaMode::EnergyDetectTest => w.ccamode().ed_mode_test1(),
+                };
+                w.ccaedthres()
+                    .bits(config.cca.ed_threshold)
                     .ccacorrthres()
-                    .bits(CCA_CORR_LIMIT_DEFAULT)
+                    .bits(config.cca.corr_threshold)
+                    .ccacorrcnt()
+                    .bits(config.cca.corr_limit)
             });
             // Configure MAC header match
             radio.mhrmatchmas.write(|w| w.bits(MHMU_MASK));
             radio.mhrmatchconf.write(|w| w.bits(0));
             // Start of frame delimiter
-            radio.sfd.write(|w| w.sfd().bits(SFD_DEFAULT));
+            radio.sfd.write(|w| w.sfd().bits(config.sfd));
+            #[cfg(feature = "bcmatch")]
             radio.bcc.write(|w| w.bcc().bits(24));
         }
-        // Set transmission power to 4dBm
-        radio.txpower.write(|w| w.txpower().pos4d_bm());
-
-        // Configure interrupts
-        configure_interrupts(&mut radio);
 
-        Self {
+        let mut radio = Self {
             radio,
-            buffer: [0u8; MAX_PACKET_LENGHT],
+            buffers: [[0u8; MAX_PACKET_LENGHT]; 2],
+            active: 0,
             state: 0,
+            chain: None,
+            rx_pool: None,
+            rx_queue: PacketQueue::new(),
+            address: config.address,
+            filter: config.filter,
+            broadcast: config.broadcast,
+            statistics: Statistics::default(),
+            last_transmission: None,
+            attempts: 0,
+            rx_overflows: 0,
+            region: config.region,
+            transmission_power: 0,
+            interrupts: config.interrupts,
+            cca_level: None,
+            state_record: None,
+            event_hook: None,
+            #[cfg(feature = "bcmatch")]
+            ack: None,
+            #[cfg(feature = "trace")]
+            hooks: TraceHooks::default(),
+            #[cfg(feature = "tsch")]
+            tsch: None,
+        };
+        // The channel and power have been validated by the configuration,
+        // without a channel the reset frequency, 2402 MHz, is kept
+        if let Some(channel) = config.channel {
+            let _ = radio.set_channel(channel);
         }
+        let _ = radio.set_transmission_power(config.transmission_power);
+
+        radio.configure_interrupts();
+        radio
+    }
+
+    /// Disable the radio and release the RADIO peripheral
+    pub fn free(mut self) -> RADIO {
+        self.enter_disabled();
+        self.clear_interrupts();
+        self.radio.shorts.reset();
+        self.radio
     }
 
     fn clear_interrupts(&mut self) {
@@ -201,7 +548,94 @@ impl Radio {
     }
 
     fn configure_interrupts(&mut self) {
-        configure_interrupts(&mut self.radio);
+        if !self.interrupts {
+            self.clear_interrupts();
+        } else if self.chain.is_some() {
+            self.clear_interrupts();
+            // Enable interrupts for DISABLED and PHYEND
+            self.radio
+                .intenset
+                .write(|w| w.disabled().set().phyend().set());
+            #[cfg(feature = "bcmatch")]
+            if self.ack.is_some() {
+                self.radio.intenset.write(|w| w.bcmatch().set());
+            }
+        } else {
+            configure_interrupts(&mut self.radio);
+        }
+    }
+
+    /// Set the addresses of this device
+    pub fn set_address(&mut self, address: DeviceAddress) {
+        self.address = address;
+    }
+
+    /// Get the addresses of this device
+    pub fn address(&self) -> DeviceAddress {
+        self.address
+    }
+
+    /// Drop received frames with a signal quality below the filter
+    pub fn set_receive_filter(&mut self, filter: ReceiveFilter) {
+        self.filter = filter;
+    }
+
+    /// Get the receive filter
+    pub fn receive_filter(&self) -> ReceiveFilter {
+        self.filter
+    }
+
+    /// Radio counters
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    /// Radio counters, for recording events from the MAC layer such as
+    /// missing acknowledgements
+    pub fn statistics_mut(&mut self) -> &mut Statistics {
+        &mut self.statistics
+    }
+
+    /// Take the number of received frames lost since the last call
+    ///
+    /// Frames are lost when the receive queue or pool is full, or when a new
+    /// frame starts before the previous frame was read from the receive
+    /// buffer.
+    ///
+    /// # Return
+    ///
+    /// Returns a `Event::RxOverflow` if any frames were lost.
+    ///
+    pub fn rx_overflow(&mut self) -> Option<Event> {
+        if self.rx_overflows == 0 {
+            None
+        } else {
+            let dropped = self.rx_overflows;
+            self.rx_overflows = 0;
+            Some(Event::RxOverflow { dropped })
+        }
+    }
+
+    /// Count a received frame which was lost
+    fn record_rx_overflow(&mut self) {
+        self.statistics.rx_overflows = self.statistics.rx_overflows.wrapping_add(1);
+        self.rx_overflows = self.rx_overflows.saturating_add(1);
+        self.log_event(Event::RxOverflow { dropped: 1 });
+    }
+
+    /// Log a error reported by the radio
+    fn record_error(&self, error: Error) {
+        self.log_event(Event::Error(error.code()));
+    }
+
+    /// Set the handling of broadcast frames
+    pub fn set_broadcast_policy(&mut self, policy: BroadcastPolicy) {
+        self.broadcast = policy;
+    }
+
+    /// Get the handling of broadcast frames
+    pub fn broadcast_policy(&self) -> BroadcastPolicy {
+        self.broadcast
     }
 
     /// Configure channel to use
@@ -210,14 +644,20 @@ impl Radio {
     ///
     /// frequency = 2400 MHz + ((channel - 10) * 5 MHz)
     ///
-    pub fn set_channel(&mut self, channel: u8) {
-        if channel < 11 || channel > 26 {
-            panic!("Bad 802.15.4 channel");
+    /// Returns `Error::InvalidChannel` for other channels, or panics with the
+    /// `defensive-panic` feature.
+    pub fn set_channel(&mut self, channel: u8) -> Result<(), Error> {
+        if !is_valid_channel(channel) {
+            if cfg!(feature = "defensive-panic") {
+                panic!("Bad 802.15.4 channel");
+            }
+            return Err(Error::InvalidChannel);
         }
         let frequency_offset = (channel - 10) * 5;
         self.radio
             .frequency
             .write(|w| unsafe { w.frequency().bits(frequency_offset).map().default() });
+        Ok(())
     }
 
     /// Get the configured channel
@@ -231,10 +671,18 @@ impl Radio {
         self.state & STATE_SEND == STATE_SEND
     }
 
+    /// Configured transmission power in dBm
+    pub fn transmission_power(&self) -> i8 {
+        self.transmission_power
+    }
+
     /// Configure transmission power
     ///
     /// Valid power levels are 8-2,0,-4,-8,-12,-16,-20,-40 dBm
-    pub fn set_transmission_power(&mut self, power: i8) {
+    ///
+    /// Returns `Error::InvalidPower` for other levels, or panics with the
+    /// `defensive-panic` feature.
+    pub fn set_transmission_power(&mut self, power: i8) -> Result<(), Error> {
         match power {
             8 => self.radio.txpower.write(|w| w.txpower().pos8d_bm()),
             7 => self.radio.txpower.write(|w| w.txpower().pos7d_bm()),
@@ -250,12 +698,24 @@ impl Radio {
             -16 => self.radio.txpower.write(|w| w.txpower().neg16d_bm()),
             -20 => self.radio.txpower.write(|w| w.txpower().neg20d_bm()),
             -40 => self.radio.txpower.write(|w| w.txpower().neg40d_bm()),
-            _ => panic!("Bad transmission power value"),
+            _ => {
+                if cfg!(feature = "defensive-panic") {
+                    panic!("Bad transmission power value");
+                }
+                return Err(Error::InvalidPower);
+            }
         }
+        self.transmission_power = power;
+        // The listen-before-talk threshold follows the transmission power
+        self.apply_region();
+        Ok(())
     }
 
     // Enter the disabled state
     fn enter_disabled(&mut self) {
+        self.chain_disarm();
+        #[cfg(feature = "bcmatch")]
+        self.ack_disarm();
         if self.state() != radio::state::STATE_A::DISABLED {
             self.radio
                 .tasks_disable
@@ -275,6 +735,54 @@ impl Radio {
         self.radio.events_disabled.reset();
     }
 
+    /// Disable the radio, stop receiving
+    pub fn disable(&mut self) {
+        self.enter_disabled();
+        self.record_state(DriverState::Idle);
+    }
+
+    /// Transmit a unmodulated carrier on `channel` for a moment, the radio is
+    /// left disabled on the original channel
+    ///
+    /// Returns true if the transmitter ramped up.
+    pub(crate) fn test_carrier(&mut self, channel: u8) -> bool {
+        let restore = self.get_channel();
+        self.enter_disabled();
+        self.radio.shorts.reset();
+        if self.set_channel(channel).is_err() {
+            return false;
+        }
+        // The radio sends a carrier in TXIDLE
+        self.radio.tasks_txen.write(|w| w.tasks_txen().set_bit());
+        let ramped_up = (0..RECOVER_DISABLE_ATTEMPTS).any(|_| self.state() == state::TX_IDLE);
+        self.enter_disabled();
+        self.radio.events_ready.reset();
+        let _ = self.set_channel(restore);
+        ramped_up
+    }
+
+    /// Abort any operation and return to receive
+    ///
+    /// Used to recover when the radio does not progress. The wait for the
+    /// radio to become disabled is bounded.
+    pub fn recover(&mut self) {
+        self.chain_disarm();
+        #[cfg(feature = "bcmatch")]
+        self.ack_disarm();
+        self.radio.shorts.reset();
+        self.radio
+            .tasks_disable
+            .write(|w| w.tasks_disable().set_bit());
+        for _ in 0..RECOVER_DISABLE_ATTEMPTS {
+            if self.state() == radio::state::STATE_A::DISABLED {
+                break;
+            }
+        }
+        self.state = 0;
+        self.configure_interrupts();
+        self.receive_prepare();
+    }
+
     /// Get the radio state
     pub fn state(&mut self) -> radio::state::STATE_A {
         match self.radio.state.read().state().variant() {
@@ -283,9 +791,33 @@ impl Radio {
         }
     }
 
+    /// Read the RADIO registers
+    pub fn registers(&self) -> RegisterSnapshot {
+        RegisterSnapshot {
+            state: self.radio.state.read().bits(),
+            frequency: self.radio.frequency.read().bits(),
+            txpower: self.radio.txpower.read().bits(),
+            mode: self.radio.mode.read().bits(),
+            pcnf0: self.radio.pcnf0.read().bits(),
+            pcnf1: self.radio.pcnf1.read().bits(),
+            crccnf: self.radio.crccnf.read().bits(),
+            ccactrl: self.radio.ccactrl.read().bits(),
+            sfd: self.radio.sfd.read().bits(),
+            shorts: self.radio.shorts.read().bits(),
+            intenset: self.radio.intenset.read().bits(),
+            packetptr: self.radio.packetptr.read().bits(),
+        }
+    }
+
     /// Prepare to receive data
     pub fn receive_prepare(&mut self) {
         self.enter_disabled();
+        self.record_state(DriverState::Receiving);
+        if let Some(chain) = self.chain {
+            self.set_packet_pointer();
+            self.chain_receive(chain);
+            return;
+        }
         self.radio.shorts.reset();
         self.radio
             .shorts
@@ -328,37 +860,337 @@ impl Radio {
     /// # Return
     ///
     /// Returns the number of bytes received, or zero if no data could be received.
+    /// Returns `Error::BufferTooSmall` if the received packet does not fit in
+    /// the buffer, the frame is then dropped and counted as a overflow. A
+    /// buffer of `MAX_PACKET_LENGHT` octets holds any packet.
     ///
     pub fn receive_slice(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
-        assert!(buffer.len() >= MAX_PACKET_LENGHT);
         // PHYEND event signal
-        let length = if self.radio.events_phyend.read().events_phyend().bit_is_set() {
+        let received = match self.handle_phyend() {
+            Some(phr) => match check_received_length(phr, buffer.len()) {
+                Ok(length) if self.is_accepted(phr) => {
+                    #[cfg(feature = "trace")]
+                    self.trace_receive(length);
+                    self.record_received(length);
+                    buffer[0] = phr;
+                    buffer[1..=length].copy_from_slice(&self.buffers[self.active][1..=length]);
+                    Ok(length)
+                }
+                Err(Error::BufferTooSmall) => {
+                    self.record_error(Error::BufferTooSmall);
+                    self.record_rx_overflow();
+                    Err(Error::BufferTooSmall)
+                }
+                Err(error) => {
+                    self.record_error(error);
+                    Ok(0)
+                }
+                // Rejected by the filters
+                _ => Ok(0),
+            },
+            None => Ok(0),
+        };
+        self.handle_disabled();
+        self.handle_ready();
+        if self.handle_ccabusy() {
+            return Err(Error::CcaBusy);
+        }
+        #[cfg(feature = "bcmatch")]
+        self.handle_bcmatch();
+        received
+    }
+
+    /// Receive a frame without copying it into a buffer of the caller
+    ///
+    /// The returned frame refers to a buffer of the radio. The radio keeps
+    /// receiving after PHYEND, so it is switched to its second buffer and
+    /// the received frame stays in place until the next call.
+    ///
+    /// # Return
+    ///
+    /// Returns the received frame, or None if no frame has been received.
+    ///
+    pub fn receive_in_place(&mut self) -> Result<Option<ReceivedFrame<'_>>, Error> {
+        let phr = self.handle_phyend();
+        self.handle_disabled();
+        self.handle_ready();
+        if self.handle_ccabusy() {
+            return Err(Error::CcaBusy);
+        }
+        #[cfg(feature = "bcmatch")]
+        self.handle_bcmatch();
+        let length = match phr.map(|phr| check_received_length(phr, MAX_PACKET_LENGHT)) {
+            Some(Ok(length)) => Some(length),
+            Some(Err(error)) => {
+                self.record_error(error);
+                None
+            }
+            None => None,
+        };
+        match (phr, length) {
+            (Some(phr), Some(length)) if self.is_accepted(phr) => {
+                #[cfg(feature = "trace")]
+                self.trace_receive(length);
+                self.record_received(length);
+                let received = self.active;
+                self.active ^= 1;
+                // The radio reads the packet pointer when the next frame
+                // starts. While acknowledging, the pointer is restored when
+                // the acknowledgement is sent
+                if self.state & STATE_ACK == 0 {
+                    self.set_packet_pointer();
+                }
+                let frame = &self.buffers[received];
+                Ok(Some(ReceivedFrame {
+                    data: &frame[1..length - 1],
+                    lqi: frame[length - 1],
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Advance the radio without interrupts
+    ///
+    /// In polled mode, selected with `RadioConfig::interrupts` or
+    /// `Radio::set_interrupts`, no radio interrupt is enabled and the events
+    /// are handled by calling `poll` repeatedly. Each call handles the events
+    /// the interrupt would, the return to receive after a transmission,
+    /// busy channels and automatic acknowledgements. Poll continuously while
+    /// automatic acknowledgement is enabled, the acknowledgement is armed
+    /// while the frame is received.
+    ///
+    /// Energy detect and CCA results are read with `report_energy_detect`
+    /// and `report_cca` as usual.
+    ///
+    /// # Return
+    ///
+    /// Returns the received frame, or None if no frame has been received.
+    ///
+    pub fn poll(&mut self) -> Result<Option<ReceivedFrame<'_>>, Error> {
+        self.receive_in_place()
+    }
+
+    /// Enable or disable the radio interrupts
+    ///
+    /// With the interrupts disabled the radio is driven by `poll` and the
+    /// RADIO interrupt can be owned by other code.
+    pub fn set_interrupts(&mut self, enabled: bool) {
+        self.interrupts = enabled;
+        self.configure_interrupts();
+    }
+
+    /// Set the record of the driver state, kept through resets for the
+    /// reset diagnostics
+    pub fn set_state_record(&mut self, record: &'static mut StateRecord) {
+        self.state_record = Some(record);
+    }
+
+    /// Set the hook called with radio events, state changes, CCA results
+    /// and retransmissions
+    ///
+    /// The hook is called from the context calling the driver, often the
+    /// radio interrupt. It would typically add the event to a `EventLog`
+    /// with a timestamp.
+    pub fn set_event_hook(&mut self, hook: fn(Event)) {
+        self.event_hook = Some(hook);
+    }
+
+    fn log_event(&self, event: Event) {
+        if let Some(hook) = self.event_hook {
+            hook(event);
+        }
+    }
+
+    fn record_state(&mut self, state: DriverState) {
+        self.log_event(Event::State(state as u8));
+        if self.state_record.is_none() {
+            return;
+        }
+        let channel = self.get_channel();
+        if let Some(record) = self.state_record.as_mut() {
+            record.update(state, channel);
+        }
+    }
+
+    /// Set the pool providing buffers for the receive queue
+    pub fn set_receive_pool<const N: usize>(&mut self, pool: &'static PacketPool<N>) {
+        self.rx_pool = Some(pool.pool());
+    }
+
+    /// Handle radio events and put a received frame in the receive queue
+    ///
+    /// Call this from the radio interrupt instead of `receive`. The frame is
+    /// stored in a buffer from the receive pool, it is dropped and counted as
+    /// a overflow if the pool is exhausted or the queue is full.
+    ///
+    /// # Return
+    ///
+    /// Returns true if a frame was queued.
+    ///
+    pub fn receive_queued(&mut self) -> Result<bool, Error> {
+        let pool = self.rx_pool;
+        let packet = match self.receive_in_place()? {
+            Some(frame) => match pool.and_then(|pool| pool.allocate()) {
+                Some(mut packet) => {
+                    packet.set_data(frame.data);
+                    packet.set_lqi(frame.lqi);
+                    Some(packet)
+                }
+                None => None,
+            },
+            None => return Ok(false),
+        };
+        let queued = match packet {
+            Some(packet) => self.rx_queue.push(packet).is_ok(),
+            None => false,
+        };
+        if !queued {
+            self.record_rx_overflow();
+        }
+        Ok(queued)
+    }
+
+    /// Take the oldest frame from the receive queue
+    pub fn pop_received(&mut self) -> Option<PacketHandle<'static>> {
+        self.rx_queue.pop()
+    }
+
+    /// Process all frames in the receive queue
+    ///
+    /// Each frame is passed to `f` and then returned to the pool.
+    ///
+    /// # Return
+    ///
+    /// Returns the number of frames processed.
+    ///
+    pub fn drain(&mut self, f: &mut impl FnMut(ReceivedFrame)) -> usize {
+        let mut count = 0;
+        while let Some(packet) = self.rx_queue.pop() {
+            f(ReceivedFrame {
+                data: packet.data(),
+                lqi: packet.lqi(),
+            });
+            count += 1;
+        }
+        count
+    }
+
+    /// Queue a transmission of the packet
+    ///
+    /// See `queue_transmission` and `queue_transmission_no_cca`.
+    pub fn queue_transmission_packet(
+        &mut self,
+        packet: &PacketHandle,
+        cca: bool,
+    ) -> Result<usize, Error> {
+        if cca {
+            self.queue_transmission(packet.data())
+        } else {
+            self.queue_transmission_no_cca(packet.data())
+        }
+    }
+
+    /// Handle the PHYEND event
+    ///
+    /// Returns the PHR of the received packet, or None if no packet was
+    /// received. The packet is left in the internal buffer.
+    fn handle_phyend(&mut self) -> Option<u8> {
+        if self.radio.events_phyend.read().events_phyend().bit_is_set() {
             // PHR contains length of the packet in the low 7 bits, MSB
             // indicates if this packet is a 802.11.4 packet or not
             // 16-bit CRC has been removed, 1 octet LQI has been added to the end
-            let phr = self.buffer[0];
+            let phr = self.buffers[self.active][0];
             // Clear PHR so we do not read old data next time
-            self.buffer[0] = 0;
-            let length = if self.state & STATE_SEND == STATE_SEND {
-                0
-            } else {
-                let length = if (phr & 0x80) == 0 {
-                    (phr & 0x7f) as usize
-                } else {
-                    0
-                };
-                if length > 0 {
-                    buffer[0] = phr & 0x7f;
-                    buffer[1..=length].copy_from_slice(&self.buffer[1..=length]);
-                }
-                length
-            };
+            self.buffers[self.active][0] = 0;
             // Clear interrupt
             self.radio.events_phyend.reset();
-            length
+            // Every received frame starts with a FRAMESTART, if it was
+            // cleared when handling the previous frame, this frame started
+            // before the previous frame was read and overwrote it
+            let started = self
+                .radio
+                .events_framestart
+                .read()
+                .events_framestart()
+                .bit_is_set();
+            self.radio.events_framestart.reset();
+            #[cfg(feature = "bcmatch")]
+            self.ack_reset_stage();
+            if self.state & STATE_SEND == STATE_SEND {
+                None
+            } else {
+                if !started && self.state & STATE_ACK == 0 {
+                    self.record_rx_overflow();
+                }
+                if self.radio.crcstatus.read().crcstatus().is_crcerror() {
+                    self.statistics.crc_errors = self.statistics.crc_errors.wrapping_add(1);
+                }
+                Some(phr)
+            }
         } else {
-            0
-        };
+            None
+        }
+    }
+
+    /// Count a received frame, frames with a CRC error are only counted in
+    /// `crc_errors`
+    fn record_received(&mut self, length: usize) {
+        if self.radio.crcstatus.read().crcstatus().is_crcok() {
+            self.statistics.received = self.statistics.received.wrapping_add(1);
+        }
+        self.log_event(Event::Receive {
+            length: (length - FCS_LENGTH) as u8,
+            lqi: self.buffers[self.active][length - 1],
+        });
+    }
+
+    /// Count a transmission of the packet in the buffer
+    fn record_transmission(&mut self, length: usize) {
+        let frame = &self.buffers[self.active][1..length - 1];
+        let transmission =
+            header::destination(frame).map(|(_, destination)| (frame[2], destination));
+        let retry = transmission.is_some() && transmission == self.last_transmission;
+        self.statistics.transmitted = self.statistics.transmitted.wrapping_add(1);
+        self.log_event(Event::Transmit {
+            length: frame.len() as u8,
+        });
+        if retry {
+            self.statistics.retries = self.statistics.retries.wrapping_add(1);
+            self.attempts = self.attempts.saturating_add(1);
+            self.log_event(Event::Retransmission {
+                sequence: frame[2],
+                attempt: self.attempts,
+            });
+        } else {
+            self.attempts = 1;
+        }
+        if let Some((_, destination)) = transmission {
+            self.statistics.links.record_transmission(destination, retry);
+        }
+        self.last_transmission = transmission;
+    }
+
+    /// Check if the received packet passes the receive filter
+    fn is_accepted(&self, phr: u8) -> bool {
+        let length = (phr & 0x7f) as usize;
+        if length < 2 {
+            return length == 0;
+        }
+        if !self.filter.accepts(self.buffers[self.active][length - 1]) {
+            return false;
+        }
+        match header::destination(&self.buffers[self.active][1..length - 1]) {
+            Some((pan_id, address)) => {
+                (pan_id != BROADCAST || self.broadcast.accept_pan)
+                    && (address != Address::Short(BROADCAST) || self.broadcast.accept_address)
+            }
+            None => true,
+        }
+    }
+
+    /// Handle the DISABLED event, re-enable receive after sending
+    fn handle_disabled(&mut self) {
         if self
             .radio
             .events_disabled
@@ -367,7 +1199,18 @@ impl Radio {
             .bit_is_set()
         {
             // Errata 204: Always use DISABLE when switching from TX to RX.
-            if self.state & STATE_SEND == STATE_SEND {
+            if self.state & STATE_ACK == STATE_ACK {
+                // Acknowledgement is sequenced by PPI, resume receive when done
+                #[cfg(feature = "bcmatch")]
+                self.ack_disabled();
+            } else if self.state & STATE_TSCH == STATE_TSCH {
+                // The radio stays disabled until the next timeslot
+                self.state = STATE_TSCH;
+            } else if self.state & STATE_SEND == STATE_SEND && self.chain.is_some() {
+                // Receive has already been re-enabled by the chain
+                self.chain_complete();
+                self.state = 0;
+            } else if self.state & STATE_SEND == STATE_SEND {
                 // Re-enable receive after sending a packet
                 self.radio.shorts.reset();
                 self.radio
@@ -379,13 +1222,29 @@ impl Radio {
             // Clear interrupt
             self.radio.events_disabled.reset();
         }
+    }
+
+    /// Handle the READY event, configure packet buffer
+    fn handle_ready(&mut self) {
         if self.radio.events_ready.read().events_ready().bit_is_set() {
-            self.radio
-                .packetptr
-                .write(|w| unsafe { w.bits(self.buffer.as_ptr() as u32) });
+            // Keep the acknowledge packet in place while acknowledging
+            if self.state & STATE_ACK == 0 {
+                self.set_packet_pointer();
+            }
             // Clear interrupt
             self.radio.events_ready.reset();
         }
+    }
+
+    /// Point the radio to the active buffer
+    fn set_packet_pointer(&mut self) {
+        self.radio
+            .packetptr
+            .write(|w| unsafe { w.bits(self.buffers[self.active].as_ptr() as u32) });
+    }
+
+    /// Handle the CCABUSY event, returns true if the channel was busy
+    fn handle_ccabusy(&mut self) -> bool {
         if self
             .radio
             .events_ccabusy
@@ -393,11 +1252,31 @@ impl Radio {
             .events_ccabusy()
             .bit_is_set()
         {
-            self.receive_prepare();
+            if self.chain.is_none() {
+                // The radio remains receiving after the assessment
+                self.cca_level = Some(self.sample_energy());
+                self.receive_prepare();
+                self.state &= !STATE_SEND;
+            }
+            #[cfg(feature = "trace")]
+            self.trace_error(Error::CcaBusy);
+            self.log_event(Event::Cca { busy: true });
+            self.record_error(Error::CcaBusy);
+            self.statistics.cca_busy = self.statistics.cca_busy.wrapping_add(1);
+            if let Some((_, destination)) = self.last_transmission {
+                self.statistics.links.record_cca_deferral(destination);
+            }
             // Clear interrupt
             self.radio.events_ccabusy.reset();
-            return Err(Error::CcaBusy);
+            true
+        } else {
+            false
         }
+    }
+
+    /// Handle the BCMATCH event
+    #[cfg(feature = "bcmatch")]
+    fn handle_bcmatch(&mut self) {
         if self
             .radio
             .events_bcmatch
@@ -407,8 +1286,10 @@ impl Radio {
         {
             // Clear interrupt
             self.radio.events_bcmatch.reset();
+            if self.ack.is_some() {
+                self.ack_bcmatch();
+            }
         }
-        Ok(length)
     }
 
     /// Queue a transmission of the provided data, do not use CCA
@@ -417,17 +1298,38 @@ impl Radio {
     ///
     /// If the transmission succeeds the PHYEND event shall signal.
     ///
+    /// Refused with `Error::CcaRequired` when the regulatory region requires
+    /// listen-before-talk.
+    ///
     /// # Return
     ///
-    /// Returns the number of bytes queued for transmission, or zero if no data could be sent.
+    /// Returns the number of bytes queued for transmission. Frames longer than
+    /// 125 octets are refused with `Error::InvalidLength`, or panic with the
+    /// `defensive-panic` feature.
     ///
-    pub fn queue_transmission_no_cca(&mut self, data: &[u8]) -> usize {
+    pub fn queue_transmission_no_cca(&mut self, data: &[u8]) -> Result<usize, Error> {
+        if self.is_cca_required() {
+            return Err(Error::CcaRequired);
+        }
+        if !is_valid_frame_length(data.len()) {
+            return Err(Error::InvalidLength);
+        }
         self.enter_disabled();
         let data_length = data.len();
         let tx_length = data_length + 2; // The radio will add FCS, two octets
-        assert!(tx_length < (MAX_PACKET_LENGHT - 1) as usize);
-        self.buffer[0] = tx_length as u8;
-        self.buffer[1..(tx_length - 1)].copy_from_slice(data);
+        self.buffers[self.active][0] = tx_length as u8;
+        self.buffers[self.active][1..(tx_length - 1)].copy_from_slice(data);
+        #[cfg(feature = "trace")]
+        self.trace_transmit(tx_length);
+        self.record_transmission(tx_length);
+        if let Some(chain) = self.chain {
+            self.set_packet_pointer();
+            compiler_fence(Ordering::Release);
+            self.chain_transmit(chain, false);
+            self.state |= STATE_SEND;
+            self.record_state(DriverState::Transmitting);
+            return Ok(data_length);
+        }
         // Configure shortcuts
         //
         // The radio goes through following states when sending a 802.15.4 packet
@@ -441,7 +1343,8 @@ impl Radio {
         // Start task
         self.radio.tasks_txen.write(|w| w.tasks_txen().set_bit());
         self.state |= STATE_SEND;
-        data_length
+        self.record_state(DriverState::Transmitting);
+        Ok(data_length)
     }
 
     /// Queue a transmission of the provided data
@@ -454,15 +1357,31 @@ impl Radio {
     ///
     /// # Return
     ///
-    /// Returns the number of bytes queued for transmission, or zero if no data could be sent.
+    /// Returns the number of bytes queued for transmission. Frames longer than
+    /// 125 octets are refused with `Error::InvalidLength`, or panic with the
+    /// `defensive-panic` feature.
     ///
-    pub fn queue_transmission(&mut self, data: &[u8]) -> usize {
+    pub fn queue_transmission(&mut self, data: &[u8]) -> Result<usize, Error> {
+        if !is_valid_frame_length(data.len()) {
+            return Err(Error::InvalidLength);
+        }
         self.enter_disabled();
+        self.cca_level = None;
         let data_length = data.len();
         let tx_length = data_length + 2; // The radio will add FCS, two octets
-        assert!(tx_length < (MAX_PACKET_LENGHT - 1) as usize);
-        self.buffer[0] = tx_length as u8;
-        self.buffer[1..(tx_length - 1)].copy_from_slice(data);
+        self.buffers[self.active][0] = tx_length as u8;
+        self.buffers[self.active][1..(tx_length - 1)].copy_from_slice(data);
+        #[cfg(feature = "trace")]
+        self.trace_transmit(tx_length);
+        self.record_transmission(tx_length);
+        if let Some(chain) = self.chain {
+            self.set_packet_pointer();
+            compiler_fence(Ordering::Release);
+            self.chain_transmit(chain, true);
+            self.state |= STATE_SEND;
+            self.record_state(DriverState::Transmitting);
+            return Ok(data_length);
+        }
         // Configure shortcuts
         //
         // The radio goes through following states when sending a 802.15.4 packet
@@ -470,7 +1389,8 @@ impl Radio {
         // enable RX → ramp up RX → clear channel assessment (CCA) → CCA result
         // CCA idle → enable TX → start TX → TX → end (PHYEND) → disabled
         //
-        // CCA might end up in the event CCABUSY in which there will be no transmission
+        // CCA might end up in the event CCABUSY in which there will be no transmission,
+        // the radio remains receiving so that the energy level can be sampled
         self.radio.shorts.reset();
         self.radio.shorts.write(|w| {
             w.rxready_ccastart()
@@ -479,8 +1399,6 @@ impl Radio {
                 .enabled()
                 .txready_start()
                 .enabled()
-                .ccabusy_disable()
-                .enabled()
                 .phyend_disable()
                 .enabled()
         });
@@ -488,17 +1406,19 @@ impl Radio {
         // Start task
         self.radio.tasks_rxen.write(|w| w.tasks_rxen().set_bit());
         self.state |= STATE_SEND;
-        data_length
+        self.record_state(DriverState::Transmitting);
+        Ok(data_length)
     }
 
-    /// Start a energy detect query on the current channel
+    /// Start a energy detect query on the current channel, for `duration`
     ///
     /// # Return
     ///
     /// Returns true if the energy detection query could be started.
     ///
-    pub fn start_energy_detect(&mut self, count: u32) -> bool {
-        if count > 0 && count <= 0x10_0000 {
+    #[cfg(feature = "energy-detect")]
+    pub fn start_energy_detect(&mut self, duration: ScanDuration) -> bool {
+        if let Some(count) = duration.iterations() {
             self.enter_disabled();
             self.radio.edcnt.write(|w| unsafe { w.bits(count - 1) });
             self.radio.shorts.reset();
@@ -510,9 +1430,12 @@ impl Radio {
                 .write(|w| w.events_edend().clear_bit());
             self.clear_interrupts();
             // Enable interrupts for EDEND
-            self.radio.intenset.write(|w| w.edend().set());
+            if self.interrupts {
+                self.radio.intenset.write(|w| w.edend().set());
+            }
             // Start energy detection
             self.radio.tasks_rxen.write(|w| w.tasks_rxen().set_bit());
+            self.record_state(DriverState::EnergyDetect);
             true
         } else {
             false
@@ -525,6 +1448,7 @@ impl Radio {
     ///
     /// Returns the energy level, or None.
     ///
+    #[cfg(feature = "energy-detect")]
     pub fn report_energy_detect(&mut self) -> Option<u8> {
         if self.radio.events_edend.read().events_edend().bit_is_set() {
             self.radio.events_edend.reset();
@@ -533,9 +1457,102 @@ impl Radio {
                 .events_edend
                 .write(|w| w.events_edend().clear_bit());
             self.configure_interrupts();
+            let channel = self.get_channel();
+            self.log_event(Event::EnergyDetect { channel, level });
             Some(level)
         } else {
             None
         }
     }
+
+    /// Start a clear channel assessment on the current channel
+    ///
+    /// The CCA mode and threshold are those of the radio configuration. The
+    /// radio is disabled when the result has been reported, call
+    /// `receive_prepare` to resume receiving.
+    pub fn start_cca(&mut self) {
+        self.enter_disabled();
+        self.radio.shorts.reset();
+        self.radio.shorts.write(|w| w.rxready_ccastart().enabled());
+        self.radio.events_ccaidle.reset();
+        self.radio.events_ccabusy.reset();
+        self.clear_interrupts();
+        // Enable interrupts for CCAIDLE and CCABUSY
+        if self.interrupts {
+            self.radio
+                .intenset
+                .write(|w| w.ccaidle().set().ccabusy().set());
+        }
+        self.radio.tasks_rxen.write(|w| w.tasks_rxen().set_bit());
+        self.record_state(DriverState::Cca);
+    }
+
+    /// Clear channel assessment result
+    ///
+    /// The energy level is sampled when the result is reported.
+    ///
+    /// # Return
+    ///
+    /// Returns the result, or None if the assessment is not done.
+    ///
+    pub fn report_cca(&mut self) -> Option<CcaResult> {
+        let busy = if self
+            .radio
+            .events_ccaidle
+            .read()
+            .events_ccaidle()
+            .bit_is_set()
+        {
+            false
+        } else if self
+            .radio
+            .events_ccabusy
+            .read()
+            .events_ccabusy()
+            .bit_is_set()
+        {
+            true
+        } else {
+            return None;
+        };
+        let level = self.sample_energy();
+        self.enter_disabled();
+        self.radio.events_ccaidle.reset();
+        self.radio.events_ccabusy.reset();
+        self.configure_interrupts();
+        self.log_event(Event::Cca { busy });
+        Some(CcaResult { busy, level })
+    }
+
+    /// Energy level measured when the CCA of the last transmission found the
+    /// channel busy
+    ///
+    /// The level is sampled when the busy channel is handled, after the
+    /// assessment. It is not available for chained transmissions.
+    pub fn cca_level(&self) -> Option<u8> {
+        self.cca_level
+    }
+
+    /// Sample the energy level on the channel, the radio shall be receiving
+    fn sample_energy(&mut self) -> u8 {
+        self.radio.events_rssiend.reset();
+        self.radio
+            .tasks_rssistart
+            .write(|w| w.tasks_rssistart().set_bit());
+        for _ in 0..RSSI_ATTEMPTS {
+            if self
+                .radio
+                .events_rssiend
+                .read()
+                .events_rssiend()
+                .bit_is_set()
+            {
+                break;
+            }
+        }
+        self.radio.events_rssiend.reset();
+        // The sample is the received signal strength in -dBm
+        let rssi = self.radio.rssisample.read().rssisample().bits();
+        dbm_to_level(-(rssi as i8))
+    }
 }
@@ -0,0 +1,370 @@
+//! Automatic acknowledgement with precise AIFS timing
+//!
+//! Frames requesting acknowledgement and addressed to this device are
+//! acknowledged by the radio. The decision is made in the BCMATCH interrupt
+//! while the frame is being received, the acknowledgement transmission is
+//! then triggered by a dedicated TIMER and PPI. The timing does not depend on
+//! interrupt latency.
+//!
+//! ```notrust
+//! CRCOK → TIMER CLEAR, START
+//! PHYEND → DISABLE → DISABLED → TXEN → TXREADY
+//! TIMER COMPARE[0] (AIFS after CRCOK) → START → PHYEND → DISABLE
+//! ```
+//!
+//! | Channel | Event      | Task        | Fork            | Group |
+//! |---------|------------|-------------|-----------------|-------|
+//! | 0       | CRCOK      | TIMER CLEAR | TIMER START     | ACK   |
+//! | 1       | COMPARE[0] | START       | disable ACK     | ACK   |
+//! | 2       | DISABLED   | TXEN        | disable TXEN    | TXEN  |
+//!
+//! When the CRC check fails the timer is never started, the acknowledgement
+//! is cancelled in the DISABLED interrupt.
+//!
+
+use crate::pac::{ppi, PPI};
+
+use super::builder::build_ack;
+use super::header::{destination, destination_address_length, Address, BROADCAST};
+use super::{
+    is_valid_ppi, radio, Error, Radio, RadioTimer, AIFS_MICROSECONDS, MICROSECONDS_PER_SYMBOL,
+    STATE_ACK, STATE_SEND,
+};
+
+/// Minimum acknowledge delay in symbols, aTurnaroundTime
+const ACK_DELAY_MIN_SYMBOLS: u32 = 12;
+/// Maximum acknowledge delay in symbols, aTurnaroundTime + aUnitBackoffPeriod
+const ACK_DELAY_MAX_SYMBOLS: u32 = 32;
+
+/// Byte count for the frame control and sequence number
+const BCC_HEADER: u32 = 24;
+
+/// Acknowledge packet length, PHR, frame control, sequence number and space for FCS
+const ACK_PACKET_LENGTH: usize = 6;
+
+const FRAME_TYPE_MASK: u16 = 0x0007;
+const FRAME_TYPE_DATA: u16 = 1;
+const FRAME_TYPE_COMMAND: u16 = 3;
+const ACKNOWLEDGE_REQUEST: u16 = 1 << 5;
+
+/// Check if the frame control field requests acknowledgement
+pub(crate) fn is_ack_requested(frame_control: u16) -> bool {
+    let frame_type = frame_control & FRAME_TYPE_MASK;
+    (frame_type == FRAME_TYPE_DATA || frame_type == FRAME_TYPE_COMMAND)
+        && frame_control & ACKNOWLEDGE_REQUEST == ACKNOWLEDGE_REQUEST
+}
+
+/// PPI resources reserved for automatic acknowledgement
+///
+/// The channels and groups must not be used by anything else.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AckResources {
+    channels: [u8; 3],
+    groups: [u8; 2],
+}
+
+impl Default for AckResources {
+    /// Use the channels 11 to 13 and the groups 1 and 2
+    fn default() -> Self {
+        Self {
+            channels: [11, 12, 13],
+            groups: [1, 2],
+        }
+    }
+}
+
+const GROUP_ACK: usize = 0;
+const GROUP_TXEN: usize = 1;
+
+fn ppi() -> &'static ppi::RegisterBlock {
+    // Only the tasks of the reserved groups are accessed
+    unsafe { &*PPI::ptr() }
+}
+
+/// Automatic acknowledgement state
+pub(super) struct AutoAck {
+    resources: AckResources,
+    timer: RadioTimer,
+    /// Waiting for the destination address
+    address_stage: bool,
+    /// Set the frame pending bit in acknowledgements
+    frame_pending: bool,
+    /// Acknowledge packet
+    packet: [u8; ACK_PACKET_LENGTH],
+}
+
+impl AckResources {
+    /// Reserve three PPI channels, 0 to 19, and two PPI channel groups, 0 to
+    /// 5, the ACK group and the TXEN group
+    ///
+    /// Returns `Error::InvalidResources` if a channel or group does not
+    /// exist.
+    pub fn new(channels: [u8; 3], groups: [u8; 2]) -> Result<Self, Error> {
+        if !is_valid_ppi(&channels, &groups) {
+            return Err(Error::InvalidResources);
+        }
+        Ok(Self { channels, groups })
+    }
+
+    fn channel_mask(&self, channels: &[usize]) -> u32 {
+        channels
+            .iter()
+            .fold(0, |mask, index| mask | 1 << self.channels[*index])
+    }
+}
+
+impl AutoAck {
+    fn enable_group(&self, group: usize) {
+        ppi().tasks_chg[self.resources.groups[group] as usize]
+            .en
+            .write(|w| unsafe { w.bits(1) });
+    }
+
+    fn disable_group(&self, group: usize) {
+        ppi().tasks_chg[self.resources.groups[group] as usize]
+            .dis
+            .write(|w| unsafe { w.bits(1) });
+    }
+
+    fn is_started(&self) -> bool {
+        self.timer.events_compare[0]
+            .read()
+            .events_compare()
+            .bit_is_set()
+    }
+}
+
+impl<const RX: usize> Radio<RX> {
+    /// Acknowledge received frames automatically
+    ///
+    /// `timer` is owned by the radio for timing the acknowledgements, until
+    /// returned by `disable_auto_ack`, and the PPI resources are reserved for
+    /// the radio. Frames are acknowledged when addressed to the address set
+    /// with `set_address`.
+    pub fn enable_auto_ack<T>(&mut self, ppi: &mut PPI, timer: T, resources: AckResources)
+    where
+        T: Into<RadioTimer>,
+    {
+        let owned = timer.into();
+        let timer = owned.registers();
+        timer.tasks_stop.write(|w| w.tasks_stop().set_bit());
+        timer.mode.write(|w| w.mode().timer());
+        timer.bitmode.write(|w| w.bitmode()._32bit());
+        // 1 μs resolution
+        timer.prescaler.write(|w| unsafe { w.prescaler().bits(4) });
+        timer.shorts.write(|w| w.compare0_stop().enabled());
+        timer.cc[0].write(|w| unsafe { w.bits(AIFS_MICROSECONDS) });
+        timer.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        timer.tasks_clear.write(|w| w.tasks_clear().set_bit());
+        timer.events_compare[0].reset();
+
+        let radio = &self.radio;
+        let group_ack = resources.groups[GROUP_ACK] as usize;
+        let group_txen = resources.groups[GROUP_TXEN] as usize;
+        let connections = [
+            (
+                &radio.events_crcok as *const _ as u32,
+                &timer.tasks_clear as *const _ as u32,
+                &timer.tasks_start as *const _ as u32,
+            ),
+            (
+                &timer.events_compare[0] as *const _ as u32,
+                &radio.tasks_start as *const _ as u32,
+                &ppi.tasks_chg[group_ack].dis as *const _ as u32,
+            ),
+            (
+                &radio.events_disabled as *const _ as u32,
+                &radio.tasks_txen as *const _ as u32,
+                &ppi.tasks_chg[group_txen].dis as *const _ as u32,
+            ),
+        ];
+        for (index, (event, task, fork)) in connections.iter().enumerate() {
+            let channel = resources.channels[index] as usize;
+            ppi.ch[channel].eep.write(|w| unsafe { w.bits(*event) });
+            ppi.ch[channel].tep.write(|w| unsafe { w.bits(*task) });
+            ppi.fork[channel].tep.write(|w| unsafe { w.bits(*fork) });
+        }
+        ppi.chenclr
+            .write(|w| unsafe { w.bits(resources.channel_mask(&[0, 1, 2])) });
+        ppi.chg[group_ack].write(|w| unsafe { w.bits(resources.channel_mask(&[0, 1])) });
+        ppi.chg[group_txen].write(|w| unsafe { w.bits(resources.channel_mask(&[2])) });
+
+        self.ack = Some(AutoAck {
+            resources,
+            timer: owned,
+            address_stage: false,
+            frame_pending: false,
+            packet: [0u8; ACK_PACKET_LENGTH],
+        });
+        self.radio.bcc.write(|w| unsafe { w.bcc().bits(BCC_HEADER) });
+        self.configure_interrupts();
+    }
+
+    /// Stop acknowledging received frames, releasing the PPI resources
+    ///
+    /// # Return
+    ///
+    /// Returns the TIMER given to `enable_auto_ack`, stopped.
+    ///
+    pub fn disable_auto_ack(&mut self, ppi: &mut PPI) -> Option<RadioTimer> {
+        let armed = self.state & STATE_ACK == STATE_ACK;
+        self.ack_disarm();
+        self.ack.take().map(|ack| {
+            ppi.chenclr
+                .write(|w| unsafe { w.bits(ack.resources.channel_mask(&[0, 1, 2])) });
+            for group in ack.resources.groups.iter() {
+                ppi.chg[*group as usize].reset();
+            }
+            self.configure_interrupts();
+            if armed {
+                self.set_packet_pointer();
+                self.receive_prepare();
+            }
+            ack.timer.tasks_stop.write(|w| w.tasks_stop().set_bit());
+            ack.timer.shorts.reset();
+            ack.timer
+        })
+    }
+
+    /// Set the delay from the end of a received frame to the start of the
+    /// acknowledgement, in symbols
+    ///
+    /// The delay is limited to 12 to 32 symbols, the default is 12.
+    pub fn set_ack_delay(&mut self, symbols: u32) {
+        if let Some(ack) = &self.ack {
+            let symbols = symbols.clamp(ACK_DELAY_MIN_SYMBOLS, ACK_DELAY_MAX_SYMBOLS);
+            ack.timer.cc[0].write(|w| unsafe { w.bits(MICROSECONDS_PER_SYMBOL * symbols) });
+        }
+    }
+
+    /// Set the frame pending bit in the acknowledgements
+    pub fn set_frame_pending(&mut self, pending: bool) {
+        if let Some(ack) = &mut self.ack {
+            ack.frame_pending = pending;
+        }
+    }
+
+    /// Check if the destination address matches this device
+    fn is_ack_destination(&self) -> bool {
+        let (pan_id, address) = match destination(&self.buffers[self.active][1..]) {
+            Some(destination) => destination,
+            None => return false,
+        };
+        let broadcast = self.broadcast;
+        let pan_id_matches = pan_id == self.address.pan_id
+            || (pan_id == BROADCAST && broadcast.accept_pan && broadcast.ack_pan);
+        let address_matches = match address {
+            Address::Short(BROADCAST) => broadcast.accept_address && broadcast.ack_address,
+            Address::Short(address) => address == self.address.short_address,
+            Address::Extended(address) => address == self.address.extended_address,
+        };
+        pan_id_matches && address_matches
+    }
+
+    /// Handle BCMATCH for automatic acknowledgement
+    pub(super) fn ack_bcmatch(&mut self) {
+        if self.state & (STATE_SEND | STATE_ACK) != 0 {
+            return;
+        }
+        let buffer = &self.buffers[self.active];
+        let frame_control = u16::from_le_bytes([buffer[1], buffer[2]]);
+        let address_stage = match &self.ack {
+            Some(ack) => ack.address_stage,
+            None => return,
+        };
+        if !address_stage {
+            let length = destination_address_length(frame_control);
+            if is_ack_requested(frame_control) && length > 0 {
+                // Wait for the destination PAN identifier and address
+                let count = (3 + 2 + length) as u32 * 8;
+                self.radio.bcc.write(|w| unsafe { w.bcc().bits(count) });
+                if let Some(ack) = &mut self.ack {
+                    ack.address_stage = true;
+                }
+            }
+            return;
+        }
+        self.ack_reset_stage();
+        if self.is_ack_destination() {
+            self.ack_arm();
+        }
+    }
+
+    /// Prepare the matching stage for the next frame
+    ///
+    /// A pending BCMATCH event belongs to a frame that has already ended and
+    /// is discarded.
+    pub(super) fn ack_reset_stage(&mut self) {
+        if let Some(ack) = &mut self.ack {
+            self.radio.events_bcmatch.reset();
+            if ack.address_stage {
+                ack.address_stage = false;
+                self.radio.bcc.write(|w| unsafe { w.bcc().bits(BCC_HEADER) });
+            }
+        }
+    }
+
+    /// Arm the acknowledgement of the frame being received
+    fn ack_arm(&mut self) {
+        let sequence = self.buffers[self.active][3];
+        self.chain_disarm();
+        let ack = match &mut self.ack {
+            Some(ack) => ack,
+            None => return,
+        };
+        // Frame control, sequence number and FCS
+        ack.packet[0] = 5;
+        build_ack(&mut ack.packet[1..], sequence, ack.frame_pending);
+        ack.timer.events_compare[0].reset();
+        // The current reception has already read the packet pointer
+        self.radio
+            .packetptr
+            .write(|w| unsafe { w.bits(ack.packet.as_ptr() as u32) });
+        self.radio.shorts.reset();
+        self.radio.shorts.write(|w| w.phyend_disable().enabled());
+        ack.enable_group(GROUP_ACK);
+        ack.enable_group(GROUP_TXEN);
+        self.state |= STATE_ACK;
+        if self.radio.events_phyend.read().events_phyend().bit_is_set() {
+            // The frame ended before the acknowledgement was armed
+            self.ack_finish();
+        }
+    }
+
+    /// Handle DISABLED while an acknowledgement is armed
+    pub(super) fn ack_disabled(&mut self) {
+        let (started, crc_ok) = match &self.ack {
+            Some(ack) => (
+                ack.is_started(),
+                self.radio.crcstatus.read().crcstatus().is_crcok(),
+            ),
+            None => (true, true),
+        };
+        if !started && !crc_ok {
+            // Frame had CRC error, no acknowledgement
+            self.ack_finish();
+        } else if started && self.state() == radio::state::STATE_A::DISABLED {
+            // Acknowledgement has been sent
+            self.ack_finish();
+        }
+    }
+
+    /// Disable the acknowledgement groups, the radio will not be touched by
+    /// PPI
+    pub(super) fn ack_disarm(&mut self) {
+        if let Some(ack) = &self.ack {
+            ack.disable_group(GROUP_ACK);
+            ack.disable_group(GROUP_TXEN);
+            ack.timer.tasks_stop.write(|w| w.tasks_stop().set_bit());
+            ack.timer.events_compare[0].reset();
+        }
+        self.state &= !STATE_ACK;
+    }
+
+    /// Return to receive after an acknowledgement
+    fn ack_finish(&mut self) {
+        self.ack_disarm();
+        self.set_packet_pointer();
+        self.receive_prepare();
+    }
+}
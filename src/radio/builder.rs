@@ -0,0 +1,287 @@
+//! Allocation-free builders for acknowledgement and beacon frames
+//!
+//! The frames are written without PHR and FCS, ready for
+//! `Radio::queue_transmission`, or a packet buffer after the PHR. Only the
+//! fields needed by the timing critical transmit paths are supported.
+//!
+
+use super::header::Address;
+use crate::superframe::SuperframeConfig;
+
+const FRAME_TYPE_BEACON: u16 = 0;
+const FRAME_TYPE_ACKNOWLEDGEMENT: u16 = 2;
+const FRAME_PENDING: u16 = 1 << 4;
+const IE_PRESENT: u16 = 1 << 9;
+const ADDRESS_MODE_SHORT: u16 = 0b10;
+const ADDRESS_MODE_EXTENDED: u16 = 0b11;
+const DESTINATION_ADDRESS_MODE_SHIFT: u16 = 10;
+const SOURCE_ADDRESS_MODE_SHIFT: u16 = 14;
+const FRAME_VERSION_2015: u16 = 0b10 << 12;
+
+/// Time correction header IE element identifier
+const IE_TIME_CORRECTION: u8 = 0x1e;
+/// Largest header IE content
+const IE_MAX_CONTENT: usize = 127;
+
+/// Writes fields into a buffer, failing when the buffer is full
+struct Writer<'a> {
+    buffer: &'a mut [u8],
+    length: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn new(buffer: &'a mut [u8]) -> Self {
+        Self { buffer, length: 0 }
+    }
+
+    fn put(&mut self, data: &[u8]) -> Option<()> {
+        let end = self.length + data.len();
+        self.buffer.get_mut(self.length..end)?.copy_from_slice(data);
+        self.length = end;
+        Some(())
+    }
+
+    fn put_u8(&mut self, value: u8) -> Option<()> {
+        self.put(&[value])
+    }
+
+    fn put_u16(&mut self, value: u16) -> Option<()> {
+        self.put(&value.to_le_bytes())
+    }
+
+    fn put_address(&mut self, address: Address) -> Option<()> {
+        match address {
+            Address::Short(address) => self.put_u16(address),
+            Address::Extended(address) => self.put(&address.to_le_bytes()),
+        }
+    }
+
+    fn put_header_ie(&mut self, id: u8, content: &[u8]) -> Option<()> {
+        if content.len() > IE_MAX_CONTENT {
+            return None;
+        }
+        // Length in bits 0 to 6, element identifier in bits 7 to 14
+        self.put_u16(content.len() as u16 | u16::from(id) << 7)?;
+        self.put(content)
+    }
+}
+
+fn address_mode(address: Address) -> u16 {
+    match address {
+        Address::Short(_) => ADDRESS_MODE_SHORT,
+        Address::Extended(_) => ADDRESS_MODE_EXTENDED,
+    }
+}
+
+/// Build a immediate acknowledgement
+///
+/// # Return
+///
+/// Returns the length of the frame, or None if the buffer is too small.
+///
+pub fn build_ack(buffer: &mut [u8], sequence: u8, pending: bool) -> Option<usize> {
+    let mut frame_control = FRAME_TYPE_ACKNOWLEDGEMENT;
+    if pending {
+        frame_control |= FRAME_PENDING;
+    }
+    let mut writer = Writer::new(buffer);
+    writer.put_u16(frame_control)?;
+    writer.put_u8(sequence)?;
+    Some(writer.length)
+}
+
+/// A header information element
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeaderIe<'a> {
+    /// Element identifier
+    pub id: u8,
+    /// Content, at most 127 octets
+    pub content: &'a [u8],
+}
+
+/// Enhanced acknowledgement builder
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnhancedAck<'a> {
+    sequence: u8,
+    pending: bool,
+    destination: Option<(u16, Address)>,
+    time_correction: Option<(i16, bool)>,
+    ie: Option<HeaderIe<'a>>,
+}
+
+impl<'a> EnhancedAck<'a> {
+    /// Acknowledge the frame with sequence number `sequence`
+    pub fn new(sequence: u8) -> Self {
+        Self {
+            sequence,
+            pending: false,
+            destination: None,
+            time_correction: None,
+            ie: None,
+        }
+    }
+
+    /// Set the frame pending bit
+    pub fn pending(mut self, pending: bool) -> Self {
+        self.pending = pending;
+        self
+    }
+
+    /// Address the acknowledgement to the originator
+    pub fn destination(mut self, pan_id: u16, address: Address) -> Self {
+        self.destination = Some((pan_id, address));
+        self
+    }
+
+    /// Add a time correction IE, `correction` in microseconds and limited to
+    /// ±2048 μs, `nack` if the frame is not acknowledged
+    pub fn time_correction(mut self, correction: i16, nack: bool) -> Self {
+        self.time_correction = Some((correction, nack));
+        self
+    }
+
+    /// Add a header IE
+    pub fn header_ie(mut self, ie: HeaderIe<'a>) -> Self {
+        self.ie = Some(ie);
+        self
+    }
+
+    /// Write the frame
+    ///
+    /// # Return
+    ///
+    /// Returns the length of the frame, or None if the buffer is too small.
+    ///
+    pub fn write(&self, buffer: &mut [u8]) -> Option<usize> {
+        let mut frame_control = FRAME_TYPE_ACKNOWLEDGEMENT | FRAME_VERSION_2015;
+        if self.pending {
+            frame_control |= FRAME_PENDING;
+        }
+        if self.time_correction.is_some() || self.ie.is_some() {
+            frame_control |= IE_PRESENT;
+        }
+        if let Some((_, address)) = self.destination {
+            frame_control |= address_mode(address) << DESTINATION_ADDRESS_MODE_SHIFT;
+        }
+        let mut writer = Writer::new(buffer);
+        writer.put_u16(frame_control)?;
+        writer.put_u8(self.sequence)?;
+        if let Some((pan_id, address)) = self.destination {
+            writer.put_u16(pan_id)?;
+            writer.put_address(address)?;
+        }
+        if let Some((correction, nack)) = self.time_correction {
+            // 12-bit two's complement, NACK in bit 15
+            let correction = correction.clamp(-2048, 2047) as u16 & 0x0fff;
+            let nack = if nack { 0x8000 } else { 0 };
+            writer.put_header_ie(IE_TIME_CORRECTION, &(correction | nack).to_le_bytes())?;
+        }
+        if let Some(ie) = self.ie {
+            writer.put_header_ie(ie.id, ie.content)?;
+        }
+        Some(writer.length)
+    }
+}
+
+/// Beacon frame builder
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Beacon<'a> {
+    sequence: u8,
+    pan_id: u16,
+    source: Address,
+    superframe: Option<SuperframeConfig>,
+    pan_coordinator: bool,
+    association_permit: bool,
+    pending: bool,
+    payload: &'a [u8],
+}
+
+impl<'a> Beacon<'a> {
+    /// Beacon from `source` in the PAN `pan_id`, of a non beacon-enabled PAN
+    pub fn new(sequence: u8, pan_id: u16, source: Address) -> Self {
+        Self {
+            sequence,
+            pan_id,
+            source,
+            superframe: None,
+            pan_coordinator: false,
+            association_permit: false,
+            pending: false,
+            payload: &[],
+        }
+    }
+
+    /// Superframe of a beacon-enabled PAN
+    pub fn superframe(mut self, superframe: SuperframeConfig) -> Self {
+        self.superframe = Some(superframe);
+        self
+    }
+
+    /// Set the PAN coordinator bit
+    pub fn pan_coordinator(mut self, pan_coordinator: bool) -> Self {
+        self.pan_coordinator = pan_coordinator;
+        self
+    }
+
+    /// Set the association permit bit
+    pub fn association_permit(mut self, association_permit: bool) -> Self {
+        self.association_permit = association_permit;
+        self
+    }
+
+    /// Set the frame pending bit
+    pub fn pending(mut self, pending: bool) -> Self {
+        self.pending = pending;
+        self
+    }
+
+    /// Beacon payload
+    pub fn payload(mut self, payload: &'a [u8]) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Superframe specification field
+    fn superframe_specification(&self) -> u16 {
+        // Beacon order and superframe order 15, no beacons
+        let mut specification = match self.superframe {
+            Some(superframe) => {
+                u16::from(superframe.beacon_order & 0x0f)
+                    | u16::from(superframe.superframe_order & 0x0f) << 4
+                    | u16::from(superframe.final_cap_slot & 0x0f) << 8
+            }
+            None => 0x0fff,
+        };
+        if self.pan_coordinator {
+            specification |= 1 << 14;
+        }
+        if self.association_permit {
+            specification |= 1 << 15;
+        }
+        specification
+    }
+
+    /// Write the frame, without GTS and pending addresses
+    ///
+    /// # Return
+    ///
+    /// Returns the length of the frame, or None if the buffer is too small.
+    ///
+    pub fn write(&self, buffer: &mut [u8]) -> Option<usize> {
+        let mut frame_control =
+            FRAME_TYPE_BEACON | address_mode(self.source) << SOURCE_ADDRESS_MODE_SHIFT;
+        if self.pending {
+            frame_control |= FRAME_PENDING;
+        }
+        let mut writer = Writer::new(buffer);
+        writer.put_u16(frame_control)?;
+        writer.put_u8(self.sequence)?;
+        writer.put_u16(self.pan_id)?;
+        writer.put_address(self.source)?;
+        writer.put_u16(self.superframe_specification())?;
+        // GTS specification and pending address specification, both empty
+        writer.put(&[0, 0])?;
+        writer.put(self.payload)?;
+        Some(writer.length)
+    }
+}
@@ -0,0 +1,235 @@
+//! PPI chained transmit path
+//!
+//! When enabled, the whole sequence of a transmission,
+//!
+//! ```notrust
+//! RXEN → CCA → TXEN → START → PHYEND → DISABLE → RXEN → START
+//! ```
+//!
+//! is sequenced by SHORTS and PPI. Only one interrupt, DISABLED, signals the
+//! completion of the transmission. The radio is back in receive mode without
+//! any software involvement, so the timing does not depend on interrupt
+//! latency.
+//!
+//! The PPI channels used for RXREADY and PHYEND have different tasks when
+//! transmitting and receiving, three channel groups are used to switch
+//! between them.
+//!
+//! | Channel | Event   | Task     | Fork             | Group   |
+//! |---------|---------|----------|------------------|---------|
+//! | 0       | RXREADY | CCASTART | disable CCA      | CCA     |
+//! | 1       | PHYEND  | DISABLE  | disable TX       | TX      |
+//! | 2       | PHYEND  | enable RX|                  | TX      |
+//! | 3       | CCABUSY | enable RX| disable TX       | TX      |
+//! | 4       | RXREADY | START    |                  | RX      |
+//! | 5       | PHYEND  | START    |                  | RX      |
+//!
+//! The shorts CCAIDLE → TXEN, TXREADY → START, CCABUSY → DISABLE and
+//! DISABLED → RXEN complete the chain.
+//!
+
+use crate::pac::{ppi, PPI};
+
+use super::{is_valid_ppi, Error, Radio};
+
+/// PPI resources reserved for the chained transmit path
+///
+/// The channels and groups must not be used by anything else.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PpiChain {
+    channels: [u8; 6],
+    groups: [u8; 3],
+}
+
+impl Default for PpiChain {
+    /// Use the channels 14 to 19 and the groups 3 to 5
+    fn default() -> Self {
+        Self {
+            channels: [14, 15, 16, 17, 18, 19],
+            groups: [3, 4, 5],
+        }
+    }
+}
+
+const GROUP_CCA: usize = 0;
+const GROUP_TX: usize = 1;
+const GROUP_RX: usize = 2;
+
+fn ppi() -> &'static ppi::RegisterBlock {
+    // Only the tasks of the reserved groups are accessed
+    unsafe { &*PPI::ptr() }
+}
+
+impl PpiChain {
+    /// Reserve six PPI channels, 0 to 19, and three PPI channel groups, 0 to
+    /// 5, the CCA group, TX group and RX group
+    ///
+    /// Returns `Error::InvalidResources` if a channel or group does not
+    /// exist.
+    pub fn new(channels: [u8; 6], groups: [u8; 3]) -> Result<Self, Error> {
+        if !is_valid_ppi(&channels, &groups) {
+            return Err(Error::InvalidResources);
+        }
+        Ok(Self { channels, groups })
+    }
+
+    fn channel_mask(&self, channels: &[usize]) -> u32 {
+        channels
+            .iter()
+            .fold(0, |mask, index| mask | 1 << self.channels[*index])
+    }
+
+    fn enable_group(&self, group: usize) {
+        ppi().tasks_chg[self.groups[group] as usize]
+            .en
+            .write(|w| unsafe { w.bits(1) });
+    }
+
+    fn disable_group(&self, group: usize) {
+        ppi().tasks_chg[self.groups[group] as usize]
+            .dis
+            .write(|w| unsafe { w.bits(1) });
+    }
+
+    fn group_enable_task(&self, group: usize) -> u32 {
+        &ppi().tasks_chg[self.groups[group] as usize].en as *const _ as u32
+    }
+
+    fn group_disable_task(&self, group: usize) -> u32 {
+        &ppi().tasks_chg[self.groups[group] as usize].dis as *const _ as u32
+    }
+}
+
+impl<const RX: usize> Radio<RX> {
+    /// Use the fully PPI chained transmit path
+    ///
+    /// The PPI channels and groups in `chain` are configured and reserved for
+    /// the radio. The radio is put in receive mode.
+    pub fn enable_chained_transmit(&mut self, ppi: &mut PPI, chain: PpiChain) {
+        self.enter_disabled();
+        let radio = &self.radio;
+        let connections = [
+            (
+                &radio.events_rxready as *const _ as u32,
+                &radio.tasks_ccastart as *const _ as u32,
+                chain.group_disable_task(GROUP_CCA),
+            ),
+            (
+                &radio.events_phyend as *const _ as u32,
+                &radio.tasks_disable as *const _ as u32,
+                chain.group_disable_task(GROUP_TX),
+            ),
+            (
+                &radio.events_phyend as *const _ as u32,
+                chain.group_enable_task(GROUP_RX),
+                0,
+            ),
+            (
+                &radio.events_ccabusy as *const _ as u32,
+                chain.group_enable_task(GROUP_RX),
+                chain.group_disable_task(GROUP_TX),
+            ),
+            (
+                &radio.events_rxready as *const _ as u32,
+                &radio.tasks_start as *const _ as u32,
+                0,
+            ),
+            (
+                &radio.events_phyend as *const _ as u32,
+                &radio.tasks_start as *const _ as u32,
+                0,
+            ),
+        ];
+        for (index, (event, task, fork)) in connections.iter().enumerate() {
+            let channel = chain.channels[index] as usize;
+            ppi.ch[channel].eep.write(|w| unsafe { w.bits(*event) });
+            ppi.ch[channel].tep.write(|w| unsafe { w.bits(*task) });
+            ppi.fork[channel].tep.write(|w| unsafe { w.bits(*fork) });
+        }
+        ppi.chenclr
+            .write(|w| unsafe { w.bits(chain.channel_mask(&[0, 1, 2, 3, 4, 5])) });
+        ppi.chg[chain.groups[GROUP_CCA] as usize]
+            .write(|w| unsafe { w.bits(chain.channel_mask(&[0])) });
+        ppi.chg[chain.groups[GROUP_TX] as usize]
+            .write(|w| unsafe { w.bits(chain.channel_mask(&[1, 2, 3])) });
+        ppi.chg[chain.groups[GROUP_RX] as usize]
+            .write(|w| unsafe { w.bits(chain.channel_mask(&[4, 5])) });
+        self.chain = Some(chain);
+        self.configure_interrupts();
+        self.receive_prepare();
+    }
+
+    /// Stop using the PPI chained transmit path, releasing the PPI resources
+    pub fn disable_chained_transmit(&mut self, ppi: &mut PPI) {
+        if let Some(chain) = self.chain {
+            self.enter_disabled();
+            ppi.chenclr
+                .write(|w| unsafe { w.bits(chain.channel_mask(&[0, 1, 2, 3, 4, 5])) });
+            for group in chain.groups.iter() {
+                ppi.chg[*group as usize].reset();
+            }
+            self.chain = None;
+            self.configure_interrupts();
+            self.receive_prepare();
+        }
+    }
+
+    /// Disable all chain groups, the radio will not be touched by PPI
+    pub(super) fn chain_disarm(&mut self) {
+        if let Some(chain) = self.chain {
+            self.radio.shorts.reset();
+            chain.disable_group(GROUP_CCA);
+            chain.disable_group(GROUP_TX);
+            chain.disable_group(GROUP_RX);
+        }
+    }
+
+    /// Arm the chain for reception, the radio shall be disabled
+    pub(super) fn chain_receive(&mut self, chain: PpiChain) {
+        self.radio.shorts.reset();
+        chain.enable_group(GROUP_RX);
+        self.radio.tasks_rxen.write(|w| w.tasks_rxen().set_bit());
+    }
+
+    /// Arm the chain for transmission, the radio shall be disabled and the
+    /// packet in the buffer
+    pub(super) fn chain_transmit(&mut self, chain: PpiChain, cca: bool) {
+        // Only interrupt on completion
+        self.radio.intenclr.write(|w| w.phyend().clear());
+        self.radio.shorts.reset();
+        self.radio.shorts.write(|w| {
+            w.ccaidle_txen()
+                .enabled()
+                .txready_start()
+                .enabled()
+                .ccabusy_disable()
+                .enabled()
+                .disabled_rxen()
+                .enabled()
+        });
+        chain.enable_group(GROUP_TX);
+        if cca {
+            chain.enable_group(GROUP_CCA);
+            self.radio.tasks_rxen.write(|w| w.tasks_rxen().set_bit());
+        } else {
+            self.radio.tasks_txen.write(|w| w.tasks_txen().set_bit());
+        }
+    }
+
+    /// Handle completion of a chained transmission, the radio is already
+    /// returning to receive
+    pub(super) fn chain_complete(&mut self) {
+        // Keep the next software disable from re-enabling receive
+        self.radio.shorts.write(|w| {
+            w.ccaidle_txen()
+                .enabled()
+                .txready_start()
+                .enabled()
+                .ccabusy_disable()
+                .enabled()
+        });
+        if self.interrupts {
+            self.radio.intenset.write(|w| w.phyend().set());
+        }
+    }
+}
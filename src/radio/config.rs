@@ -0,0 +1,178 @@
+//! Radio configuration applied at construction
+//!
+//! ```notrust
+//! let config = RadioConfig::new()
+//!     .channel(15)?
+//!     .transmission_power(8)?
+//!     .address(address);
+//! let radio = Radio::new_with_config(peripheral, config);
+//! ```
+//!
+//! The channel and the transmission power are validated when set, so that
+//! the radio is always brought up with the requested settings.
+//!
+
+use super::{
+    is_valid_channel, is_valid_transmission_power, BroadcastPolicy, DeviceAddress, Error,
+    ReceiveFilter, Region, CCA_CORR_LIMIT_DEFAULT, CCA_CORR_THRESHOLD_DEFAULT,
+    CCA_ED_THRESHOLD_DEFAULT, CRC_POLYNOMIAL, SFD_DEFAULT,
+};
+
+/// Clear channel assessment (CCA) mode
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CcaMode {
+    /// Energy above threshold
+    EnergyDetect,
+    /// Carrier seen
+    Carrier,
+    /// Energy above threshold and carrier seen
+    CarrierAndEnergyDetect,
+    /// Energy above threshold or carrier seen
+    CarrierOrEnergyDetect,
+    /// Energy above threshold, test mode which aborts the CCA on the first
+    /// busy detection
+    EnergyDetectTest,
+}
+
+/// Clear channel assessment (CCA) configuration
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CcaConfig {
+    /// CCA mode
+    pub mode: CcaMode,
+    /// Energy detect threshold
+    pub ed_threshold: u8,
+    /// Carrier correlator threshold
+    pub corr_threshold: u8,
+    /// Number of carrier correlator peaks required for busy
+    pub corr_limit: u8,
+}
+
+impl Default for CcaConfig {
+    fn default() -> Self {
+        Self {
+            mode: CcaMode::EnergyDetect,
+            ed_threshold: CCA_ED_THRESHOLD_DEFAULT,
+            corr_threshold: CCA_CORR_THRESHOLD_DEFAULT,
+            corr_limit: CCA_CORR_LIMIT_DEFAULT,
+        }
+    }
+}
+
+/// Configuration for `Radio::new_with_config`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RadioConfig {
+    pub(super) channel: Option<u8>,
+    pub(super) transmission_power: i8,
+    pub(super) cca: CcaConfig,
+    pub(super) sfd: u8,
+    pub(super) crc_polynomial: u32,
+    pub(super) crc_init: u32,
+    pub(super) address: DeviceAddress,
+    pub(super) filter: ReceiveFilter,
+    pub(super) broadcast: BroadcastPolicy,
+    pub(super) region: Region,
+    pub(super) interrupts: bool,
+}
+
+impl RadioConfig {
+    /// Create the default configuration
+    ///
+    /// The channel is not set, transmission power is 4 dBm, CCA uses energy
+    /// detect and interrupts are enabled.
+    pub fn new() -> Self {
+        Self {
+            channel: None,
+            transmission_power: 4,
+            cca: CcaConfig::default(),
+            sfd: SFD_DEFAULT,
+            crc_polynomial: CRC_POLYNOMIAL,
+            crc_init: 0,
+            address: DeviceAddress::default(),
+            filter: ReceiveFilter::default(),
+            broadcast: BroadcastPolicy::default(),
+            region: Region::default(),
+            interrupts: true,
+        }
+    }
+
+    /// Set the channel, 11 to 26
+    ///
+    /// Returns `Error::InvalidChannel` for other channels.
+    pub fn channel(mut self, channel: u8) -> Result<Self, Error> {
+        if !is_valid_channel(channel) {
+            return Err(Error::InvalidChannel);
+        }
+        self.channel = Some(channel);
+        Ok(self)
+    }
+
+    /// Set the transmission power in dBm, see `Radio::set_transmission_power`
+    ///
+    /// Returns `Error::InvalidPower` for other levels.
+    pub fn transmission_power(mut self, power: i8) -> Result<Self, Error> {
+        if !is_valid_transmission_power(power) {
+            return Err(Error::InvalidPower);
+        }
+        self.transmission_power = power;
+        Ok(self)
+    }
+
+    /// Set the clear channel assessment configuration
+    pub fn cca(mut self, cca: CcaConfig) -> Self {
+        self.cca = cca;
+        self
+    }
+
+    /// Set the start of frame delimiter
+    pub fn sfd(mut self, sfd: u8) -> Self {
+        self.sfd = sfd;
+        self
+    }
+
+    /// Set the CRC polynomial and initial value
+    pub fn crc(mut self, polynomial: u32, init: u32) -> Self {
+        self.crc_polynomial = polynomial;
+        self.crc_init = init;
+        self
+    }
+
+    /// Set the addresses of the device
+    pub fn address(mut self, address: DeviceAddress) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Set the signal quality filter for received frames
+    pub fn receive_filter(mut self, filter: ReceiveFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Set the handling of broadcast frames
+    pub fn broadcast(mut self, policy: BroadcastPolicy) -> Self {
+        self.broadcast = policy;
+        self
+    }
+
+    /// Set the regulatory region, see `Radio::set_region`
+    pub fn region(mut self, region: Region) -> Self {
+        self.region = region;
+        self
+    }
+
+    /// Enable or disable the radio interrupts
+    ///
+    /// Disable the interrupts when the radio is polled, see `Radio::poll`.
+    pub fn interrupts(mut self, enabled: bool) -> Self {
+        self.interrupts = enabled;
+        self
+    }
+}
+
+impl Default for RadioConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
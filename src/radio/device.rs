@@ -0,0 +1,128 @@
+//! smoltcp device implementation
+//!
+//! Lets the radio be used as a IEEE 802.15.4 medium by smoltcp, for running
+//! 6LoWPAN and IPv6 on the same driver that psila uses for Zigbee.
+//!
+
+use smoltcp::phy::{self, Device, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+
+use crate::phy::MAX_FRAME_LENGTH;
+
+use super::{Error, PacketBuffer, Radio, MAX_PACKET_LENGHT, RX_QUEUE_LENGTH};
+
+/// smoltcp device using the radio
+///
+/// Received frames are read into a internal buffer when smoltcp polls the
+/// device. Transmissions use clear channel assessment, a frame the radio
+/// refuses is not sent and the error is kept for `take_error`.
+pub struct RadioDevice<'r, const RX: usize = RX_QUEUE_LENGTH> {
+    radio: &'r mut Radio<RX>,
+    rx_buffer: PacketBuffer,
+    tx_buffer: [u8; MAX_FRAME_LENGTH],
+    /// Error of the last refused transmission
+    error: Option<Error>,
+}
+
+impl<'r, const RX: usize> RadioDevice<'r, RX> {
+    /// Create a device using the radio, the radio is put in receive mode
+    pub fn new(radio: &'r mut Radio<RX>) -> Self {
+        radio.receive_prepare();
+        Self {
+            radio,
+            rx_buffer: [0u8; MAX_PACKET_LENGHT],
+            tx_buffer: [0u8; MAX_FRAME_LENGTH],
+            error: None,
+        }
+    }
+
+    /// Take the error of the last refused transmission
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+}
+
+/// Token holding a received frame
+pub struct RxToken<'a> {
+    buffer: &'a mut [u8],
+}
+
+impl<'a> phy::RxToken for RxToken<'a> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(self.buffer)
+    }
+}
+
+/// Token for transmitting a frame
+///
+/// smoltcp keeps frames within the MTU, a longer frame is built into the
+/// whole buffer but not sent.
+pub struct TxToken<'a, const RX: usize> {
+    radio: &'a mut Radio<RX>,
+    buffer: &'a mut [u8; MAX_FRAME_LENGTH],
+    error: &'a mut Option<Error>,
+}
+
+impl<'a, const RX: usize> phy::TxToken for TxToken<'a, RX> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        if len > MAX_FRAME_LENGTH {
+            *self.error = Some(Error::InvalidLength);
+            return f(&mut self.buffer[..]);
+        }
+        let result = f(&mut self.buffer[..len]);
+        if let Err(error) = self.radio.queue_transmission(&self.buffer[..len]) {
+            *self.error = Some(error);
+        }
+        result
+    }
+}
+
+impl<'r, const RX: usize> Device for RadioDevice<'r, RX> {
+    type RxToken<'a> = RxToken<'a> where Self: 'a;
+    type TxToken<'a> = TxToken<'a, RX> where Self: 'a;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        if self.radio.is_tx_busy() {
+            return None;
+        }
+        let length = match self.radio.receive(&mut self.rx_buffer) {
+            Ok(length) if length > 2 => length,
+            _ => return None,
+        };
+        // Strip PHR, FCS and LQI
+        let rx = RxToken {
+            buffer: &mut self.rx_buffer[1..length - 1],
+        };
+        let tx = TxToken {
+            radio: &mut *self.radio,
+            buffer: &mut self.tx_buffer,
+            error: &mut self.error,
+        };
+        Some((rx, tx))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        if self.radio.is_tx_busy() {
+            return None;
+        }
+        Some(TxToken {
+            radio: &mut *self.radio,
+            buffer: &mut self.tx_buffer,
+            error: &mut self.error,
+        })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut capabilities = DeviceCapabilities::default();
+        capabilities.medium = Medium::Ieee802154;
+        capabilities.max_transmission_unit = MAX_FRAME_LENGTH;
+        capabilities.max_burst_size = Some(1);
+        capabilities
+    }
+}
@@ -0,0 +1,98 @@
+//! Typed frame transmission and reception
+//!
+//! Serializes and deserializes `ieee802154` MAC frames at the driver
+//! boundary.
+//!
+
+use byte::BytesExt;
+use ieee802154::mac::{FooterMode, Frame, FrameSerDesContext};
+
+use crate::phy::MAX_FRAME_LENGTH;
+
+use super::{Error, PacketBuffer, Radio};
+
+/// Errors returned by the typed frame functions
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameError {
+    /// Error reported by the radio
+    Radio(Error),
+    /// The frame could not be serialized, it might be too large
+    Encode,
+    /// The received data is not a valid frame
+    Decode,
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FrameError::Radio(error) => write!(f, "radio error, {}", error),
+            FrameError::Encode => f.write_str("failed to encode frame"),
+            FrameError::Decode => f.write_str("failed to decode frame"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::error::Error for FrameError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            FrameError::Radio(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for FrameError {
+    fn from(error: Error) -> Self {
+        FrameError::Radio(error)
+    }
+}
+
+impl<const RX: usize> Radio<RX> {
+    /// Queue a transmission of a frame, using CCA
+    ///
+    /// The FCS is added by the radio.
+    ///
+    /// # Return
+    ///
+    /// Returns the number of bytes queued for transmission.
+    ///
+    pub fn transmit_frame(&mut self, frame: &Frame) -> Result<usize, FrameError> {
+        let mut data = [0u8; MAX_FRAME_LENGTH];
+        let mut length = 0;
+        data.write_with(
+            &mut length,
+            *frame,
+            &mut FrameSerDesContext::no_security(FooterMode::None),
+        )
+        .map_err(|_| FrameError::Encode)?;
+        Ok(self.queue_transmission(&data[..length])?)
+    }
+
+    /// Read a received frame
+    ///
+    /// The frame is received into `buffer` and the returned frame refers to it.
+    ///
+    /// # Return
+    ///
+    /// Returns the frame and the link quality indicator (LQI), or None if no
+    /// frame has been received.
+    ///
+    pub fn receive_frame<'b>(
+        &mut self,
+        buffer: &'b mut PacketBuffer,
+    ) -> Result<Option<(Frame<'b>, u8)>, FrameError> {
+        let length = self.receive(buffer)?;
+        if length <= 2 {
+            return Ok(None);
+        }
+        let lqi = buffer[length - 1];
+        let data: &'b [u8] = &buffer[1..length - 1];
+        let frame = data
+            .read_with::<Frame>(&mut 0, FooterMode::None)
+            .map_err(|_| FrameError::Decode)?;
+        Ok(Some((frame, lqi)))
+    }
+}
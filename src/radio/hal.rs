@@ -0,0 +1,202 @@
+//! Implementation of the `radio` crate traits
+//!
+//! Allows generic MAC layers and test harnesses written against the
+//! [radio](https://crates.io/crates/radio) traits to use this driver.
+//!
+
+use radio_hal::{Channel, Power, Receive, ReceiveInfo, Rssi, Transmit};
+
+use crate::phy::check_frame_length;
+
+use super::{
+    is_valid_channel, is_valid_transmission_power, level_to_dbm, radio, state, Error,
+    PacketBuffer, Radio, MAX_PACKET_LENGHT, RSSI_ATTEMPTS,
+};
+
+/// Errors returned by the `radio` trait implementations
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HalError {
+    /// Error reported by the radio
+    Radio(Error),
+    /// The channel is not a valid 802.15.4 channel
+    InvalidChannel,
+    /// The transmission power is not supported
+    InvalidPower,
+    /// The frame does not fit in a 802.15.4 packet
+    InvalidLength,
+    /// The radio is not in receive mode
+    InvalidState,
+    /// The radio did not complete the operation in time
+    Timeout,
+}
+
+impl From<Error> for HalError {
+    fn from(error: Error) -> Self {
+        HalError::Radio(error)
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for HalError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            HalError::Radio(error) => write!(f, "radio error, {}", error),
+            HalError::InvalidChannel => f.write_str("invalid channel"),
+            HalError::InvalidPower => f.write_str("invalid transmission power"),
+            HalError::InvalidLength => f.write_str("invalid frame length"),
+            HalError::InvalidState => f.write_str("not receiving"),
+            HalError::Timeout => f.write_str("timeout"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::error::Error for HalError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            HalError::Radio(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Information about a received frame
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameInfo {
+    /// Received signal strength in dBm
+    pub rssi: i16,
+    /// Link quality indicator
+    pub lqi: u8,
+}
+
+impl ReceiveInfo for FrameInfo {
+    fn rssi(&self) -> i16 {
+        self.rssi
+    }
+}
+
+impl<const RX: usize> Transmit for Radio<RX> {
+    type Error = HalError;
+
+    fn start_transmit(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        check_frame_length(data.len()).map_err(|_| HalError::InvalidLength)?;
+        self.queue_transmission(data)?;
+        Ok(())
+    }
+
+    fn check_transmit(&mut self) -> Result<bool, Self::Error> {
+        if !self.is_tx_busy() {
+            return Ok(true);
+        }
+        // Discard the PHYEND from our own transmission
+        let _ = self.handle_phyend();
+        self.handle_disabled();
+        if self.handle_ccabusy() {
+            self.state = 0;
+            return Err(HalError::Radio(Error::CcaBusy));
+        }
+        Ok(!self.is_tx_busy())
+    }
+}
+
+impl<const RX: usize> Receive for Radio<RX> {
+    type Info = FrameInfo;
+    type Error = HalError;
+
+    fn start_receive(&mut self) -> Result<(), Self::Error> {
+        self.receive_prepare();
+        Ok(())
+    }
+
+    fn check_receive(&mut self, restart: bool) -> Result<bool, Self::Error> {
+        self.handle_disabled();
+        self.handle_ready();
+        if self.is_tx_busy() {
+            return Ok(false);
+        }
+        if self.radio.events_phyend.read().events_phyend().bit_is_set() {
+            let phr = self.buffers[self.active][0];
+            if phr & 0x80 == 0 && phr & 0x7f > 2 {
+                return Ok(true);
+            }
+            // Not a 802.15.4 packet, drop it
+            let _ = self.handle_phyend();
+            if restart {
+                self.receive_prepare();
+            }
+        }
+        Ok(false)
+    }
+
+    fn get_received(&mut self, buff: &mut [u8]) -> Result<(usize, Self::Info), Self::Error> {
+        let mut packet: PacketBuffer = [0u8; MAX_PACKET_LENGHT];
+        let length = self.receive(&mut packet)?;
+        if length <= 2 {
+            return Ok((0, FrameInfo::default()));
+        }
+        let lqi = packet[length - 1];
+        let payload = &packet[1..length - 1];
+        if buff.len() < payload.len() {
+            return Err(HalError::InvalidLength);
+        }
+        buff[..payload.len()].copy_from_slice(payload);
+        let info = FrameInfo {
+            rssi: i16::from(level_to_dbm(lqi)),
+            lqi,
+        };
+        Ok((payload.len(), info))
+    }
+}
+
+impl<const RX: usize> Channel for Radio<RX> {
+    type Channel = u8;
+    type Error = HalError;
+
+    fn set_channel(&mut self, channel: &Self::Channel) -> Result<(), Self::Error> {
+        if !is_valid_channel(*channel) {
+            return Err(HalError::InvalidChannel);
+        }
+        Radio::set_channel(self, *channel).map_err(HalError::Radio)
+    }
+}
+
+impl<const RX: usize> Power for Radio<RX> {
+    type Error = HalError;
+
+    fn set_power(&mut self, power: i8) -> Result<(), Self::Error> {
+        if !is_valid_transmission_power(power) {
+            return Err(HalError::InvalidPower);
+        }
+        self.set_transmission_power(power).map_err(HalError::Radio)
+    }
+}
+
+impl<const RX: usize> Rssi for Radio<RX> {
+    type Error = HalError;
+
+    fn poll_rssi(&mut self) -> Result<i16, Self::Error> {
+        match self.state() {
+            state::RX_IDLE | radio::state::STATE_A::RX => (),
+            _ => return Err(HalError::InvalidState),
+        }
+        self.radio.events_rssiend.reset();
+        self.radio
+            .tasks_rssistart
+            .write(|w| w.tasks_rssistart().set_bit());
+        let sampled = (0..RSSI_ATTEMPTS).any(|_| {
+            self.radio
+                .events_rssiend
+                .read()
+                .events_rssiend()
+                .bit_is_set()
+        });
+        self.radio.events_rssiend.reset();
+        if !sampled {
+            return Err(HalError::Timeout);
+        }
+        // The sample is the magnitude of the negative signal strength
+        let sample = self.radio.rssisample.read().rssisample().bits();
+        Ok(-i16::from(sample))
+    }
+}
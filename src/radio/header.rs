@@ -0,0 +1,56 @@
+//! Minimal MAC header parsing used by the driver
+//!
+//! Only the frame control and the destination fields are inspected, which
+//! is enough for address filtering and acknowledgement.
+//!
+
+/// Broadcast PAN identifier and short address
+pub const BROADCAST: u16 = 0xffff;
+
+/// Destination address of a frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Address {
+    /// Short address
+    Short(u16),
+    /// Extended address
+    Extended(u64),
+}
+
+/// Frame control field, the frame is without PHR
+pub(super) fn frame_control(frame: &[u8]) -> Option<u16> {
+    if frame.len() < 2 {
+        return None;
+    }
+    Some(u16::from_le_bytes([frame[0], frame[1]]))
+}
+
+/// Length of the destination address for the frame control field
+pub(super) fn destination_address_length(frame_control: u16) -> usize {
+    match (frame_control >> 10) & 0b11 {
+        0b10 => 2,
+        0b11 => 8,
+        _ => 0,
+    }
+}
+
+/// Destination PAN identifier and address, the frame is without PHR
+///
+/// Returns None if the frame has no destination or is too short.
+pub(super) fn destination(frame: &[u8]) -> Option<(u16, Address)> {
+    let frame_control = frame_control(frame)?;
+    let length = destination_address_length(frame_control);
+    // Frame control, sequence number, PAN identifier and address
+    if length == 0 || frame.len() < 5 + length {
+        return None;
+    }
+    let pan_id = u16::from_le_bytes([frame[3], frame[4]]);
+    let address = if length == 2 {
+        Address::Short(u16::from_le_bytes([frame[5], frame[6]]))
+    } else {
+        let mut address = [0u8; 8];
+        address.copy_from_slice(&frame[5..13]);
+        Address::Extended(u64::from_le_bytes(address))
+    };
+    Some((pan_id, address))
+}
@@ -0,0 +1,124 @@
+//! Listen-before-talk for regulatory compliance
+//!
+//! In the `Region::Europe` region the radio follows the adaptivity
+//! requirements of ETSI EN 300 328 for load based equipment.
+//!
+//! * Every transmission is preceded by a clear channel assessment, 8 symbols
+//!   or 128 μs, well above the minimum listen time. Transmissions without CCA
+//!   are refused.
+//! * The CCA uses energy detect, with a threshold following the transmission
+//!   power, `TL = -70 dBm/MHz + 10 × log10(100 mW / Pout)` over the 2 MHz
+//!   channel.
+//! * After a busy channel the MAC must back off for a random number of CCA
+//!   slots, given by `Radio::lbt_backoff`, before the next attempt.
+//!
+
+use super::{dbm_to_level, Radio};
+
+/// Duration of a CCA slot in microseconds, 8 symbols
+const CCA_SLOT_MICROSECONDS: u32 = 128;
+/// Energy detect threshold at 20 dBm e.i.r.p. over 2 MHz, in dBm
+const THRESHOLD_REFERENCE_DBM: i16 = -70 + 3;
+/// Reference e.i.r.p. in dBm, 100 mW
+const REFERENCE_EIRP_DBM: i16 = 20;
+/// Smallest and largest back-off factor q
+const BACKOFF_FACTOR_MIN: u8 = 4;
+const BACKOFF_FACTOR_MAX: u8 = 32;
+
+/// Listen-before-talk parameters
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LbtConfig {
+    /// Gain between the radio and the air in dB, antenna gain and any
+    /// external amplifier
+    pub antenna_gain: i8,
+    /// Back-off factor q, 4 to 32. The back-off is 1 to q CCA slots and
+    /// the channel occupancy time is limited to 13 / 32 × q ms
+    pub backoff_factor: u8,
+}
+
+impl Default for LbtConfig {
+    fn default() -> Self {
+        Self {
+            antenna_gain: 0,
+            backoff_factor: BACKOFF_FACTOR_MAX,
+        }
+    }
+}
+
+/// Regulatory region
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Region {
+    /// No channel access requirements beyond 802.15.4
+    Unrestricted,
+    /// Listen-before-talk following ETSI EN 300 328
+    Europe(LbtConfig),
+}
+
+impl Default for Region {
+    /// No restrictions
+    fn default() -> Self {
+        Region::Unrestricted
+    }
+}
+
+impl<const RX: usize> Radio<RX> {
+    /// Set the regulatory region
+    ///
+    /// The CCA configuration is overridden while listen-before-talk is
+    /// required, and kept when returning to `Region::Unrestricted`.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.apply_region();
+    }
+
+    /// Regulatory region
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Check if transmissions without CCA are allowed in the region
+    pub(super) fn is_cca_required(&self) -> bool {
+        self.region != Region::Unrestricted
+    }
+
+    /// Energy detect threshold for the transmission power, in dBm
+    ///
+    /// Returns None if the region does not require listen-before-talk.
+    pub fn lbt_threshold(&self) -> Option<i8> {
+        match self.region {
+            Region::Unrestricted => None,
+            Region::Europe(lbt) => {
+                let eirp = i16::from(self.transmission_power) + i16::from(lbt.antenna_gain);
+                let threshold = THRESHOLD_REFERENCE_DBM + REFERENCE_EIRP_DBM - eirp;
+                Some(threshold.clamp(i16::from(i8::MIN), i16::from(i8::MAX)) as i8)
+            }
+        }
+    }
+
+    /// Back-off after a busy channel in microseconds, from a random number
+    ///
+    /// Returns zero if the region does not require a back-off.
+    pub fn lbt_backoff(&self, random: u32) -> u32 {
+        match self.region {
+            Region::Unrestricted => 0,
+            Region::Europe(lbt) => {
+                let q = lbt
+                    .backoff_factor
+                    .clamp(BACKOFF_FACTOR_MIN, BACKOFF_FACTOR_MAX);
+                (1 + random % u32::from(q)) * CCA_SLOT_MICROSECONDS
+            }
+        }
+    }
+
+    /// Apply the CCA energy detect threshold of the region
+    pub(super) fn apply_region(&mut self) {
+        if let Some(threshold) = self.lbt_threshold() {
+            let level = dbm_to_level(threshold);
+            self.radio
+                .ccactrl
+                .modify(|_, w| unsafe { w.ccamode().ed_mode().ccaedthres().bits(level) });
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Implementation of the `Ieee802154Phy` trait
+
+use super::Radio;
+#[cfg(feature = "energy-detect")]
+use crate::phy::ScanDuration;
+use crate::phy::{CcaResult, Error, Ieee802154Phy};
+
+impl<const RX: usize> Ieee802154Phy for Radio<RX> {
+    fn set_channel(&mut self, channel: u8) -> Result<(), Error> {
+        Radio::set_channel(self, channel)
+    }
+
+    fn get_channel(&mut self) -> u8 {
+        Radio::get_channel(self)
+    }
+
+    fn is_tx_busy(&self) -> bool {
+        Radio::is_tx_busy(self)
+    }
+
+    fn set_transmission_power(&mut self, power: i8) -> Result<(), Error> {
+        Radio::set_transmission_power(self, power)
+    }
+
+    fn transmit(&mut self, data: &[u8], cca: bool) -> Result<usize, Error> {
+        if cca {
+            self.queue_transmission(data)
+        } else {
+            self.queue_transmission_no_cca(data)
+        }
+    }
+
+    fn receive_slice(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        Radio::receive_slice(self, buffer)
+    }
+
+    #[cfg(feature = "energy-detect")]
+    fn start_energy_detect(&mut self, duration: ScanDuration) -> bool {
+        Radio::start_energy_detect(self, duration)
+    }
+
+    #[cfg(feature = "energy-detect")]
+    fn report_energy_detect(&mut self) -> Option<u8> {
+        Radio::report_energy_detect(self)
+    }
+
+    fn start_cca(&mut self) {
+        Radio::start_cca(self)
+    }
+
+    fn report_cca(&mut self) -> Option<CcaResult> {
+        Radio::report_cca(self)
+    }
+}
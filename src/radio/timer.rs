@@ -0,0 +1,62 @@
+//! TIMER dedicated to the radio
+//!
+//! Automatic acknowledgement and TSCH time the radio with a TIMER connected
+//! through PPI. The radio takes ownership of the TIMER while in use, so that
+//! nothing else reprograms it, and hands it back when disabled.
+//!
+
+use core::ops::Deref;
+
+use crate::pac::{timer0, TIMER0, TIMER1, TIMER2};
+
+/// A TIMER peripheral owned by the radio
+pub enum RadioTimer {
+    /// TIMER0
+    Timer0(TIMER0),
+    /// TIMER1
+    Timer1(TIMER1),
+    /// TIMER2
+    Timer2(TIMER2),
+}
+
+impl RadioTimer {
+    /// Registers of the TIMER
+    ///
+    /// The reference is not tied to the borrow of `self`, so that the radio
+    /// can use the registers while updating its own state. The TIMER is owned
+    /// by the radio for as long as the reference is used.
+    pub(super) fn registers(&self) -> &'static timer0::RegisterBlock {
+        let pointer = match self {
+            RadioTimer::Timer0(_) => TIMER0::ptr(),
+            RadioTimer::Timer1(_) => TIMER1::ptr(),
+            RadioTimer::Timer2(_) => TIMER2::ptr(),
+        };
+        unsafe { &*pointer }
+    }
+}
+
+impl Deref for RadioTimer {
+    type Target = timer0::RegisterBlock;
+
+    fn deref(&self) -> &timer0::RegisterBlock {
+        self.registers()
+    }
+}
+
+impl From<TIMER0> for RadioTimer {
+    fn from(timer: TIMER0) -> Self {
+        RadioTimer::Timer0(timer)
+    }
+}
+
+impl From<TIMER1> for RadioTimer {
+    fn from(timer: TIMER1) -> Self {
+        RadioTimer::Timer1(timer)
+    }
+}
+
+impl From<TIMER2> for RadioTimer {
+    fn from(timer: TIMER2) -> Self {
+        RadioTimer::Timer2(timer)
+    }
+}
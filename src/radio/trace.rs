@@ -0,0 +1,66 @@
+//! Transmit and receive trace hooks
+//!
+//! Applications can observe the frames passing through the driver, for
+//! logging, statistics or protocol analysis. The hooks are called from the
+//! context calling the driver, often the radio interrupt, and should return
+//! promptly.
+//!
+
+use super::{Error, Radio};
+
+/// Metadata of a transmitted or received frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameMetadata<'a> {
+    /// Frame without PHR and FCS
+    pub data: &'a [u8],
+    /// Channel, 11 to 26
+    pub channel: u8,
+    /// Link quality indicator of a received frame
+    pub lqi: Option<u8>,
+}
+
+/// Hooks called by the driver
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceHooks {
+    /// Called when a frame is queued for transmission
+    pub on_transmit: Option<fn(&FrameMetadata)>,
+    /// Called when a frame has been received
+    pub on_receive: Option<fn(&FrameMetadata)>,
+    /// Called when a error is reported
+    pub on_error: Option<fn(Error)>,
+}
+
+impl<const RX: usize> Radio<RX> {
+    /// Set the trace hooks
+    pub fn set_trace_hooks(&mut self, hooks: TraceHooks) {
+        self.hooks = hooks;
+    }
+
+    pub(super) fn trace_transmit(&mut self, length: usize) {
+        if let Some(hook) = self.hooks.on_transmit {
+            let channel = self.get_channel();
+            hook(&FrameMetadata {
+                data: &self.buffers[self.active][1..length - 1],
+                channel,
+                lqi: None,
+            });
+        }
+    }
+
+    pub(super) fn trace_receive(&mut self, length: usize) {
+        if let Some(hook) = self.hooks.on_receive {
+            let channel = self.get_channel();
+            hook(&FrameMetadata {
+                data: &self.buffers[self.active][1..length - 1],
+                channel,
+                lqi: Some(self.buffers[self.active][length - 1]),
+            });
+        }
+    }
+
+    pub(super) fn trace_error(&self, error: Error) {
+        if let Some(hook) = self.hooks.on_error {
+            hook(error);
+        }
+    }
+}
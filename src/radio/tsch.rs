@@ -0,0 +1,398 @@
+//! Time-slotted channel hopping
+//!
+//! Timeslot mode following the IEEE 802.15.4-2015 TSCH timeslot template.
+//! A dedicated TIMER is cleared at the start of every timeslot, the radio is
+//! enabled through PPI at the transmit or receive offset of the template so
+//! the timing does not depend on interrupt latency.
+//!
+//! ```notrust
+//! TIMER COMPARE[0] (timeslot length) → TIMER CLEAR (short), interrupt
+//! TIMER COMPARE[1] (TX or RX offset) → TXEN or RXEN
+//! TIMER COMPARE[2] (RX offset + RX wait) → DISABLE
+//! FRAMESTART → disable GUARD
+//! ```
+//!
+//! | Channel | Event      | Task         | Group |
+//! |---------|------------|--------------|-------|
+//! | 0       | COMPARE[1] | TXEN or RXEN |       |
+//! | 1       | COMPARE[2] | DISABLE      | GUARD |
+//! | 2       | FRAMESTART | disable GUARD|       |
+//!
+//! The channel of a active timeslot is taken from the hopping sequence,
+//! `sequence[(ASN + channel offset) % sequence length]`. Call
+//! `Radio::tsch_timeslot` from the TIMER interrupt, received frames are read
+//! with `Radio::receive` as usual. Acknowledgements are left to the MAC.
+//!
+
+use crate::pac::PPI;
+use crate::phy::check_frame_length;
+
+use super::{Error, Radio, RadioTimer, MAX_PACKET_LENGHT, STATE_SEND, STATE_TSCH};
+
+/// Radio ramp-up time in microseconds
+const RAMP_UP_MICROSECONDS: u32 = 130;
+
+/// Timeslot template, all durations in microseconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimeslotTemplate {
+    /// Start of the timeslot to the start of the transmission
+    pub tx_offset: u32,
+    /// Start of the timeslot to the start of receive
+    pub rx_offset: u32,
+    /// Time to wait for the start of a frame
+    pub rx_wait: u32,
+    /// Timeslot length
+    pub length: u32,
+}
+
+impl Default for TimeslotTemplate {
+    /// The default timeslot template of IEEE 802.15.4-2015
+    fn default() -> Self {
+        Self {
+            tx_offset: 2120,
+            rx_offset: 1020,
+            rx_wait: 2200,
+            length: 10000,
+        }
+    }
+}
+
+/// Kind of link
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkKind {
+    /// Transmit a queued frame
+    Transmit,
+    /// Receive a frame
+    Receive,
+}
+
+/// A link in the slotframe
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Link {
+    /// Timeslot in the slotframe
+    pub timeslot: u16,
+    /// Channel offset into the hopping sequence
+    pub channel_offset: u16,
+    /// Kind of link
+    pub kind: LinkKind,
+}
+
+/// Slotframe, repeating every `length` timeslots
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slotframe {
+    /// Number of timeslots
+    pub length: u16,
+    /// Active timeslots
+    pub links: &'static [Link],
+}
+
+/// PPI resources reserved for TSCH
+///
+/// The channels and group must not be used by anything else.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TschResources {
+    /// Three PPI channels, 0 to 19
+    pub channels: [u8; 3],
+    /// A PPI channel group, 0 to 5. The receive guard group
+    pub group: u8,
+}
+
+impl Default for TschResources {
+    /// Use the channels 8 to 10 and the group 0
+    fn default() -> Self {
+        Self {
+            channels: [8, 9, 10],
+            group: 0,
+        }
+    }
+}
+
+impl TschResources {
+    fn channel_mask(&self, channels: &[usize]) -> u32 {
+        channels
+            .iter()
+            .fold(0, |mask, index| mask | 1 << self.channels[*index])
+    }
+}
+
+/// TSCH configuration
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TschConfig {
+    /// PPI resources
+    pub resources: TschResources,
+    /// Timeslot template
+    pub template: TimeslotTemplate,
+    /// Channel hopping sequence
+    pub hopping_sequence: &'static [u8],
+    /// Slotframe
+    pub slotframe: Slotframe,
+}
+
+/// A active timeslot, reported by `Radio::tsch_timeslot`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timeslot {
+    /// Absolute slot number
+    pub asn: u64,
+    /// Channel used in the timeslot
+    pub channel: u8,
+    /// Kind of link
+    pub kind: LinkKind,
+    /// A frame is transmitted, false for a transmit link without a queued
+    /// frame and for receive links
+    pub transmitting: bool,
+}
+
+/// TSCH state
+pub(super) struct Tsch {
+    config: TschConfig,
+    timer: RadioTimer,
+    /// Absolute slot number of the current timeslot
+    asn: u64,
+    /// Frame to transmit in the next transmit link
+    packet: [u8; MAX_PACKET_LENGHT],
+    pending: bool,
+}
+
+impl<const RX: usize> Radio<RX> {
+    /// Enter TSCH mode, the first timeslot starts immediately with absolute
+    /// slot number `asn`
+    ///
+    /// `timer` is owned by the radio for timing the timeslots, until returned
+    /// by `disable_tsch`, its interrupt shall call `tsch_timeslot`. The radio
+    /// is only enabled in active timeslots.
+    pub fn enable_tsch<T>(&mut self, ppi: &mut PPI, timer: T, config: TschConfig, asn: u64)
+    where
+        T: Into<RadioTimer>,
+    {
+        let owned = timer.into();
+        let timer = owned.registers();
+        self.enter_disabled();
+        self.radio.shorts.reset();
+
+        let resources = config.resources;
+        let group = resources.group as usize;
+        let connections = [
+            (
+                &timer.events_compare[1] as *const _ as u32,
+                &self.radio.tasks_rxen as *const _ as u32,
+            ),
+            (
+                &timer.events_compare[2] as *const _ as u32,
+                &self.radio.tasks_disable as *const _ as u32,
+            ),
+            (
+                &self.radio.events_framestart as *const _ as u32,
+                &ppi.tasks_chg[group].dis as *const _ as u32,
+            ),
+        ];
+        for (index, (event, task)) in connections.iter().enumerate() {
+            let channel = resources.channels[index] as usize;
+            ppi.ch[channel].eep.write(|w| unsafe { w.bits(*event) });
+            ppi.ch[channel].tep.write(|w| unsafe { w.bits(*task) });
+            ppi.fork[channel].tep.reset();
+        }
+        ppi.chenclr
+            .write(|w| unsafe { w.bits(resources.channel_mask(&[0, 1, 2])) });
+        ppi.chg[group].write(|w| unsafe { w.bits(resources.channel_mask(&[1])) });
+
+        timer.tasks_stop.write(|w| w.tasks_stop().set_bit());
+        timer.mode.write(|w| w.mode().timer());
+        timer.bitmode.write(|w| w.bitmode()._32bit());
+        // 1 μs resolution
+        timer.prescaler.write(|w| unsafe { w.prescaler().bits(4) });
+        timer.shorts.write(|w| w.compare0_clear().enabled());
+        timer.cc[0].write(|w| unsafe { w.bits(config.template.length) });
+        timer.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+        timer.intenset.write(|w| w.compare0().set());
+        for event in timer.events_compare.iter() {
+            event.reset();
+        }
+
+        self.tsch = Some(Tsch {
+            config,
+            timer: owned,
+            // Incremented at the start of the first timeslot
+            asn: asn.wrapping_sub(1),
+            packet: [0u8; MAX_PACKET_LENGHT],
+            pending: false,
+        });
+        self.state = STATE_TSCH;
+        // Start the first timeslot at once
+        timer.cc[0].write(|w| unsafe { w.bits(1) });
+        timer.tasks_clear.write(|w| w.tasks_clear().set_bit());
+        timer.tasks_start.write(|w| w.tasks_start().set_bit());
+    }
+
+    /// Leave TSCH mode, releasing the PPI resources and return to receive
+    ///
+    /// # Return
+    ///
+    /// Returns the TIMER given to `enable_tsch`, stopped.
+    ///
+    pub fn disable_tsch(&mut self, ppi: &mut PPI) -> Option<RadioTimer> {
+        self.tsch.take().map(|tsch| {
+            let resources = tsch.config.resources;
+            ppi.chenclr
+                .write(|w| unsafe { w.bits(resources.channel_mask(&[0, 1, 2])) });
+            ppi.chg[resources.group as usize].reset();
+            tsch.timer.tasks_stop.write(|w| w.tasks_stop().set_bit());
+            tsch.timer
+                .intenclr
+                .write(|w| unsafe { w.bits(0xffff_ffff) });
+            tsch.timer.shorts.reset();
+            self.state = 0;
+            self.radio.shorts.reset();
+            self.configure_interrupts();
+            self.receive_prepare();
+            tsch.timer
+        })
+    }
+
+    /// Absolute slot number of the current timeslot
+    pub fn tsch_asn(&self) -> Option<u64> {
+        self.tsch.as_ref().map(|tsch| tsch.asn)
+    }
+
+    /// Lengthen, or shorten, the current timeslot by `correction`
+    /// microseconds, to synchronise with the time source neighbour
+    pub fn set_tsch_correction(&mut self, correction: i32) {
+        if let Some(tsch) = &self.tsch {
+            let length = tsch.config.template.length as i32 + correction;
+            tsch.timer.cc[0].write(|w| unsafe { w.bits(length as u32) });
+        }
+    }
+
+    /// Queue a frame for the next transmit link
+    ///
+    /// `data` should contain the packet payload to be sent without the PHR
+    /// and FCS.
+    ///
+    /// # Return
+    ///
+    /// Returns the number of bytes queued for transmission,
+    /// `Error::InvalidState` if TSCH is not enabled or `Error::InvalidLength`
+    /// for frames refused by `check_frame_length`.
+    ///
+    pub fn tsch_queue_transmission(&mut self, data: &[u8]) -> Result<usize, Error> {
+        check_frame_length(data.len())?;
+        let tsch = self.tsch.as_mut().ok_or(Error::InvalidState)?;
+        let tx_length = data.len() + 2;
+        tsch.packet[0] = tx_length as u8;
+        tsch.packet[1..(tx_length - 1)].copy_from_slice(data);
+        tsch.pending = true;
+        Ok(data.len())
+    }
+
+    /// Check if a queued frame is waiting for a transmit link
+    pub fn is_tsch_pending(&self) -> bool {
+        matches!(&self.tsch, Some(tsch) if tsch.pending)
+    }
+
+    /// Start the next timeslot, call this from the TIMER interrupt
+    ///
+    /// # Return
+    ///
+    /// Returns the timeslot if it is active.
+    ///
+    pub fn tsch_timeslot(&mut self, ppi: &mut PPI) -> Option<Timeslot> {
+        let (timer, config) = match &self.tsch {
+            Some(tsch) => (tsch.timer.registers(), tsch.config),
+            None => return None,
+        };
+        if timer.events_compare[0]
+            .read()
+            .events_compare()
+            .bit_is_clear()
+        {
+            return None;
+        }
+        timer.events_compare[0].reset();
+        timer.events_compare[1].reset();
+        timer.events_compare[2].reset();
+        let resources = config.resources;
+        ppi.chenclr
+            .write(|w| unsafe { w.bits(resources.channel_mask(&[0, 1, 2])) });
+        // A correction applies to a single timeslot
+        timer.cc[0].write(|w| unsafe { w.bits(config.template.length) });
+        self.enter_disabled();
+        self.radio.shorts.reset();
+        self.state = STATE_TSCH;
+
+        let asn = match &mut self.tsch {
+            Some(tsch) => {
+                tsch.asn = tsch.asn.wrapping_add(1);
+                tsch.asn
+            }
+            None => return None,
+        };
+        let slotframe = config.slotframe;
+        let timeslot = (asn % u64::from(slotframe.length.max(1))) as u16;
+        let link = slotframe
+            .links
+            .iter()
+            .find(|link| link.timeslot == timeslot)?;
+        let sequence = config.hopping_sequence;
+        if sequence.is_empty() {
+            return None;
+        }
+        let index =
+            (asn.wrapping_add(u64::from(link.channel_offset)) % sequence.len() as u64) as usize;
+        let channel = sequence[index];
+        self.set_channel(channel).ok()?;
+
+        let template = config.template;
+        let pending = self.is_tsch_pending();
+        let transmitting = link.kind == LinkKind::Transmit && pending;
+        let enable_channel = resources.channels[0] as usize;
+        if transmitting {
+            let length = match &mut self.tsch {
+                Some(tsch) => {
+                    tsch.pending = false;
+                    self.buffers[self.active].copy_from_slice(&tsch.packet);
+                    tsch.packet[0] as usize
+                }
+                None => return None,
+            };
+            self.set_packet_pointer();
+            self.record_transmission(length);
+            self.state |= STATE_SEND;
+            ppi.ch[enable_channel]
+                .tep
+                .write(|w| unsafe { w.bits(&self.radio.tasks_txen as *const _ as u32) });
+            timer.cc[1].write(|w| unsafe {
+                w.bits(template.tx_offset.saturating_sub(RAMP_UP_MICROSECONDS))
+            });
+            ppi.chenset
+                .write(|w| unsafe { w.bits(resources.channel_mask(&[0])) });
+        } else if link.kind == LinkKind::Receive {
+            self.set_packet_pointer();
+            ppi.ch[enable_channel]
+                .tep
+                .write(|w| unsafe { w.bits(&self.radio.tasks_rxen as *const _ as u32) });
+            timer.cc[1].write(|w| unsafe {
+                w.bits(template.rx_offset.saturating_sub(RAMP_UP_MICROSECONDS))
+            });
+            timer.cc[2].write(|w| unsafe { w.bits(template.rx_offset + template.rx_wait) });
+            ppi.tasks_chg[resources.group as usize]
+                .en
+                .write(|w| unsafe { w.bits(1) });
+            ppi.chenset
+                .write(|w| unsafe { w.bits(resources.channel_mask(&[0, 2])) });
+        }
+        if transmitting || link.kind == LinkKind::Receive {
+            self.radio
+                .shorts
+                .write(|w| w.ready_start().enabled().phyend_disable().enabled());
+        }
+        Some(Timeslot {
+            asn,
+            channel,
+            kind: link.kind,
+            transmitting,
+        })
+    }
+}
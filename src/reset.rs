@@ -0,0 +1,234 @@
+//! # Reset reason
+//!
+//! Captures the reset reason from the RESETREAS register of the POWER
+//! peripheral at startup, together with the last state of the radio driver
+//! before the reset, so that field failures such as watchdog resets during
+//! a transmission or lockups can be attributed.
+//!
+//! The radio records its state in a `StateRecord` set with
+//! `Radio::set_state_record`. The record shall be placed in RAM that is not
+//! initialised at startup, so that it survives resets which retain RAM,
+//! with cortex-m-rt a `.uninit` section.
+//!
+//! ```notrust
+//! #[link_section = ".uninit.psila"]
+//! static mut RECORD: StateRecord = StateRecord::new();
+//! ```
+//!
+//! Call `capture` early at startup, before the radio is created. RESETREAS
+//! is cumulative and is cleared by `capture`. The RAM is not retained on
+//! power-on and brown-out resets, the record is then ignored.
+//!
+
+use core::ptr;
+
+use crate::pac::POWER;
+
+/// Marks a valid state record
+const RECORD_MAGIC: u32 = 0x5053_4c41;
+
+/// Reset from the reset pin
+const RESET_PIN: u32 = 1 << 0;
+/// Reset from the watchdog
+const RESET_DOG: u32 = 1 << 1;
+/// Soft reset, AIRCR.SYSRESETREQ
+const RESET_SREQ: u32 = 1 << 2;
+/// Reset from a CPU lockup
+const RESET_LOCKUP: u32 = 1 << 3;
+/// Wakeup from System OFF by a GPIO DETECT signal
+const RESET_OFF: u32 = 1 << 16;
+/// Wakeup from System OFF by LPCOMP
+const RESET_LPCOMP: u32 = 1 << 17;
+/// Wakeup from System OFF by the debug interface
+const RESET_DIF: u32 = 1 << 18;
+/// Wakeup from System OFF by NFC field detect
+const RESET_NFC: u32 = 1 << 19;
+/// Wakeup from System OFF by VBUS rising into the valid range
+const RESET_VBUS: u32 = 1 << 20;
+/// Reasons of resets where the RAM is retained
+const RESET_RETAINED: u32 = RESET_PIN | RESET_DOG | RESET_SREQ | RESET_LOCKUP;
+
+/// Reset reasons, the content of RESETREAS
+///
+/// Several reasons may be reported, no reason means a power-on or brown-out
+/// reset.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ResetReason(u32);
+
+impl ResetReason {
+    /// Reset reasons from the RESETREAS value `bits`
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// The RESETREAS value
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Power-on or brown-out reset, no reason is reported
+    pub fn is_power_on(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Reset from the reset pin
+    pub fn is_pin(&self) -> bool {
+        self.0 & RESET_PIN != 0
+    }
+
+    /// Reset from the watchdog
+    pub fn is_watchdog(&self) -> bool {
+        self.0 & RESET_DOG != 0
+    }
+
+    /// Soft reset requested by the software
+    pub fn is_soft(&self) -> bool {
+        self.0 & RESET_SREQ != 0
+    }
+
+    /// Reset from a CPU lockup
+    pub fn is_lockup(&self) -> bool {
+        self.0 & RESET_LOCKUP != 0
+    }
+
+    /// Wakeup from System OFF, by GPIO, LPCOMP, debug interface, NFC or VBUS
+    pub fn is_wakeup(&self) -> bool {
+        self.0 & (RESET_OFF | RESET_LPCOMP | RESET_DIF | RESET_NFC | RESET_VBUS) != 0
+    }
+
+    /// The RAM was retained through the reset
+    fn is_retained(&self) -> bool {
+        self.0 & RESET_RETAINED != 0
+    }
+}
+
+/// State of the radio driver
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DriverState {
+    /// Disabled
+    Idle,
+    /// Receiving
+    Receiving,
+    /// Transmitting, including the CCA before the transmission
+    Transmitting,
+    /// Energy detection
+    EnergyDetect,
+    /// Clear channel assessment
+    Cca,
+}
+
+impl DriverState {
+    fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0 => Some(DriverState::Idle),
+            1 => Some(DriverState::Receiving),
+            2 => Some(DriverState::Transmitting),
+            3 => Some(DriverState::EnergyDetect),
+            4 => Some(DriverState::Cca),
+            _ => None,
+        }
+    }
+}
+
+/// Last state of the radio driver, kept in RAM through resets
+#[repr(C)]
+pub struct StateRecord {
+    magic: u32,
+    state: u32,
+    channel: u32,
+    check: u32,
+}
+
+impl StateRecord {
+    /// Create a invalid record
+    pub const fn new() -> Self {
+        Self {
+            magic: 0,
+            state: 0,
+            channel: 0,
+            check: 0,
+        }
+    }
+
+    /// Record the driver state and channel
+    pub fn update(&mut self, state: DriverState, channel: u8) {
+        let state = state as u32;
+        let channel = u32::from(channel);
+        // Volatile, the record is read after a reset
+        unsafe {
+            ptr::write_volatile(&mut self.magic, RECORD_MAGIC);
+            ptr::write_volatile(&mut self.state, state);
+            ptr::write_volatile(&mut self.channel, channel);
+            ptr::write_volatile(&mut self.check, Self::check(state, channel));
+        }
+    }
+
+    /// Read the recorded state and channel, if the record is valid
+    pub fn read(&self) -> Option<(DriverState, u8)> {
+        let (magic, state, channel, check) = unsafe {
+            (
+                ptr::read_volatile(&self.magic),
+                ptr::read_volatile(&self.state),
+                ptr::read_volatile(&self.channel),
+                ptr::read_volatile(&self.check),
+            )
+        };
+        if magic != RECORD_MAGIC || check != Self::check(state, channel) || channel > 0xff {
+            return None;
+        }
+        DriverState::from_bits(state).map(|state| (state, channel as u8))
+    }
+
+    /// Invalidate the record
+    pub fn clear(&mut self) {
+        unsafe {
+            ptr::write_volatile(&mut self.magic, 0);
+        }
+    }
+
+    fn check(state: u32, channel: u32) -> u32 {
+        !(RECORD_MAGIC ^ state ^ channel.rotate_left(16))
+    }
+}
+
+impl Default for StateRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reset diagnostics captured at startup
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ResetDiagnostics {
+    /// Reset reasons
+    pub reason: ResetReason,
+    /// State of the radio driver and channel before the reset, if known
+    pub last_state: Option<(DriverState, u8)>,
+}
+
+impl ResetDiagnostics {
+    /// The watchdog reset the device while the radio was transmitting
+    pub fn is_watchdog_during_transmission(&self) -> bool {
+        self.reason.is_watchdog() && matches!(self.last_state, Some((DriverState::Transmitting, _)))
+    }
+}
+
+/// Read and clear RESETREAS and the last driver state in `record`
+///
+/// The record is invalidated, the radio writes it again when it is used.
+pub fn capture(power: &POWER, record: &mut StateRecord) -> ResetDiagnostics {
+    let bits = power.resetreas.read().bits();
+    // Bits are cleared by writing one
+    power.resetreas.write(|w| unsafe { w.bits(bits) });
+    let reason = ResetReason::from_bits(bits);
+    let last_state = if reason.is_retained() {
+        record.read()
+    } else {
+        None
+    };
+    record.clear();
+    ResetDiagnostics { reason, last_state }
+}
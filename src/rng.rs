@@ -0,0 +1,78 @@
+//! # Random number generator
+//!
+//! The RNG peripheral generates true random numbers from thermal noise. Bias
+//! correction is enabled, giving uniformly distributed octets at the cost of
+//! a slower generation rate.
+//!
+//! With the `rand_core` feature the generator implements `RngCore` and
+//! `CryptoRng`, so crates of the rand ecosystem can use it directly, for key
+//! generation or random backoff.
+//!
+
+use crate::pac::RNG;
+
+/// Random number generator using the RNG peripheral
+pub struct Rng {
+    rng: RNG,
+}
+
+impl Rng {
+    /// Enable bias correction and take the RNG peripheral
+    pub fn new(rng: RNG) -> Self {
+        rng.config.write(|w| w.dercen().enabled());
+        rng.shorts.reset();
+        rng.intenclr.write(|w| w.valrdy().clear());
+        Self { rng }
+    }
+
+    /// Generate a random octet
+    pub fn random_u8(&mut self) -> u8 {
+        self.rng.events_valrdy.reset();
+        self.rng.tasks_start.write(|w| w.tasks_start().set_bit());
+        while self.rng.events_valrdy.read().events_valrdy().bit_is_clear() {}
+        self.rng.tasks_stop.write(|w| w.tasks_stop().set_bit());
+        self.rng.value.read().value().bits()
+    }
+
+    /// Generate a random 32-bit word
+    pub fn random_u32(&mut self) -> u32 {
+        let mut octets = [0u8; 4];
+        self.fill(&mut octets);
+        u32::from_le_bytes(octets)
+    }
+
+    /// Fill `buffer` with random octets
+    pub fn fill(&mut self, buffer: &mut [u8]) {
+        for octet in buffer.iter_mut() {
+            *octet = self.random_u8();
+        }
+    }
+
+    /// Release the RNG peripheral
+    pub fn free(self) -> RNG {
+        self.rng
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl rand_core::RngCore for Rng {
+    fn next_u32(&mut self) -> u32 {
+        self.random_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_u32(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.fill(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill(dest);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl rand_core::CryptoRng for Rng {}
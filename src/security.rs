@@ -0,0 +1,744 @@
+//! # MAC security policy
+//!
+//! Incoming frame security checks of IEEE 802.15.4-2011 section 7.2.3, on
+//! top of the CCM* mode of the `crypto` module. A received frame is checked
+//! against the security level table, the key table and the device table of
+//! a `SecurityPolicy`, so that a frame with a correct MIC is still refused
+//! when it is secured with a weaker level, or with another key, than the
+//! policy requires for its frame type.
+//!
+//! The tables are slices, typically constants in flash. Frames of the 2003
+//! and 2015 frame versions are not supported. The frame counter of a
+//! accepted frame is returned, replay protection is left to the caller.
+//!
+
+use crate::crypto::ccm::{decrypt_frame, Nonce};
+use crate::crypto::{BlockCipher, CryptoError, Key};
+
+/// Security enabled bit of the frame control field
+const SECURITY_ENABLED: u16 = 1 << 3;
+/// PAN identifier compression bit of the frame control field
+const PAN_ID_COMPRESSION: u16 = 1 << 6;
+/// Frame version of IEEE 802.15.4-2006 and 2011
+const FRAME_VERSION_2006: u16 = 1;
+/// Frame control, sequence number
+const HEADER_BASE_LENGTH: usize = 3;
+/// Security control, frame counter
+const AUXILIARY_BASE_LENGTH: usize = 5;
+/// Encryption bit of the security level
+const LEVEL_ENCRYPTION: u8 = 0b100;
+/// MIC length bits of the security level
+const LEVEL_MIC: u8 = 0b011;
+
+/// Errors returned by the security checks, named as the MLME status codes
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SecurityError {
+    /// The frame is too short or malformed
+    InvalidFrame,
+    /// The frame uses the security of a unsupported frame version
+    UnsupportedLegacy,
+    /// The frame is secured with security level zero, or with the security
+    /// of the 2015 frame version
+    UnsupportedSecurity,
+    /// No key matches the key identifier of the frame
+    UnavailableKey,
+    /// The extended address of the source is unknown
+    UnavailableDevice,
+    /// The security level is below the minimum for the frame type
+    ImproperSecurityLevel,
+    /// The key is not allowed for the frame type
+    ImproperKeyType,
+    /// The frame counter is exhausted
+    CounterError,
+    /// Decryption failed or the MIC did not match
+    Crypto(CryptoError),
+}
+
+impl From<CryptoError> for SecurityError {
+    fn from(error: CryptoError) -> Self {
+        SecurityError::Crypto(error)
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for SecurityError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SecurityError::InvalidFrame => f.write_str("invalid frame"),
+            SecurityError::UnsupportedLegacy => f.write_str("unsupported frame version"),
+            SecurityError::UnsupportedSecurity => f.write_str("unsupported security"),
+            SecurityError::UnavailableKey => f.write_str("unavailable key"),
+            SecurityError::UnavailableDevice => f.write_str("unavailable device"),
+            SecurityError::ImproperSecurityLevel => f.write_str("improper security level"),
+            SecurityError::ImproperKeyType => f.write_str("improper key type"),
+            SecurityError::CounterError => f.write_str("frame counter exhausted"),
+            SecurityError::Crypto(error) => write!(f, "security error, {}", error),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::error::Error for SecurityError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            SecurityError::Crypto(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// MAC frame types
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameType {
+    /// Beacon
+    Beacon,
+    /// Data
+    Data,
+    /// Acknowledgement
+    Acknowledgement,
+    /// MAC command
+    Command,
+}
+
+impl FrameType {
+    fn from_bits(bits: u16) -> Option<Self> {
+        match bits {
+            0 => Some(FrameType::Beacon),
+            1 => Some(FrameType::Data),
+            2 => Some(FrameType::Acknowledgement),
+            3 => Some(FrameType::Command),
+            _ => None,
+        }
+    }
+}
+
+/// Check that security level `level` is at least as strong as `minimum`
+///
+/// A level satisfies the minimum if it encrypts when the minimum does and
+/// its MIC is at least as long.
+pub fn satisfies_level(level: u8, minimum: u8) -> bool {
+    level & LEVEL_ENCRYPTION >= minimum & LEVEL_ENCRYPTION
+        && level & LEVEL_MIC >= minimum & LEVEL_MIC
+}
+
+/// Length of the MIC of security level `level`
+fn mic_length(level: u8) -> usize {
+    match level & LEVEL_MIC {
+        0 => 0,
+        1 => 4,
+        2 => 8,
+        _ => 16,
+    }
+}
+
+/// Minimum security for a frame type, a entry of macSecurityLevelTable
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SecurityLevelDescriptor {
+    /// Frame type
+    pub frame_type: FrameType,
+    /// Command frame identifier for `FrameType::Command`, None matches all
+    /// commands
+    pub command: Option<u8>,
+    /// Minimum security level, 0 to 7
+    pub minimum: u8,
+    /// Exempt devices may send the frame unsecured
+    pub device_override: bool,
+}
+
+impl SecurityLevelDescriptor {
+    fn matches(&self, frame_type: FrameType, command: Option<u8>) -> bool {
+        self.frame_type == frame_type && (self.command.is_none() || self.command == command)
+    }
+}
+
+/// Frame type a key may be used for
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyUsage {
+    /// Frame type
+    pub frame_type: FrameType,
+    /// Command frame identifier for `FrameType::Command`, None allows all
+    /// commands
+    pub command: Option<u8>,
+}
+
+/// Key identifier of the auxiliary security header
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyIdentifier {
+    /// Key identifier mode 0, the key is implicit from the source device,
+    /// given by its extended address
+    Implicit(u64),
+    /// Key identifier mode 1, key index with the default key source
+    Index(u8),
+    /// Key identifier mode 2, four octet key source and key index, the key
+    /// source read little endian from the frame
+    Source4(u32, u8),
+    /// Key identifier mode 3, eight octet key source and key index, the key
+    /// source read little endian from the frame
+    Source8(u64, u8),
+}
+
+/// A key and its usage policy, a entry of macKeyTable
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyDescriptor<'a> {
+    /// Identifier of the key in incoming frames
+    pub id: KeyIdentifier,
+    /// The key
+    pub key: Key,
+    /// Frame types the key may be used for
+    pub usage: &'a [KeyUsage],
+}
+
+impl KeyDescriptor<'_> {
+    fn allows(&self, frame_type: FrameType, command: Option<u8>) -> bool {
+        self.usage.iter().any(|usage| {
+            usage.frame_type == frame_type && (usage.command.is_none() || usage.command == command)
+        })
+    }
+}
+
+/// A known device, a entry of macDeviceTable
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceDescriptor {
+    /// PAN identifier
+    pub pan_id: u16,
+    /// Short address
+    pub short_address: u16,
+    /// Extended address
+    pub extended_address: u64,
+    /// The device may send frames below the minimum security level, where
+    /// the level descriptor allows it
+    pub exempt: bool,
+}
+
+/// Security tables checked for incoming frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SecurityPolicy<'a> {
+    /// Minimum security per frame type, the first matching entry applies
+    pub levels: &'a [SecurityLevelDescriptor],
+    /// Minimum security level for frame types without a entry in `levels`
+    pub default_minimum: u8,
+    /// Keys for incoming frames
+    pub keys: &'a [KeyDescriptor<'a>],
+    /// Known devices, for the extended address of sources using a short
+    /// address and for exempt devices
+    pub devices: &'a [DeviceDescriptor],
+}
+
+/// Source address of a frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Source {
+    Short(u16, u16),
+    Extended(u16, u64),
+}
+
+/// Parsed MAC header
+struct Header {
+    frame_type: FrameType,
+    frame_control: u16,
+    source: Option<Source>,
+    /// Length of the MAC header without the auxiliary security header
+    length: usize,
+}
+
+/// Read a little endian field of `N` octets at `offset`
+fn read_le<const N: usize>(frame: &[u8], offset: usize) -> Result<[u8; N], SecurityError> {
+    let mut field = [0u8; N];
+    field.copy_from_slice(
+        frame
+            .get(offset..offset + N)
+            .ok_or(SecurityError::InvalidFrame)?,
+    );
+    Ok(field)
+}
+
+/// Length of a address for the address mode
+fn address_length(mode: u16) -> usize {
+    match mode {
+        0b10 => 2,
+        0b11 => 8,
+        _ => 0,
+    }
+}
+
+fn parse_header(frame: &[u8]) -> Result<Header, SecurityError> {
+    let frame_control = u16::from_le_bytes(read_le(frame, 0)?);
+    let frame_type =
+        FrameType::from_bits(frame_control & 0b111).ok_or(SecurityError::InvalidFrame)?;
+    let destination_mode = (frame_control >> 10) & 0b11;
+    let source_mode = (frame_control >> 14) & 0b11;
+    let mut offset = HEADER_BASE_LENGTH;
+    let mut pan_id = None;
+    if destination_mode != 0 {
+        pan_id = Some(u16::from_le_bytes(read_le(frame, offset)?));
+        offset += 2 + address_length(destination_mode);
+    }
+    let mut source = None;
+    if source_mode != 0 {
+        if frame_control & PAN_ID_COMPRESSION == 0 || pan_id.is_none() {
+            pan_id = Some(u16::from_le_bytes(read_le(frame, offset)?));
+            offset += 2;
+        }
+        let pan_id = pan_id.unwrap_or_default();
+        source = Some(match address_length(source_mode) {
+            2 => Source::Short(pan_id, u16::from_le_bytes(read_le(frame, offset)?)),
+            8 => Source::Extended(pan_id, u64::from_le_bytes(read_le(frame, offset)?)),
+            _ => return Err(SecurityError::InvalidFrame),
+        });
+        offset += address_length(source_mode);
+    }
+    if offset > frame.len() {
+        return Err(SecurityError::InvalidFrame);
+    }
+    Ok(Header {
+        frame_type,
+        frame_control,
+        source,
+        length: offset,
+    })
+}
+
+/// Parsed auxiliary security header
+struct Auxiliary {
+    level: u8,
+    frame_counter: u32,
+    key_id: KeyIdentifier,
+    length: usize,
+}
+
+fn parse_auxiliary(
+    frame: &[u8],
+    offset: usize,
+    source: Option<u64>,
+) -> Result<Auxiliary, SecurityError> {
+    let control = *frame.get(offset).ok_or(SecurityError::InvalidFrame)?;
+    let frame_counter = u32::from_le_bytes(read_le(frame, offset + 1)?);
+    let key_offset = offset + AUXILIARY_BASE_LENGTH;
+    let (key_id, key_length) = match (control >> 3) & 0b11 {
+        0 => (
+            KeyIdentifier::Implicit(source.ok_or(SecurityError::UnavailableDevice)?),
+            0,
+        ),
+        1 => (KeyIdentifier::Index(read_le::<1>(frame, key_offset)?[0]), 1),
+        2 => (
+            KeyIdentifier::Source4(
+                u32::from_le_bytes(read_le(frame, key_offset)?),
+                read_le::<1>(frame, key_offset + 4)?[0],
+            ),
+            5,
+        ),
+        _ => (
+            KeyIdentifier::Source8(
+                u64::from_le_bytes(read_le(frame, key_offset)?),
+                read_le::<1>(frame, key_offset + 8)?[0],
+            ),
+            9,
+        ),
+    };
+    Ok(Auxiliary {
+        level: control & (LEVEL_ENCRYPTION | LEVEL_MIC),
+        frame_counter,
+        key_id,
+        length: AUXILIARY_BASE_LENGTH + key_length,
+    })
+}
+
+/// A frame which passed the security checks
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Unsecured {
+    /// Security level of the frame, 0 for unsecured frames
+    pub level: u8,
+    /// Frame counter of a secured frame
+    pub frame_counter: Option<u32>,
+    /// Offset of the MAC payload, after the auxiliary security header
+    pub payload: usize,
+    /// Length of the MAC payload, without the MIC
+    pub payload_length: usize,
+}
+
+impl<'a> SecurityPolicy<'a> {
+    /// Minimum security for a frame type
+    fn level_descriptor(&self, frame_type: FrameType, command: Option<u8>) -> (u8, bool) {
+        self.levels
+            .iter()
+            .find(|descriptor| descriptor.matches(frame_type, command))
+            .map_or((self.default_minimum, false), |descriptor| {
+                (descriptor.minimum, descriptor.device_override)
+            })
+    }
+
+    /// Device sending from `source`
+    fn device(&self, source: Option<Source>) -> Option<&DeviceDescriptor> {
+        self.devices.iter().find(|device| match source {
+            Some(Source::Short(pan_id, address)) => {
+                device.pan_id == pan_id && device.short_address == address
+            }
+            Some(Source::Extended(_, address)) => device.extended_address == address,
+            None => false,
+        })
+    }
+
+    /// Extended address of the source, for the nonce and implicit keys
+    fn extended_source(&self, source: Option<Source>) -> Option<u64> {
+        match source {
+            Some(Source::Extended(_, address)) => Some(address),
+            _ => self.device(source).map(|device| device.extended_address),
+        }
+    }
+
+    /// Check a received frame against the policy and decrypt it in place
+    ///
+    /// `frame` is the frame without PHR and FCS. Unsecured frames pass if
+    /// the minimum security level for the frame type is zero, or if the
+    /// level descriptor allows exempt devices and the source is exempt.
+    /// Secured frames pass if the security level satisfies the minimum, the
+    /// key is found and allows the frame type, and the MIC matches. The
+    /// cipher key is set to the key of the frame.
+    ///
+    /// # Return
+    ///
+    /// Returns the security level, the frame counter and the location of the
+    /// payload, decrypted in place, or the reason the frame was refused.
+    ///
+    pub fn unsecure_frame<C: BlockCipher>(
+        &self,
+        cipher: &mut C,
+        frame: &mut [u8],
+    ) -> Result<Unsecured, SecurityError> {
+        let header = parse_header(frame)?;
+        if header.frame_control & SECURITY_ENABLED == 0 {
+            let command = match header.frame_type {
+                FrameType::Command => Some(
+                    *frame
+                        .get(header.length)
+                        .ok_or(SecurityError::InvalidFrame)?,
+                ),
+                _ => None,
+            };
+            let (minimum, device_override) = self.level_descriptor(header.frame_type, command);
+            let exempt = device_override
+                && self
+                    .device(header.source)
+                    .is_some_and(|device| device.exempt);
+            if minimum != 0 && !exempt {
+                return Err(SecurityError::ImproperSecurityLevel);
+            }
+            return Ok(Unsecured {
+                level: 0,
+                frame_counter: None,
+                payload: header.length,
+                payload_length: frame.len() - header.length,
+            });
+        }
+        match (header.frame_control >> 12) & 0b11 {
+            FRAME_VERSION_2006 => (),
+            0 => return Err(SecurityError::UnsupportedLegacy),
+            _ => return Err(SecurityError::UnsupportedSecurity),
+        }
+        let source = self.extended_source(header.source);
+        let auxiliary = parse_auxiliary(frame, header.length, source)?;
+        if auxiliary.level == 0 {
+            return Err(SecurityError::UnsupportedSecurity);
+        }
+        let payload = header.length + auxiliary.length;
+        let mic_length = mic_length(auxiliary.level);
+        let command = match header.frame_type {
+            FrameType::Command => Some(*frame.get(payload).ok_or(SecurityError::InvalidFrame)?),
+            _ => None,
+        };
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key.id == auxiliary.key_id)
+            .ok_or(SecurityError::UnavailableKey)?;
+        let (minimum, _) = self.level_descriptor(header.frame_type, command);
+        if !satisfies_level(auxiliary.level, minimum) {
+            return Err(SecurityError::ImproperSecurityLevel);
+        }
+        if !key.allows(header.frame_type, command) {
+            return Err(SecurityError::ImproperKeyType);
+        }
+        if auxiliary.frame_counter == u32::MAX {
+            return Err(SecurityError::CounterError);
+        }
+        let source = source.ok_or(SecurityError::UnavailableDevice)?;
+        let mut nonce: Nonce = [0u8; 13];
+        nonce[..8].copy_from_slice(&source.to_be_bytes());
+        nonce[8..12].copy_from_slice(&auxiliary.frame_counter.to_be_bytes());
+        nonce[12] = auxiliary.level;
+        // Without encryption the whole frame is authenticated, the command
+        // frame identifier is never encrypted
+        let aad_length = if auxiliary.level & LEVEL_ENCRYPTION == 0 {
+            frame
+                .len()
+                .checked_sub(mic_length)
+                .ok_or(SecurityError::InvalidFrame)?
+        } else if command.is_some() {
+            payload + 1
+        } else {
+            payload
+        };
+        cipher.set_key(&key.key);
+        decrypt_frame(cipher, &nonce, frame, aad_length, mic_length)?;
+        Ok(Unsecured {
+            level: auxiliary.level,
+            frame_counter: Some(auxiliary.frame_counter),
+            payload,
+            payload_length: frame.len() - payload - mic_length,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "soft-crypto"))]
+mod tests {
+    use super::*;
+    use crate::crypto::soft::SoftAes;
+
+    /// Key of the IEEE 802.15.4 annex C test vectors
+    const KEY: Key = [
+        0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xcb, 0xcc, 0xcd, 0xce,
+        0xcf,
+    ];
+    /// Source of the annex C test vectors
+    const SOURCE: u64 = 0xacde_4800_0000_0001;
+
+    const BEACONS: [KeyUsage; 1] = [KeyUsage {
+        frame_type: FrameType::Beacon,
+        command: None,
+    }];
+    const COMMANDS: [KeyUsage; 1] = [KeyUsage {
+        frame_type: FrameType::Command,
+        command: Some(0x01),
+    }];
+    const KEYS: [KeyDescriptor; 1] = [KeyDescriptor {
+        id: KeyIdentifier::Implicit(SOURCE),
+        key: KEY,
+        usage: &BEACONS,
+    }];
+    const COMMAND_KEYS: [KeyDescriptor; 1] = [KeyDescriptor {
+        id: KeyIdentifier::Implicit(SOURCE),
+        key: KEY,
+        usage: &COMMANDS,
+    }];
+
+    fn level(frame_type: FrameType, minimum: u8) -> SecurityLevelDescriptor {
+        SecurityLevelDescriptor {
+            frame_type,
+            command: None,
+            minimum,
+            device_override: false,
+        }
+    }
+
+    /// Beacon frame of annex C.2.1, security level 2
+    fn beacon() -> [u8; 34] {
+        [
+            0x08, 0xd0, 0x84, 0x21, 0x43, 0x01, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0x02,
+            0x05, 0x00, 0x00, 0x00, 0x55, 0xcf, 0x00, 0x00, 0x51, 0x52, 0x53, 0x54, 0x22, 0x3b,
+            0xc1, 0xec, 0x84, 0x1a, 0xb5, 0x53,
+        ]
+    }
+
+    /// Association request command frame of annex C.2.3, security level 6
+    fn command() -> [u8; 38] {
+        [
+            0x2b, 0xdc, 0x84, 0x21, 0x43, 0x02, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0xff,
+            0xff, 0x01, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0x06, 0x05, 0x00, 0x00, 0x00,
+            0x01, 0xd8, 0x4f, 0xde, 0x52, 0x90, 0x61, 0xf9, 0xc6, 0xf1,
+        ]
+    }
+
+    /// Unsecured data frame from short address 0x0001 in PAN 0x4321
+    fn data() -> [u8; 12] {
+        [
+            0x41, 0x88, 0x01, 0x21, 0x43, 0xff, 0xff, 0x01, 0x00, 0xaa, 0xbb, 0xcc,
+        ]
+    }
+
+    fn policy<'a>(
+        levels: &'a [SecurityLevelDescriptor],
+        keys: &'a [KeyDescriptor<'a>],
+    ) -> SecurityPolicy<'a> {
+        SecurityPolicy {
+            levels,
+            default_minimum: 0,
+            keys,
+            devices: &[],
+        }
+    }
+
+    #[test]
+    fn level_comparison() {
+        assert!(satisfies_level(6, 2));
+        assert!(satisfies_level(2, 2));
+        assert!(!satisfies_level(1, 2));
+        assert!(!satisfies_level(3, 4));
+        assert!(satisfies_level(5, 4));
+        assert!(satisfies_level(0, 0));
+    }
+
+    #[test]
+    fn authenticated_beacon() {
+        let mut cipher = SoftAes::new(&[0; 16]);
+        let mut frame = beacon();
+        let levels = [level(FrameType::Beacon, 2)];
+        let unsecured = policy(&levels, &KEYS)
+            .unsecure_frame(&mut cipher, &mut frame)
+            .unwrap();
+        assert_eq!(
+            unsecured,
+            Unsecured {
+                level: 2,
+                frame_counter: Some(5),
+                payload: 18,
+                payload_length: 8,
+            }
+        );
+        assert_eq!(frame, beacon());
+    }
+
+    #[test]
+    fn encrypted_command() {
+        let mut cipher = SoftAes::new(&[0; 16]);
+        let mut frame = command();
+        let levels = [SecurityLevelDescriptor {
+            frame_type: FrameType::Command,
+            command: Some(0x01),
+            minimum: 5,
+            device_override: false,
+        }];
+        let unsecured = policy(&levels, &COMMAND_KEYS)
+            .unsecure_frame(&mut cipher, &mut frame)
+            .unwrap();
+        assert_eq!(unsecured.payload, 28);
+        assert_eq!(unsecured.payload_length, 2);
+        assert_eq!(frame[28..30], [0x01, 0xce]);
+    }
+
+    #[test]
+    fn level_below_minimum() {
+        let mut cipher = SoftAes::new(&[0; 16]);
+        let mut frame = beacon();
+        let levels = [level(FrameType::Beacon, 6)];
+        assert_eq!(
+            policy(&levels, &KEYS).unsecure_frame(&mut cipher, &mut frame),
+            Err(SecurityError::ImproperSecurityLevel)
+        );
+        // Frame types without a entry use the default minimum
+        let mut policy = policy(&[], &KEYS);
+        policy.default_minimum = 3;
+        assert_eq!(
+            policy.unsecure_frame(&mut cipher, &mut frame),
+            Err(SecurityError::ImproperSecurityLevel)
+        );
+    }
+
+    #[test]
+    fn key_lookup() {
+        let mut cipher = SoftAes::new(&[0; 16]);
+        let mut frame = beacon();
+        assert_eq!(
+            policy(&[], &[]).unsecure_frame(&mut cipher, &mut frame),
+            Err(SecurityError::UnavailableKey)
+        );
+        assert_eq!(
+            policy(&[], &COMMAND_KEYS).unsecure_frame(&mut cipher, &mut frame),
+            Err(SecurityError::ImproperKeyType)
+        );
+        let keys = [KeyDescriptor {
+            id: KeyIdentifier::Implicit(SOURCE + 1),
+            key: KEY,
+            usage: &BEACONS,
+        }];
+        assert_eq!(
+            policy(&[], &keys).unsecure_frame(&mut cipher, &mut frame),
+            Err(SecurityError::UnavailableKey)
+        );
+    }
+
+    #[test]
+    fn mic_mismatch() {
+        let mut cipher = SoftAes::new(&[0; 16]);
+        let mut frame = beacon();
+        frame[20] ^= 1;
+        assert_eq!(
+            policy(&[], &KEYS).unsecure_frame(&mut cipher, &mut frame),
+            Err(SecurityError::Crypto(CryptoError::Authentication))
+        );
+    }
+
+    #[test]
+    fn exhausted_frame_counter() {
+        let mut cipher = SoftAes::new(&[0; 16]);
+        let mut frame = beacon();
+        frame[14..18].copy_from_slice(&[0xff; 4]);
+        assert_eq!(
+            policy(&[], &KEYS).unsecure_frame(&mut cipher, &mut frame),
+            Err(SecurityError::CounterError)
+        );
+    }
+
+    #[test]
+    fn unsecured_frames() {
+        let mut cipher = SoftAes::new(&[0; 16]);
+        let mut frame = data();
+        let unsecured = policy(&[], &[])
+            .unsecure_frame(&mut cipher, &mut frame)
+            .unwrap();
+        assert_eq!(unsecured.level, 0);
+        assert_eq!(unsecured.payload, 9);
+        assert_eq!(unsecured.payload_length, 3);
+        let levels = [level(FrameType::Data, 5)];
+        assert_eq!(
+            policy(&levels, &[]).unsecure_frame(&mut cipher, &mut frame),
+            Err(SecurityError::ImproperSecurityLevel)
+        );
+    }
+
+    #[test]
+    fn exempt_device() {
+        let mut cipher = SoftAes::new(&[0; 16]);
+        let mut frame = data();
+        let levels = [SecurityLevelDescriptor {
+            device_override: true,
+            ..level(FrameType::Data, 5)
+        }];
+        let mut devices = [DeviceDescriptor {
+            pan_id: 0x4321,
+            short_address: 0x0001,
+            extended_address: SOURCE,
+            exempt: false,
+        }];
+        let strict = SecurityPolicy {
+            devices: &devices,
+            ..policy(&levels, &[])
+        };
+        assert_eq!(
+            strict.unsecure_frame(&mut cipher, &mut frame),
+            Err(SecurityError::ImproperSecurityLevel)
+        );
+        devices[0].exempt = true;
+        let exempt = SecurityPolicy {
+            devices: &devices,
+            ..policy(&levels, &[])
+        };
+        assert!(exempt.unsecure_frame(&mut cipher, &mut frame).is_ok());
+    }
+
+    #[test]
+    fn truncated_frames() {
+        let mut cipher = SoftAes::new(&[0; 16]);
+        for length in 0..18 {
+            let mut frame = beacon();
+            assert!(policy(&[], &KEYS)
+                .unsecure_frame(&mut cipher, &mut frame[..length])
+                .is_err());
+        }
+    }
+}
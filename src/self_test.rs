@@ -0,0 +1,196 @@
+//! # Boot-time self test
+//!
+//! Exercises the hardware used by the stack and returns a report, for
+//! production test and field diagnostics.
+//!
+//! * The radio transmits a short carrier on a unused channel, then a energy
+//!   detect is read back on the same channel.
+//! * The TIMER is measured against the RTC, clocked by the LFCLK, over
+//!   125 ms.
+//! * The AES-128 backend is checked with the known-answer vectors of
+//!   FIPS-197.
+//!
+//! Run the self test before the radio interrupts are enabled, the radio is
+//! left in receive mode. The LFCLK must be running for the timer
+//! measurement.
+//!
+
+use core::ops::Deref;
+
+use crate::crypto::{Block, BlockCipher, Key};
+use crate::pac::rtc0;
+use crate::radio::Radio;
+#[cfg(feature = "energy-detect")]
+use crate::radio::ScanDuration;
+use crate::timer::Timer;
+
+/// RTC frequency with prescaler zero
+const RTC_FREQUENCY: u32 = 32768;
+/// RTC ticks in the timer measurement, 125 ms
+const RTC_TICKS: u32 = 4096;
+/// The RTC counter is 24 bits
+const RTC_COUNTER_MASK: u32 = 0x00ff_ffff;
+/// Microseconds to wait for the RTC to advance a tick
+const RTC_TICK_TIMEOUT: u32 = 1_000;
+/// Number of polls waiting for the energy detect result
+#[cfg(feature = "energy-detect")]
+const ED_ATTEMPTS: u32 = 100_000;
+
+/// AES-128 known-answer vectors, key, plaintext and ciphertext
+const AES_VECTORS: [(Key, Block, Block); 2] = [
+    // FIPS-197 appendix B
+    (
+        [
+            0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf,
+            0x4f, 0x3c,
+        ],
+        [
+            0x32, 0x43, 0xf6, 0xa8, 0x88, 0x5a, 0x30, 0x8d, 0x31, 0x31, 0x98, 0xa2, 0xe0, 0x37,
+            0x07, 0x34,
+        ],
+        [
+            0x39, 0x25, 0x84, 0x1d, 0x02, 0xdc, 0x09, 0xfb, 0xdc, 0x11, 0x85, 0x97, 0x19, 0x6a,
+            0x0b, 0x32,
+        ],
+    ),
+    // FIPS-197 appendix C.1
+    (
+        [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+            0x0e, 0x0f,
+        ],
+        [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+            0xee, 0xff,
+        ],
+        [
+            0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4,
+            0xc5, 0x5a,
+        ],
+    ),
+];
+
+/// Result of the self test
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SelfTestReport {
+    /// The transmitter ramped up and sent a carrier
+    pub carrier: bool,
+    /// Energy level read back, None if the energy detect did not complete or
+    /// the `energy-detect` feature is disabled
+    pub energy_level: Option<u8>,
+    /// Deviation of the TIMER from the RTC in ppm, None if the RTC did not
+    /// advance
+    pub timer_deviation: Option<i32>,
+    /// The AES-128 backend passed the known-answer vectors
+    pub cipher: bool,
+}
+
+impl SelfTestReport {
+    /// Check if all parts passed, with the timer within `max_deviation` ppm
+    ///
+    /// Use a few hundred ppm with the LFXO and a few percent with the LFRC.
+    pub fn passed(&self, max_deviation: u32) -> bool {
+        let energy_detect = self.energy_level.is_some() || cfg!(not(feature = "energy-detect"));
+        let timer = match self.timer_deviation {
+            Some(deviation) => deviation.unsigned_abs() <= max_deviation,
+            None => false,
+        };
+        self.carrier && energy_detect && timer && self.cipher
+    }
+}
+
+/// Run the self test
+///
+/// `channel` is a channel not used by the network. `timer` shall have been
+/// initialised using `Timer::init`, the RTC is started with prescaler zero.
+/// The test vectors replace the key of `cipher`, `key` is loaded back into
+/// `cipher` after the test.
+pub fn self_test<T, R, C, const RX: usize>(
+    radio: &mut Radio<RX>,
+    channel: u8,
+    timer: &mut T,
+    rtc: &R,
+    cipher: &mut C,
+    key: &Key,
+) -> SelfTestReport
+where
+    T: Timer,
+    R: Deref<Target = rtc0::RegisterBlock>,
+    C: BlockCipher,
+{
+    let carrier = radio.test_carrier(channel);
+    let energy_level = test_energy_detect(radio, channel);
+    radio.receive_prepare();
+    SelfTestReport {
+        carrier,
+        energy_level,
+        timer_deviation: test_timer(timer, rtc),
+        cipher: test_cipher(cipher, key),
+    }
+}
+
+#[cfg(feature = "energy-detect")]
+fn test_energy_detect<const RX: usize>(radio: &mut Radio<RX>, channel: u8) -> Option<u8> {
+    let restore = radio.get_channel();
+    radio.set_channel(channel).ok()?;
+    let level = if radio.start_energy_detect(ScanDuration::Iterations(8)) {
+        (0..ED_ATTEMPTS).find_map(|_| radio.report_energy_detect())
+    } else {
+        None
+    };
+    let _ = radio.set_channel(restore);
+    level
+}
+
+#[cfg(not(feature = "energy-detect"))]
+fn test_energy_detect<const RX: usize>(_radio: &mut Radio<RX>, _channel: u8) -> Option<u8> {
+    None
+}
+
+/// Wait for the next RTC tick, returns the timer value at the tick
+fn next_tick<T: Timer>(timer: &T, rtc: &rtc0::RegisterBlock, from: u32) -> Option<u32> {
+    let start = timer.now();
+    loop {
+        let now = timer.now();
+        if rtc.counter.read().counter().bits() != from {
+            return Some(now);
+        }
+        if now.wrapping_sub(start) > RTC_TICK_TIMEOUT {
+            return None;
+        }
+    }
+}
+
+fn test_timer<T, R>(timer: &mut T, rtc: &R) -> Option<i32>
+where
+    T: Timer,
+    R: Deref<Target = rtc0::RegisterBlock>,
+{
+    rtc.prescaler.write(|w| unsafe { w.prescaler().bits(0) });
+    rtc.tasks_start.write(|w| w.tasks_start().set_bit());
+    // Align the measurement to RTC ticks
+    let first = rtc.counter.read().counter().bits();
+    let start = next_tick(timer, rtc, first)?;
+    let start_counter = rtc.counter.read().counter().bits();
+    let mut counter = start_counter;
+    let mut end = start;
+    while counter.wrapping_sub(start_counter) & RTC_COUNTER_MASK < RTC_TICKS {
+        end = next_tick(timer, rtc, counter)?;
+        counter = rtc.counter.read().counter().bits();
+    }
+    let ticks = counter.wrapping_sub(start_counter) & RTC_COUNTER_MASK;
+    let expected = i64::from(ticks) * 1_000_000 / i64::from(RTC_FREQUENCY);
+    let elapsed = i64::from(end.wrapping_sub(start));
+    Some(((elapsed - expected) * 1_000_000 / expected) as i32)
+}
+
+fn test_cipher<C: BlockCipher>(cipher: &mut C, key: &Key) -> bool {
+    let passed = AES_VECTORS.iter().all(|(key, plaintext, ciphertext)| {
+        let mut block = *plaintext;
+        cipher.set_key(key);
+        cipher.encrypt_block(&mut block).is_ok() && block == *ciphertext
+    });
+    cipher.set_key(key);
+    passed
+}
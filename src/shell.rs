@@ -0,0 +1,406 @@
+//! # Interactive test shell
+//!
+//! A line based command line for bring-up and RF lab work, usable from a
+//! terminal. Like the `command` protocol the shell does not depend on the
+//! transport, octets received over UARTE, USB or anything else are passed to
+//! `Shell::push` and output is written to a `core::fmt::Write`.
+//!
+//! ## Commands
+//!
+//! | Command                     | Description                                     |
+//! |-----------------------------|-------------------------------------------------|
+//! | `help`                      | List the commands                               |
+//! | `channel [channel]`         | Show or set the channel                         |
+//! | `power [dBm]`               | Show or set the transmission power              |
+//! | `tx [length] [count] [cca]` | Transmit test frames, optionally with CCA       |
+//! | `ed`                        | Energy detect scan of channels 11 to 26         |
+//! | `stats [clear]`             | Show, or clear, the radio statistics            |
+//! | `regs`                      | Show the RADIO registers                        |
+//! | `reset`                     | Show the reset reason and last driver state     |
+//!
+//! Test frames are broadcast data frames, `length` octets including FCS,
+//! with an incrementing sequence number. The energy detect scan requires the
+//! `energy-detect` feature. Received frames are reported while the shell is
+//! polled. The reset diagnostics are set with `Shell::set_reset_diagnostics`.
+//!
+
+use core::fmt::{self, Write};
+
+#[cfg(feature = "energy-detect")]
+use crate::radio::ScanDuration;
+use crate::radio::{
+    is_valid_channel, is_valid_transmission_power, level_to_dbm, Error, PacketBuffer, Radio,
+    MAX_PACKET_LENGHT,
+};
+use crate::reset::ResetDiagnostics;
+
+/// Longest command line
+pub const MAX_LINE_LENGTH: usize = 64;
+
+/// Prompt written before each command line
+const PROMPT: &str = "> ";
+/// Header of the test frames, data frame with PAN identifier compression
+/// and a short destination address, the sequence number follows
+const TEST_FRAME_CONTROL: [u8; 2] = [0x41, 0x08];
+/// Broadcast PAN identifier and short address
+const TEST_FRAME_DESTINATION: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+/// Frame control, sequence number and destination
+const TEST_FRAME_HEADER_LENGTH: usize = 7;
+/// Octets of FCS added by the radio
+const FCS_LENGTH: usize = 2;
+/// Default test frame length, including FCS
+const DEFAULT_TEST_FRAME_LENGTH: usize = 20;
+/// Energy detect iterations for each channel in a scan
+#[cfg(feature = "energy-detect")]
+const SCAN_ITERATIONS: u32 = 8;
+/// Channels 11 to 26
+#[cfg(feature = "energy-detect")]
+const SCAN_CHANNELS: u32 = 0x07ff_f800;
+
+/// Test frames left to transmit
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TestFrames {
+    /// Frames left
+    count: u32,
+    /// Frame length without FCS
+    length: usize,
+    /// Use clear channel assessment
+    cca: bool,
+}
+
+/// Interactive test shell
+pub struct Shell {
+    line: [u8; MAX_LINE_LENGTH],
+    length: usize,
+    /// The last octet ended a line with carriage return
+    carriage_return: bool,
+    sequence: u8,
+    transmit: Option<TestFrames>,
+    /// Reset diagnostics captured at startup
+    reset: Option<ResetDiagnostics>,
+    /// Channels left to scan
+    #[cfg(feature = "energy-detect")]
+    scan_channels: u32,
+    /// Channel used before the scan started
+    #[cfg(feature = "energy-detect")]
+    scan_restore: u8,
+    /// Channel currently scanned
+    #[cfg(feature = "energy-detect")]
+    scan_current: u8,
+}
+
+impl Shell {
+    /// Create a new shell
+    pub fn new() -> Self {
+        Self {
+            line: [0u8; MAX_LINE_LENGTH],
+            length: 0,
+            carriage_return: false,
+            sequence: 0,
+            transmit: None,
+            reset: None,
+            #[cfg(feature = "energy-detect")]
+            scan_channels: 0,
+            #[cfg(feature = "energy-detect")]
+            scan_restore: 0,
+            #[cfg(feature = "energy-detect")]
+            scan_current: 0,
+        }
+    }
+
+    /// Set the reset diagnostics shown by the `reset` command
+    pub fn set_reset_diagnostics(&mut self, diagnostics: ResetDiagnostics) {
+        self.reset = Some(diagnostics);
+    }
+
+    /// Write the prompt, call this once when the terminal is connected
+    pub fn prompt<W: Write>(&self, out: &mut W) -> fmt::Result {
+        out.write_str(PROMPT)
+    }
+
+    /// Add a octet from the terminal
+    ///
+    /// Printable characters are echoed, backspace removes the last character
+    /// and carriage return or line feed executes the line.
+    pub fn push<W: Write, const RX: usize>(
+        &mut self,
+        radio: &mut Radio<RX>,
+        out: &mut W,
+        byte: u8,
+    ) -> fmt::Result {
+        let carriage_return = self.carriage_return;
+        self.carriage_return = byte == b'\r';
+        match byte {
+            // Line feed of a carriage return and line feed pair
+            b'\n' if carriage_return => Ok(()),
+            b'\r' | b'\n' => {
+                out.write_str("\r\n")?;
+                let length = self.length;
+                self.length = 0;
+                if length > 0 {
+                    let line = self.line;
+                    // Only printable ASCII is stored
+                    if let Ok(line) = core::str::from_utf8(&line[..length]) {
+                        self.execute(radio, out, line)?;
+                    }
+                }
+                if self.is_busy() {
+                    // The prompt follows the result
+                    Ok(())
+                } else {
+                    out.write_str(PROMPT)
+                }
+            }
+            0x08 | 0x7f if self.length > 0 => {
+                self.length -= 1;
+                out.write_str("\x08 \x08")
+            }
+            0x20..=0x7e if self.length < MAX_LINE_LENGTH => {
+                self.line[self.length] = byte;
+                self.length += 1;
+                out.write_char(char::from(byte))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Execute a command line
+    pub fn execute<W: Write, const RX: usize>(
+        &mut self,
+        radio: &mut Radio<RX>,
+        out: &mut W,
+        line: &str,
+    ) -> fmt::Result {
+        let mut arguments = line.split_whitespace();
+        let command = match arguments.next() {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        if self.is_busy() {
+            return write!(out, "busy\r\n");
+        }
+        match command {
+            "help" => out.write_str(
+                "help\r\n\
+                 channel [channel]\r\n\
+                 power [dBm]\r\n\
+                 tx [length] [count] [cca]\r\n\
+                 ed\r\n\
+                 stats [clear]\r\n\
+                 regs\r\n\
+                 reset\r\n",
+            ),
+            "channel" => match arguments.next().map(str::parse::<u8>) {
+                None => write!(out, "channel {}\r\n", radio.get_channel()),
+                Some(Ok(channel)) if is_valid_channel(channel) => {
+                    let _ = radio.set_channel(channel);
+                    radio.receive_prepare();
+                    write!(out, "channel {}\r\n", channel)
+                }
+                Some(_) => write!(out, "invalid channel\r\n"),
+            },
+            "power" => match arguments.next().map(str::parse::<i8>) {
+                None => write!(out, "power {} dBm\r\n", radio.transmission_power()),
+                Some(Ok(power)) if is_valid_transmission_power(power) => {
+                    let _ = radio.set_transmission_power(power);
+                    write!(out, "power {} dBm\r\n", power)
+                }
+                Some(_) => write!(out, "invalid power\r\n"),
+            },
+            "tx" => {
+                let length = match arguments.next().map(str::parse::<usize>) {
+                    None => DEFAULT_TEST_FRAME_LENGTH,
+                    Some(Ok(length))
+                        if (TEST_FRAME_HEADER_LENGTH + FCS_LENGTH..=MAX_PACKET_LENGHT - 2)
+                            .contains(&length) =>
+                    {
+                        length
+                    }
+                    Some(_) => return write!(out, "invalid length\r\n"),
+                };
+                let count = match arguments.next().map(str::parse::<u32>) {
+                    None => 1,
+                    Some(Ok(count)) if count > 0 => count,
+                    Some(_) => return write!(out, "invalid count\r\n"),
+                };
+                let cca = arguments.next() == Some("cca");
+                self.transmit = Some(TestFrames {
+                    count,
+                    length: length - FCS_LENGTH,
+                    cca,
+                });
+                write!(out, "tx {} frames of {} octets\r\n", count, length)?;
+                self.transmit_next(radio, out)
+            }
+            #[cfg(feature = "energy-detect")]
+            "ed" => {
+                self.scan_channels = SCAN_CHANNELS;
+                self.scan_restore = radio.get_channel();
+                self.scan_next(radio);
+                Ok(())
+            }
+            #[cfg(not(feature = "energy-detect"))]
+            "ed" => write!(out, "unsupported\r\n"),
+            "stats" => {
+                if arguments.next() == Some("clear") {
+                    radio.statistics_mut().clear();
+                }
+                let statistics = radio.statistics();
+                write!(out, "transmitted {}\r\n", statistics.transmitted)?;
+                write!(out, "retries {}\r\n", statistics.retries)?;
+                write!(out, "cca busy {}\r\n", statistics.cca_busy)?;
+                write!(out, "received {}\r\n", statistics.received)?;
+                write!(out, "crc errors {}\r\n", statistics.crc_errors)?;
+                write!(out, "rx overflows {}\r\n", statistics.rx_overflows)
+            }
+            "regs" => {
+                let registers = radio.registers();
+                write!(out, "STATE     {:#010x}\r\n", registers.state)?;
+                write!(out, "FREQUENCY {:#010x}\r\n", registers.frequency)?;
+                write!(out, "TXPOWER   {:#010x}\r\n", registers.txpower)?;
+                write!(out, "MODE      {:#010x}\r\n", registers.mode)?;
+                write!(out, "PCNF0     {:#010x}\r\n", registers.pcnf0)?;
+                write!(out, "PCNF1     {:#010x}\r\n", registers.pcnf1)?;
+                write!(out, "CRCCNF    {:#010x}\r\n", registers.crccnf)?;
+                write!(out, "CCACTRL   {:#010x}\r\n", registers.ccactrl)?;
+                write!(out, "SFD       {:#010x}\r\n", registers.sfd)?;
+                write!(out, "SHORTS    {:#010x}\r\n", registers.shorts)?;
+                write!(out, "INTENSET  {:#010x}\r\n", registers.intenset)?;
+                write!(out, "PACKETPTR {:#010x}\r\n", registers.packetptr)
+            }
+            "reset" => match self.reset {
+                Some(diagnostics) => {
+                    write!(out, "RESETREAS {:#010x}\r\n", diagnostics.reason.bits())?;
+                    match diagnostics.last_state {
+                        Some((state, channel)) => {
+                            write!(out, "last state {:?} channel {}\r\n", state, channel)
+                        }
+                        None => write!(out, "last state unknown\r\n"),
+                    }
+                }
+                None => write!(out, "not captured\r\n"),
+            },
+            _ => write!(out, "unknown command, try help\r\n"),
+        }
+    }
+
+    /// Test frames are being transmitted or a scan is in progress
+    pub fn is_busy(&self) -> bool {
+        #[cfg(feature = "energy-detect")]
+        if self.scan_current != 0 {
+            return true;
+        }
+        self.transmit.is_some()
+    }
+
+    /// Process radio events, call this from the radio interrupt
+    ///
+    /// Reports received frames, energy detect results and the progress of
+    /// test transmissions.
+    pub fn poll<W: Write, const RX: usize>(
+        &mut self,
+        radio: &mut Radio<RX>,
+        out: &mut W,
+        buffer: &mut PacketBuffer,
+    ) -> fmt::Result {
+        #[cfg(feature = "energy-detect")]
+        if self.scan_current != 0 {
+            if let Some(level) = radio.report_energy_detect() {
+                let channel = self.scan_current;
+                self.scan_next(radio);
+                write!(
+                    out,
+                    "channel {} level {} ({} dBm)\r\n",
+                    channel,
+                    level,
+                    level_to_dbm(level)
+                )?;
+                if self.scan_current == 0 {
+                    out.write_str(PROMPT)?;
+                }
+            }
+            return Ok(());
+        }
+        match radio.receive(buffer) {
+            Ok(length) if length > 2 => {
+                let lqi = buffer[length - 1];
+                write!(
+                    out,
+                    "rx {} octets LQI {} ({} dBm)\r\n",
+                    length - 1,
+                    lqi,
+                    level_to_dbm(lqi)
+                )?;
+            }
+            Ok(_) => (),
+            Err(Error::CcaBusy) => write!(out, "tx channel busy\r\n")?,
+            Err(_) => (),
+        }
+        self.transmit_next(radio, out)
+    }
+
+    /// Queue the next test frame when the previous has been sent
+    fn transmit_next<W: Write, const RX: usize>(
+        &mut self,
+        radio: &mut Radio<RX>,
+        out: &mut W,
+    ) -> fmt::Result {
+        let frames = match self.transmit {
+            Some(frames) if !radio.is_tx_busy() => frames,
+            _ => return Ok(()),
+        };
+        if frames.count == 0 {
+            self.transmit = None;
+            write!(out, "tx done\r\n")?;
+            return out.write_str(PROMPT);
+        }
+        let mut frame = [0u8; MAX_PACKET_LENGHT];
+        frame[..2].copy_from_slice(&TEST_FRAME_CONTROL);
+        frame[2] = self.sequence;
+        frame[3..TEST_FRAME_HEADER_LENGTH].copy_from_slice(&TEST_FRAME_DESTINATION);
+        for (n, octet) in frame[TEST_FRAME_HEADER_LENGTH..frames.length]
+            .iter_mut()
+            .enumerate()
+        {
+            *octet = n as u8;
+        }
+        let data = &frame[..frames.length];
+        let queued = if frames.cca {
+            radio.queue_transmission(data)
+        } else {
+            radio.queue_transmission_no_cca(data)
+        };
+        if queued.is_err() {
+            self.transmit = None;
+            write!(out, "tx refused\r\n")?;
+            return out.write_str(PROMPT);
+        }
+        self.sequence = self.sequence.wrapping_add(1);
+        self.transmit = Some(TestFrames {
+            count: frames.count - 1,
+            ..frames
+        });
+        Ok(())
+    }
+
+    #[cfg(feature = "energy-detect")]
+    fn scan_next<const RX: usize>(&mut self, radio: &mut Radio<RX>) {
+        if self.scan_channels == 0 {
+            self.scan_current = 0;
+            let _ = radio.set_channel(self.scan_restore);
+            radio.receive_prepare();
+        } else {
+            let channel = self.scan_channels.trailing_zeros() as u8;
+            self.scan_channels &= !(1 << channel);
+            self.scan_current = channel;
+            let _ = radio.set_channel(channel);
+            radio.start_energy_detect(ScanDuration::Iterations(SCAN_ITERATIONS));
+        }
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
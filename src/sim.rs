@@ -0,0 +1,534 @@
+//! # Simulated radio
+//!
+//! `SimRadio` implements `Ieee802154Phy` on a in-memory medium, for
+//! deterministic host-side testing of the MAC and queueing layers. Radios
+//! created from the same `SimMedium` receive the frames transmitted by the
+//! others on the same channel.
+//!
+//! Time is advanced by the test with `SimMedium::advance`. A frame is on
+//! air for 32 μs per octet, including the synchronisation header, and is
+//! received after a configurable delay. Frames are lost with a configurable
+//! probability, drawn from a seeded pseudo-random sequence so that a test
+//! run can be repeated. The clear channel assessment reports a busy channel
+//! while another radio transmits on the channel, or when injected with
+//! `SimRadio::inject_cca_busy`. Energy detection reports the highest level
+//! while another radio transmits on the channel, otherwise the noise level
+//! set with `SimRadio::set_noise_level`.
+//!
+
+use core::cell::{Cell, RefCell};
+
+#[cfg(feature = "energy-detect")]
+use crate::phy::ScanDuration;
+use crate::phy::{
+    check_frame_length, check_received_length, is_valid_channel, is_valid_transmission_power,
+    CcaResult, Error, Ieee802154Phy, FCS_LENGTH, MAX_FRAME_LENGTH,
+};
+
+/// Number of transmitted frames kept by the medium
+const MEDIUM_FRAMES: usize = 16;
+/// Octets sent before the PSDU, preamble, SFD and PHR
+const SHR_PHR_OCTETS: u32 = 6;
+/// Duration of a octet in microseconds
+const OCTET_DURATION: u32 = 32;
+/// Duration of a clear channel assessment and of a energy detect iteration
+/// in microseconds, 8 symbols
+const CCA_DURATION: u32 = 128;
+
+/// Check if `time` has been reached at `now`
+fn is_reached(now: u32, time: u32) -> bool {
+    (now.wrapping_sub(time) as i32) >= 0
+}
+
+/// A frame transmitted on the medium
+#[derive(Clone, Copy)]
+struct OnAir {
+    id: u32,
+    source: u8,
+    channel: u8,
+    start: u32,
+    length: u8,
+    data: [u8; MAX_FRAME_LENGTH],
+}
+
+impl OnAir {
+    fn end(&self) -> u32 {
+        let octets = SHR_PHR_OCTETS + u32::from(self.length) + FCS_LENGTH as u32;
+        self.start.wrapping_add(octets * OCTET_DURATION)
+    }
+
+    fn is_on_air(&self, now: u32) -> bool {
+        is_reached(now, self.start) && !is_reached(now, self.end())
+    }
+}
+
+/// The medium shared by simulated radios
+pub struct SimMedium {
+    now: Cell<u32>,
+    delay: Cell<u32>,
+    loss: Cell<u16>,
+    random: Cell<u32>,
+    radios: Cell<u8>,
+    next: Cell<u32>,
+    frames: RefCell<[Option<OnAir>; MEDIUM_FRAMES]>,
+}
+
+impl SimMedium {
+    /// Create a medium without delay or loss, `seed` selects the
+    /// pseudo-random sequence used for losing frames
+    pub const fn new(seed: u32) -> Self {
+        Self {
+            now: Cell::new(0),
+            delay: Cell::new(0),
+            loss: Cell::new(0),
+            // Xorshift needs a non-zero state
+            random: Cell::new(seed | 1),
+            radios: Cell::new(0),
+            next: Cell::new(0),
+            frames: RefCell::new([None; MEDIUM_FRAMES]),
+        }
+    }
+
+    /// Create a radio on the medium
+    pub fn radio(&self) -> SimRadio<'_> {
+        let id = self.radios.get();
+        self.radios.set(id.wrapping_add(1));
+        SimRadio {
+            medium: self,
+            id,
+            channel: 11,
+            lqi: u8::MAX,
+            next: self.next.get(),
+            transmission_power: 0,
+            noise_level: 0,
+            transmission_end: None,
+            cca_busy: 0,
+            cca_failed: false,
+            cca: None,
+            #[cfg(feature = "energy-detect")]
+            energy_detect: None,
+        }
+    }
+
+    /// Time in microseconds
+    pub fn now(&self) -> u32 {
+        self.now.get()
+    }
+
+    /// Advance the time by `elapsed` microseconds
+    pub fn advance(&self, elapsed: u32) {
+        self.now.set(self.now.get().wrapping_add(elapsed));
+    }
+
+    /// Set the delay in microseconds from the end of a transmission until
+    /// the frame is received
+    pub fn set_delay(&self, delay: u32) {
+        self.delay.set(delay);
+    }
+
+    /// Set the probability of losing a frame at each receiver, in per mille
+    pub fn set_loss(&self, loss: u16) {
+        self.loss.set(loss.min(1000));
+    }
+
+    /// Next number in the pseudo-random sequence, xorshift32
+    fn random(&self) -> u32 {
+        let mut x = self.random.get();
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random.set(x);
+        x
+    }
+
+    fn is_lost(&self) -> bool {
+        let loss = u32::from(self.loss.get());
+        loss > 0 && self.random() % 1000 < loss
+    }
+
+    /// Put a frame on air, returns the end of the transmission
+    fn transmit(&self, source: u8, channel: u8, data: &[u8]) -> u32 {
+        let id = self.next.get();
+        self.next.set(id.wrapping_add(1));
+        let mut frame = OnAir {
+            id,
+            source,
+            channel,
+            start: self.now(),
+            length: data.len() as u8,
+            data: [0; MAX_FRAME_LENGTH],
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        self.frames.borrow_mut()[id as usize % MEDIUM_FRAMES] = Some(frame);
+        frame.end()
+    }
+
+    /// Check if another radio is transmitting on the channel
+    fn is_busy(&self, radio: u8, channel: u8) -> bool {
+        let now = self.now();
+        self.frames
+            .borrow()
+            .iter()
+            .flatten()
+            .any(|frame| frame.source != radio && frame.channel == channel && frame.is_on_air(now))
+    }
+
+    /// The oldest frame kept with a identifier not below `next`
+    fn oldest(&self, next: u32) -> Option<OnAir> {
+        self.frames
+            .borrow()
+            .iter()
+            .flatten()
+            .filter(|frame| (frame.id.wrapping_sub(next) as i32) >= 0)
+            .min_by_key(|frame| frame.id.wrapping_sub(next))
+            .copied()
+    }
+}
+
+/// A simulated radio
+pub struct SimRadio<'a> {
+    medium: &'a SimMedium,
+    id: u8,
+    channel: u8,
+    lqi: u8,
+    /// Identifier of the next frame to receive
+    next: u32,
+    transmission_power: i8,
+    noise_level: u8,
+    /// End of the ongoing transmission
+    transmission_end: Option<u32>,
+    /// Number of clear channel assessments to fail
+    cca_busy: u32,
+    /// A clear channel assessment failed, to be reported
+    cca_failed: bool,
+    /// End and result of a clear channel assessment
+    cca: Option<(u32, CcaResult)>,
+    /// End and result of a energy detect query
+    #[cfg(feature = "energy-detect")]
+    energy_detect: Option<(u32, u8)>,
+}
+
+impl<'a> SimRadio<'a> {
+    /// Fail the next `count` clear channel assessments
+    pub fn inject_cca_busy(&mut self, count: u32) {
+        self.cca_busy = count;
+    }
+
+    /// Set the link quality indicator of received frames
+    pub fn set_lqi(&mut self, lqi: u8) {
+        self.lqi = lqi;
+    }
+
+    /// Set the energy level reported when no radio transmits on the channel
+    pub fn set_noise_level(&mut self, level: u8) {
+        self.noise_level = level;
+    }
+
+    /// Transmission power in dBm
+    pub fn transmission_power(&self) -> i8 {
+        self.transmission_power
+    }
+
+    /// Energy level on the channel
+    fn energy_level(&self) -> u8 {
+        if self.medium.is_busy(self.id, self.channel) {
+            u8::MAX
+        } else {
+            self.noise_level
+        }
+    }
+
+    fn is_cca_busy(&mut self) -> bool {
+        if self.cca_busy > 0 {
+            self.cca_busy -= 1;
+            true
+        } else {
+            self.medium.is_busy(self.id, self.channel)
+        }
+    }
+}
+
+impl<'a> Ieee802154Phy for SimRadio<'a> {
+    fn set_channel(&mut self, channel: u8) -> Result<(), Error> {
+        if !is_valid_channel(channel) {
+            return Err(Error::InvalidChannel);
+        }
+        self.channel = channel;
+        Ok(())
+    }
+
+    fn get_channel(&mut self) -> u8 {
+        self.channel
+    }
+
+    fn is_tx_busy(&self) -> bool {
+        self.cca_failed
+            || match self.transmission_end {
+                Some(end) => !is_reached(self.medium.now(), end),
+                None => false,
+            }
+    }
+
+    fn set_transmission_power(&mut self, power: i8) -> Result<(), Error> {
+        if !is_valid_transmission_power(power) {
+            return Err(Error::InvalidPower);
+        }
+        self.transmission_power = power;
+        Ok(())
+    }
+
+    fn transmit(&mut self, data: &[u8], cca: bool) -> Result<usize, Error> {
+        check_frame_length(data.len())?;
+        if cca && self.is_cca_busy() {
+            self.cca_failed = true;
+        } else {
+            let end = self.medium.transmit(self.id, self.channel, data);
+            self.transmission_end = Some(end);
+        }
+        Ok(data.len())
+    }
+
+    fn receive_slice(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        if self.cca_failed {
+            self.cca_failed = false;
+            return Err(Error::CcaBusy);
+        }
+        let now = self.medium.now();
+        while let Some(frame) = self.medium.oldest(self.next) {
+            let arrival = frame.end().wrapping_add(self.medium.delay.get());
+            if !is_reached(now, arrival) {
+                break;
+            }
+            self.next = frame.id.wrapping_add(1);
+            if frame.source == self.id || frame.channel != self.channel || self.medium.is_lost() {
+                continue;
+            }
+            // Size includes FCS, which is replaced by the LQI
+            let phr = frame.length + FCS_LENGTH as u8;
+            let length = check_received_length(phr, buffer.len())?;
+            buffer[0] = phr;
+            buffer[1..length - 1].copy_from_slice(&frame.data[..usize::from(frame.length)]);
+            buffer[length - 1] = self.lqi;
+            return Ok(length);
+        }
+        Ok(0)
+    }
+
+    #[cfg(feature = "energy-detect")]
+    fn start_energy_detect(&mut self, duration: ScanDuration) -> bool {
+        match duration.iterations() {
+            Some(iterations) => {
+                let end = self
+                    .medium
+                    .now()
+                    .wrapping_add(iterations.saturating_mul(CCA_DURATION));
+                self.energy_detect = Some((end, self.energy_level()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[cfg(feature = "energy-detect")]
+    fn report_energy_detect(&mut self) -> Option<u8> {
+        match self.energy_detect {
+            Some((end, level)) if is_reached(self.medium.now(), end) => {
+                self.energy_detect = None;
+                Some(level)
+            }
+            _ => None,
+        }
+    }
+
+    fn start_cca(&mut self) {
+        let end = self.medium.now().wrapping_add(CCA_DURATION);
+        let busy = self.is_cca_busy();
+        let level = self.energy_level();
+        self.cca = Some((end, CcaResult { busy, level }));
+    }
+
+    fn report_cca(&mut self) -> Option<CcaResult> {
+        match self.cca {
+            Some((end, result)) if is_reached(self.medium.now(), end) => {
+                self.cca = None;
+                Some(result)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Transmit a frame with `length` octets and receive it into a buffer of
+    /// `capacity` octets
+    fn round_trip(length: usize, capacity: usize) -> (Result<usize, Error>, Result<usize, Error>) {
+        let medium = SimMedium::new(1);
+        let mut transmitter = medium.radio();
+        let mut receiver = medium.radio();
+        let data = [0x5a; 127];
+        let queued = transmitter.transmit(&data[..length], false);
+        medium.advance(10_000);
+        let mut buffer = [0u8; 129];
+        (queued, receiver.receive_slice(&mut buffer[..capacity]))
+    }
+
+    #[test]
+    fn transmit_boundaries() {
+        assert_eq!(round_trip(0, 129), (Err(Error::InvalidLength), Ok(0)));
+        assert_eq!(round_trip(1, 129), (Ok(1), Ok(3)));
+        assert_eq!(round_trip(125, 129), (Ok(125), Ok(127)));
+        assert_eq!(round_trip(126, 129), (Err(Error::InvalidLength), Ok(0)));
+        assert_eq!(round_trip(127, 129), (Err(Error::InvalidLength), Ok(0)));
+    }
+
+    #[test]
+    fn receive_into_small_buffer() {
+        assert_eq!(round_trip(125, 128), (Ok(125), Ok(127)));
+        assert_eq!(round_trip(125, 127), (Ok(125), Err(Error::BufferTooSmall)));
+        assert_eq!(round_trip(1, 4), (Ok(1), Ok(3)));
+        assert_eq!(round_trip(1, 3), (Ok(1), Err(Error::BufferTooSmall)));
+        assert_eq!(round_trip(1, 0), (Ok(1), Err(Error::BufferTooSmall)));
+    }
+
+    #[test]
+    fn frame_dropped_when_too_large() {
+        let medium = SimMedium::new(1);
+        let mut transmitter = medium.radio();
+        let mut receiver = medium.radio();
+        transmitter.transmit(&[0x5a; 100], false).unwrap();
+        medium.advance(10_000);
+        let mut small = [0u8; 16];
+        assert_eq!(
+            receiver.receive_slice(&mut small),
+            Err(Error::BufferTooSmall)
+        );
+        let mut buffer = [0u8; 129];
+        assert_eq!(receiver.receive_slice(&mut buffer), Ok(0));
+    }
+
+    #[test]
+    fn received_layout() {
+        let medium = SimMedium::new(1);
+        let mut transmitter = medium.radio();
+        let mut receiver = medium.radio();
+        receiver.set_lqi(200);
+        transmitter.transmit(&[1, 2, 3], false).unwrap();
+        medium.advance(10_000);
+        let mut buffer = [0u8; 129];
+        let length = receiver.receive_slice(&mut buffer).unwrap();
+        assert_eq!(length, 5);
+        assert_eq!(buffer[0], 5);
+        assert_eq!(&buffer[1..length - 1], &[1, 2, 3]);
+        assert_eq!(buffer[length - 1], 200);
+    }
+
+    /// Send 32 frames over a medium with 50 % loss, returns a bit mask of
+    /// the received frames
+    fn lossy_pattern(seed: u32) -> u32 {
+        let medium = SimMedium::new(seed);
+        medium.set_loss(500);
+        let mut transmitter = medium.radio();
+        let mut receiver = medium.radio();
+        let mut buffer = [0u8; 129];
+        let mut pattern = 0;
+        for n in 0..32 {
+            transmitter.transmit(&[n as u8], false).unwrap();
+            medium.advance(10_000);
+            if receiver.receive_slice(&mut buffer) == Ok(3) {
+                pattern |= 1 << n;
+            }
+        }
+        pattern
+    }
+
+    #[test]
+    fn seeded_loss_is_deterministic() {
+        let pattern = lossy_pattern(7);
+        assert_eq!(lossy_pattern(7), pattern);
+        assert_ne!(pattern, 0);
+        assert_ne!(pattern, u32::MAX);
+    }
+
+    #[test]
+    fn delivery_delay() {
+        let medium = SimMedium::new(1);
+        medium.set_delay(1_000);
+        let mut transmitter = medium.radio();
+        let mut receiver = medium.radio();
+        let mut buffer = [0u8; 129];
+        transmitter.transmit(&[1, 2, 3], false).unwrap();
+        // Synchronisation header, PHR, three octets and FCS
+        medium.advance(11 * OCTET_DURATION);
+        assert!(!transmitter.is_tx_busy());
+        assert_eq!(receiver.receive_slice(&mut buffer), Ok(0));
+        medium.advance(999);
+        assert_eq!(receiver.receive_slice(&mut buffer), Ok(0));
+        medium.advance(1);
+        assert_eq!(receiver.receive_slice(&mut buffer), Ok(5));
+    }
+
+    #[test]
+    fn injected_cca_busy() {
+        let medium = SimMedium::new(1);
+        let mut transmitter = medium.radio();
+        let mut receiver = medium.radio();
+        let mut buffer = [0u8; 129];
+        transmitter.inject_cca_busy(1);
+        assert_eq!(transmitter.transmit(&[1, 2, 3], true), Ok(3));
+        assert!(transmitter.is_tx_busy());
+        assert_eq!(transmitter.receive_slice(&mut buffer), Err(Error::CcaBusy));
+        assert!(!transmitter.is_tx_busy());
+        medium.advance(10_000);
+        assert_eq!(receiver.receive_slice(&mut buffer), Ok(0));
+        // Only the injected assessment fails
+        transmitter.transmit(&[1, 2, 3], true).unwrap();
+        medium.advance(10_000);
+        assert_eq!(receiver.receive_slice(&mut buffer), Ok(5));
+    }
+
+    #[test]
+    fn channel_separation() {
+        let medium = SimMedium::new(1);
+        let mut transmitter = medium.radio();
+        let mut receiver = medium.radio();
+        let mut buffer = [0u8; 129];
+        receiver.set_channel(15).unwrap();
+        transmitter.transmit(&[1, 2, 3], false).unwrap();
+        medium.advance(10_000);
+        assert_eq!(receiver.receive_slice(&mut buffer), Ok(0));
+        transmitter.set_channel(15).unwrap();
+        transmitter.transmit(&[1, 2, 3], false).unwrap();
+        medium.advance(10_000);
+        assert_eq!(receiver.receive_slice(&mut buffer), Ok(5));
+    }
+
+    #[test]
+    fn own_frames_not_received() {
+        let medium = SimMedium::new(1);
+        let mut transmitter = medium.radio();
+        let mut receiver = medium.radio();
+        let mut buffer = [0u8; 129];
+        transmitter.transmit(&[1, 2, 3], false).unwrap();
+        medium.advance(10_000);
+        assert_eq!(transmitter.receive_slice(&mut buffer), Ok(0));
+        assert_eq!(receiver.receive_slice(&mut buffer), Ok(5));
+    }
+
+    #[test]
+    fn transmission_power_boundaries() {
+        let medium = SimMedium::new(1);
+        let mut radio = medium.radio();
+        assert_eq!(radio.set_transmission_power(8), Ok(()));
+        assert_eq!(radio.set_transmission_power(-40), Ok(()));
+        assert_eq!(radio.transmission_power(), -40);
+        assert_eq!(radio.set_transmission_power(9), Err(Error::InvalidPower));
+        assert_eq!(radio.set_transmission_power(1), Err(Error::InvalidPower));
+        assert_eq!(radio.set_transmission_power(-41), Err(Error::InvalidPower));
+        assert_eq!(radio.transmission_power(), -40);
+        assert_eq!(radio.set_channel(10), Err(Error::InvalidChannel));
+        assert_eq!(radio.set_channel(27), Err(Error::InvalidChannel));
+    }
+}
@@ -0,0 +1,431 @@
+//! # Sleepy end device scheduler
+//!
+//! Schedules the radio of a battery powered end device, which keeps the
+//! radio disabled most of the time and wakes up on a RTC compare event.
+//!
+//! * The parent is polled every poll interval, the radio is woken up for the
+//!   data request and stays receiving for the poll timeout or until the
+//!   parent reports that no data is pending. Polling also keeps the device
+//!   alive in the child table of the parent. When `max_missed_polls`
+//!   consecutive polls are not acknowledged `SleepEvent::ParentLost` is
+//!   reported and the scheduler stops.
+//! * In fast-poll mode the parent is polled with the shorter fast poll
+//!   interval, for a while after joining or during a transaction.
+//! * With low-power listening the radio is woken up for a short listen
+//!   window every listen interval, the window is extended while frames are
+//!   received.
+//!
+//! ```notrust
+//! |<------------ poll interval ------------>|
+//! | poll |   listen   |   listen   |   listen   | poll |
+//! |<-->| poll timeout
+//! ```
+//!
+//! The scheduler is driven by a compare channel of a RTC, call
+//! `Scheduler::on_rtc` from the RTC interrupt. The RTC shall be running with
+//! prescaler zero, clocked by the LFCLK, so that the HFCLK can be stopped
+//! while sleeping. The scheduler must be the only user of the compare
+//! channel.
+//!
+//! Times are given in milliseconds, at most `MAX_INTERVAL`.
+//!
+
+use core::ops::Deref;
+
+use crate::pac::rtc0;
+use crate::radio::Radio;
+
+/// RTC frequency with prescaler zero
+const RTC_FREQUENCY: u64 = 32768;
+/// The RTC counter is 24 bits
+const RTC_COUNTER_MASK: u32 = 0x00ff_ffff;
+/// Shift sign extending a 24 bit counter difference
+const RTC_COUNTER_SHIFT: u32 = 8;
+/// The compare value shall be at least two ticks ahead of the counter
+const RTC_MIN_TICKS: u32 = 2;
+/// Bit of the COMPARE0 event in INTENSET and INTENCLR
+const RTC_INTEN_COMPARE0: u32 = 16;
+/// Number of compare channels, RTC0 has the fewest
+const RTC_CHANNELS: usize = 3;
+
+/// Largest interval in milliseconds, half of the RTC counter range
+pub const MAX_INTERVAL: u32 = 256_000;
+
+/// Convert milliseconds to RTC ticks
+fn ticks(milliseconds: u32) -> u32 {
+    (u64::from(milliseconds) * RTC_FREQUENCY / 1000) as u32
+}
+
+/// Signed difference of two RTC counter values, `a - b`
+fn difference(a: u32, b: u32) -> i32 {
+    (a.wrapping_sub(b) << RTC_COUNTER_SHIFT) as i32 >> RTC_COUNTER_SHIFT
+}
+
+/// Check if `time` has been reached at `now`
+fn is_reached(now: u32, time: u32) -> bool {
+    difference(now, time) >= 0
+}
+
+/// Sleepy end device configuration, times in milliseconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SleepConfig {
+    /// Interval between polls of the parent
+    pub poll_interval: u32,
+    /// Interval between polls in fast-poll mode
+    pub fast_poll_interval: u32,
+    /// Time to wait for data from the parent after a poll
+    pub poll_timeout: u32,
+    /// Number of consecutive unacknowledged polls before the parent is lost
+    pub max_missed_polls: u8,
+    /// Low-power listening interval and listen window, or None
+    pub listen: Option<(u32, u32)>,
+}
+
+impl SleepConfig {
+    /// Check that the times are within range and that the windows are
+    /// shorter than the intervals
+    pub fn is_valid(&self) -> bool {
+        let listen = match self.listen {
+            Some((interval, window)) => interval <= MAX_INTERVAL && window > 0 && window < interval,
+            None => true,
+        };
+        self.poll_interval <= MAX_INTERVAL
+            && self.fast_poll_interval <= self.poll_interval
+            && self.poll_timeout > 0
+            && self.poll_timeout < self.fast_poll_interval
+            && self.max_missed_polls > 0
+            && listen
+    }
+}
+
+impl Default for SleepConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: 7_500,
+            fast_poll_interval: 250,
+            poll_timeout: 100,
+            max_missed_polls: 4,
+            listen: None,
+        }
+    }
+}
+
+/// Event reported by the scheduler
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SleepEvent {
+    /// The radio is receiving, send a data request to the parent
+    Poll,
+    /// The radio is receiving for a low-power listening window
+    Listen,
+    /// The radio has been disabled, the CPU may sleep until the next RTC
+    /// event
+    Sleep,
+    /// `max_missed_polls` consecutive polls were not acknowledged, the
+    /// scheduler has stopped
+    ParentLost,
+}
+
+/// What the radio is awake for
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Wake {
+    /// Waiting for the acknowledgement of, or data after, a poll
+    Poll,
+    /// In a low-power listening window
+    Listen,
+}
+
+/// Sleepy end device scheduler
+pub struct Scheduler {
+    /// RTC compare channel
+    channel: usize,
+    config: SleepConfig,
+    /// Time of the next poll in RTC ticks
+    next_poll: u32,
+    /// Time of the next listen window in RTC ticks
+    next_listen: Option<u32>,
+    /// End of fast-poll mode in RTC ticks
+    fast_poll: Option<u32>,
+    /// Radio awake and the end of the window in RTC ticks
+    awake: Option<(Wake, u32)>,
+    /// The last poll was acknowledged
+    acknowledged: bool,
+    /// Number of consecutive missed polls
+    missed: u8,
+    running: bool,
+}
+
+impl Scheduler {
+    /// Create a scheduler using the RTC compare channel `channel`, 0 to 2
+    ///
+    /// Returns None if the channel or the configuration is not valid.
+    pub fn new(channel: usize, config: SleepConfig) -> Option<Self> {
+        if channel >= RTC_CHANNELS || !config.is_valid() {
+            return None;
+        }
+        Some(Self {
+            channel,
+            config,
+            next_poll: 0,
+            next_listen: None,
+            fast_poll: None,
+            awake: None,
+            acknowledged: false,
+            missed: 0,
+            running: false,
+        })
+    }
+
+    /// Scheduler configuration
+    pub fn config(&self) -> SleepConfig {
+        self.config
+    }
+
+    /// Number of consecutive missed polls
+    pub fn missed_polls(&self) -> u8 {
+        self.missed
+    }
+
+    /// The radio is disabled
+    pub fn is_sleeping(&self) -> bool {
+        self.awake.is_none()
+    }
+
+    /// The parent is polled with the fast poll interval
+    pub fn is_fast_polling(&self) -> bool {
+        self.fast_poll.is_some()
+    }
+
+    /// Start with a poll of the parent, the radio is disabled until then
+    pub fn start<R, const RX: usize>(&mut self, rtc: &R, radio: &mut Radio<RX>)
+    where
+        R: Deref<Target = rtc0::RegisterBlock>,
+    {
+        let now = counter(rtc);
+        radio.disable();
+        self.running = true;
+        self.awake = None;
+        self.missed = 0;
+        self.next_poll = now;
+        self.next_listen = self
+            .config
+            .listen
+            .map(|(interval, _)| (now.wrapping_add(ticks(interval))) & RTC_COUNTER_MASK);
+        self.schedule(rtc, now);
+        rtc.intenset
+            .write(|w| unsafe { w.bits(1 << (RTC_INTEN_COMPARE0 + self.channel as u32)) });
+    }
+
+    /// Stop the scheduler, the radio is left as is
+    pub fn stop<R>(&mut self, rtc: &R)
+    where
+        R: Deref<Target = rtc0::RegisterBlock>,
+    {
+        self.running = false;
+        rtc.intenclr
+            .write(|w| unsafe { w.bits(1 << (RTC_INTEN_COMPARE0 + self.channel as u32)) });
+        rtc.events_compare[self.channel].reset();
+    }
+
+    /// Poll with the fast poll interval for `duration` milliseconds
+    pub fn fast_poll<R>(&mut self, rtc: &R, duration: u32)
+    where
+        R: Deref<Target = rtc0::RegisterBlock>,
+    {
+        let now = counter(rtc);
+        let duration = duration.min(MAX_INTERVAL);
+        self.fast_poll = Some(now.wrapping_add(ticks(duration)) & RTC_COUNTER_MASK);
+        let fast = now.wrapping_add(ticks(self.config.fast_poll_interval)) & RTC_COUNTER_MASK;
+        if difference(fast, self.next_poll) < 0 {
+            self.next_poll = fast;
+        }
+        self.schedule(rtc, now);
+    }
+
+    /// Return to the poll interval
+    pub fn stop_fast_poll(&mut self) {
+        self.fast_poll = None;
+    }
+
+    /// The parent acknowledged the data request, `pending` is the frame
+    /// pending flag of the acknowledgement
+    ///
+    /// Without pending data the radio is disabled at the next RTC event.
+    pub fn poll_acknowledged<R>(&mut self, rtc: &R, pending: bool)
+    where
+        R: Deref<Target = rtc0::RegisterBlock>,
+    {
+        if let Some((Wake::Poll, _)) = self.awake {
+            self.acknowledged = true;
+            self.missed = 0;
+            if !pending {
+                self.end_window(rtc);
+            }
+        }
+    }
+
+    /// A frame addressed to the device was received, `pending` is the frame
+    /// pending flag of the frame
+    ///
+    /// After a poll the parent is polled again while more data is pending.
+    /// In a listen window the window is extended.
+    pub fn frame_received<R>(&mut self, rtc: &R, pending: bool)
+    where
+        R: Deref<Target = rtc0::RegisterBlock>,
+    {
+        let now = counter(rtc);
+        match self.awake {
+            Some((Wake::Poll, _)) if pending => {
+                self.next_poll = now;
+                self.schedule(rtc, now);
+            }
+            Some((Wake::Poll, _)) => self.end_window(rtc),
+            Some((Wake::Listen, _)) => {
+                if let Some((_, window)) = self.config.listen {
+                    let end = now.wrapping_add(ticks(window)) & RTC_COUNTER_MASK;
+                    self.awake = Some((Wake::Listen, end));
+                    self.schedule(rtc, now);
+                }
+            }
+            None => (),
+        }
+    }
+
+    /// Handle the RTC compare event, call this from the RTC interrupt
+    ///
+    /// # Return
+    ///
+    /// Returns the event which has occurred, if any.
+    ///
+    pub fn on_rtc<R, const RX: usize>(
+        &mut self,
+        rtc: &R,
+        radio: &mut Radio<RX>,
+    ) -> Option<SleepEvent>
+    where
+        R: Deref<Target = rtc0::RegisterBlock>,
+    {
+        let compare = &rtc.events_compare[self.channel];
+        if !compare.read().events_compare().bit_is_set() {
+            return None;
+        }
+        compare.reset();
+        if !self.running {
+            return None;
+        }
+        let now = counter(rtc);
+        let event = self.process(radio, now);
+        if self.running {
+            self.schedule(rtc, now);
+        } else {
+            self.stop(rtc);
+        }
+        event
+    }
+
+    /// Handle the deadlines which have been reached at `now`, one at a time
+    fn process<const RX: usize>(&mut self, radio: &mut Radio<RX>, now: u32) -> Option<SleepEvent> {
+        if let Some(end) = self.fast_poll {
+            if is_reached(now, end) {
+                self.fast_poll = None;
+            }
+        }
+        if is_reached(now, self.next_poll) {
+            if let Some((Wake::Poll, _)) = self.awake {
+                if !self.acknowledged && self.missed_poll() {
+                    radio.disable();
+                    return Some(SleepEvent::ParentLost);
+                }
+            }
+            let interval = match self.fast_poll {
+                Some(_) => self.config.fast_poll_interval,
+                None => self.config.poll_interval,
+            };
+            self.next_poll = now.wrapping_add(ticks(interval)) & RTC_COUNTER_MASK;
+            let end = now.wrapping_add(ticks(self.config.poll_timeout)) & RTC_COUNTER_MASK;
+            self.awake = Some((Wake::Poll, end));
+            self.acknowledged = false;
+            radio.receive_prepare();
+            return Some(SleepEvent::Poll);
+        }
+        match self.awake {
+            Some((wake, end)) if is_reached(now, end) => {
+                if wake == Wake::Poll && !self.acknowledged && self.missed_poll() {
+                    radio.disable();
+                    return Some(SleepEvent::ParentLost);
+                }
+                self.awake = None;
+                radio.disable();
+                return Some(SleepEvent::Sleep);
+            }
+            _ => (),
+        }
+        if let (Some(next), Some((interval, window))) = (self.next_listen, self.config.listen) {
+            if is_reached(now, next) {
+                self.next_listen = Some(now.wrapping_add(ticks(interval)) & RTC_COUNTER_MASK);
+                if self.awake.is_none() {
+                    let end = now.wrapping_add(ticks(window)) & RTC_COUNTER_MASK;
+                    self.awake = Some((Wake::Listen, end));
+                    radio.receive_prepare();
+                    return Some(SleepEvent::Listen);
+                }
+            }
+        }
+        None
+    }
+
+    /// Count a missed poll, returns true if the parent is lost
+    fn missed_poll(&mut self) -> bool {
+        self.missed = self.missed.saturating_add(1);
+        if self.missed >= self.config.max_missed_polls {
+            self.running = false;
+            self.awake = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// End the current window at the next RTC event
+    fn end_window<R>(&mut self, rtc: &R)
+    where
+        R: Deref<Target = rtc0::RegisterBlock>,
+    {
+        let now = counter(rtc);
+        if let Some((wake, _)) = self.awake {
+            self.awake = Some((wake, now));
+            self.schedule(rtc, now);
+        }
+    }
+
+    /// Set the compare value to the earliest deadline
+    fn schedule<R>(&mut self, rtc: &R, now: u32)
+    where
+        R: Deref<Target = rtc0::RegisterBlock>,
+    {
+        let deadlines = [
+            Some(self.next_poll),
+            self.next_listen,
+            self.fast_poll,
+            self.awake.map(|(_, end)| end),
+        ];
+        let until = deadlines
+            .iter()
+            .flatten()
+            .map(|deadline| difference(*deadline, now))
+            .min()
+            .unwrap_or(0)
+            .max(RTC_MIN_TICKS as i32) as u32;
+        let target = now.wrapping_add(until) & RTC_COUNTER_MASK;
+        rtc.cc[self.channel].write(|w| unsafe { w.compare().bits(target) });
+    }
+}
+
+/// Current RTC counter value
+fn counter<R>(rtc: &R) -> u32
+where
+    R: Deref<Target = rtc0::RegisterBlock>,
+{
+    rtc.counter.read().counter().bits()
+}
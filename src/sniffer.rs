@@ -0,0 +1,127 @@
+//! # nRF Sniffer for 802.15.4 capture protocol
+//!
+//! Implements the line based protocol spoken by the nRF Sniffer for 802.15.4
+//! Wireshark extcap plug-in. Captured frames are written as,
+//!
+//! ```notrust
+//! received: <frame as hex> power: <RSSI dBm> lqi: <LQI> time: <timestamp μs>
+//! ```
+//!
+//! The host controls the sniffer with the commands `channel <n>`, `receive`
+//! and `sleep`, one command per line.
+//!
+
+use core::fmt::{self, Write};
+
+use crate::phy::is_valid_channel;
+use crate::radio::level_to_dbm;
+
+/// Largest line produced by `format_received`
+pub const MAX_LINE_LENGTH: usize = 320;
+
+/// Commands sent by the extcap plug-in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnifferCommand {
+    /// Select channel
+    Channel(u8),
+    /// Start capturing
+    Receive,
+    /// Stop capturing
+    Sleep,
+    /// Shell command, such as `shell echo off`, which can be acknowledged and ignored
+    Shell,
+}
+
+impl SnifferCommand {
+    /// Parse a command line, without line ending
+    pub fn parse(line: &[u8]) -> Option<Self> {
+        let line = core::str::from_utf8(line).ok()?.trim();
+        let mut parts = line.split_whitespace();
+        match parts.next()? {
+            "channel" => {
+                let channel = parts.next()?.parse::<u8>().ok()?;
+                if !is_valid_channel(channel) {
+                    return None;
+                }
+                Some(SnifferCommand::Channel(channel))
+            }
+            "receive" => Some(SnifferCommand::Receive),
+            "sleep" => Some(SnifferCommand::Sleep),
+            "shell" => Some(SnifferCommand::Shell),
+            _ => None,
+        }
+    }
+}
+
+/// Calculate the 802.15.4 frame check sequence (FCS)
+pub fn frame_check_sequence(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in data {
+        crc ^= u16::from(*byte);
+        for _ in 0..8 {
+            if crc & 1 == 1 {
+                crc = (crc >> 1) ^ 0x8408;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    crc
+}
+
+struct SliceWriter<'a> {
+    buffer: &'a mut [u8],
+    position: usize,
+}
+
+impl<'a> Write for SliceWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let bytes = s.as_bytes();
+        let end = self.position + bytes.len();
+        if end > self.buffer.len() {
+            return Err(fmt::Error);
+        }
+        self.buffer[self.position..end].copy_from_slice(bytes);
+        self.position = end;
+        Ok(())
+    }
+}
+
+/// Format a received frame as a capture line, including line ending
+///
+/// `frame` is the received frame without FCS, the FCS is recalculated since
+/// the radio does not store it. `lqi` is the link quality indicator as
+/// reported by the radio and `timestamp` the time of reception in
+/// microseconds.
+///
+/// # Return
+///
+/// Returns the number of octets written to the buffer.
+///
+pub fn format_received(
+    buffer: &mut [u8],
+    frame: &[u8],
+    lqi: u8,
+    timestamp: u64,
+) -> Result<usize, fmt::Error> {
+    let mut writer = SliceWriter {
+        buffer,
+        position: 0,
+    };
+    writer.write_str("received: ")?;
+    for byte in frame {
+        write!(writer, "{:02x}", byte)?;
+    }
+    let fcs = frame_check_sequence(frame);
+    for byte in fcs.to_le_bytes().iter() {
+        write!(writer, "{:02x}", byte)?;
+    }
+    write!(
+        writer,
+        " power: {} lqi: {} time: {}\r\n",
+        level_to_dbm(lqi),
+        lqi,
+        timestamp
+    )?;
+    Ok(writer.position)
+}
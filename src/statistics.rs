@@ -0,0 +1,271 @@
+//! # Radio statistics
+//!
+//! Counters kept by the radio driver, in total and for each destination.
+//!
+//! The radio counts transmissions, retransmissions and CCA deferrals for
+//! each destination. A frame queued with the same destination and sequence
+//! number as the previous frame is counted as a retransmission. The radio
+//! does not wait for acknowledgements, the MAC layer reports missing
+//! acknowledgements with `LinkStatistics::record_ack_failure`.
+//!
+//! The per destination table has a fixed capacity, when full the entry with
+//! the fewest transmissions is replaced.
+//!
+//! `ChannelScore` scores the operating channel from the counters collected
+//! during normal traffic, the CRC error rate, the fraction of transmissions
+//! deferred by a busy channel and the retransmission rate. The network layer
+//! can use the score to decide when to move to another channel, without
+//! dedicated scans.
+//!
+
+use crate::radio::Address;
+
+/// Number of destinations tracked by the radio
+pub const LINK_TABLE_LENGTH: usize = 8;
+
+/// Counters for a single destination
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkCounters {
+    /// Frames queued for transmission, including retransmissions
+    pub transmissions: u32,
+    /// Retransmissions
+    pub retries: u32,
+    /// Transmissions deferred by a busy channel
+    pub cca_deferrals: u32,
+    /// Transmissions which were not acknowledged
+    pub ack_failures: u32,
+}
+
+impl LinkCounters {
+    /// Ratio of transmissions which were not acknowledged, in per mille
+    pub fn ack_failure_rate(&self) -> u32 {
+        per_mille(self.ack_failures, self.transmissions)
+    }
+
+    /// Ratio of transmissions which were retransmissions, in per mille
+    pub fn retry_rate(&self) -> u32 {
+        per_mille(self.retries, self.transmissions)
+    }
+}
+
+fn per_mille(count: u32, total: u32) -> u32 {
+    if total == 0 {
+        0
+    } else {
+        ((u64::from(count) * 1000) / u64::from(total)) as u32
+    }
+}
+
+/// Counters for up to `N` destinations
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkStatistics<const N: usize> {
+    entries: [Option<(Address, LinkCounters)>; N],
+}
+
+impl<const N: usize> LinkStatistics<N> {
+    /// Create a empty table
+    pub const fn new() -> Self {
+        Self { entries: [None; N] }
+    }
+
+    /// Counters for the destination, if tracked
+    pub fn get(&self, destination: Address) -> Option<&LinkCounters> {
+        self.entries
+            .iter()
+            .flatten()
+            .find(|(address, _)| *address == destination)
+            .map(|(_, counters)| counters)
+    }
+
+    /// Iterate over the tracked destinations
+    pub fn iter(&self) -> impl Iterator<Item = &(Address, LinkCounters)> {
+        self.entries.iter().flatten()
+    }
+
+    /// Forget all destinations
+    pub fn clear(&mut self) {
+        self.entries = [None; N];
+    }
+
+    fn entry(&mut self, destination: Address) -> Option<&mut LinkCounters> {
+        let index = match self
+            .entries
+            .iter()
+            .position(|entry| matches!(entry, Some((address, _)) if *address == destination))
+        {
+            Some(index) => index,
+            None => {
+                let index = self
+                    .entries
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, entry)| entry.map(|(_, counters)| counters.transmissions))
+                    .map(|(index, _)| index)?;
+                self.entries[index] = Some((destination, LinkCounters::default()));
+                index
+            }
+        };
+        self.entries[index].as_mut().map(|(_, counters)| counters)
+    }
+
+    /// Record a transmission to the destination
+    pub fn record_transmission(&mut self, destination: Address, retry: bool) {
+        if let Some(counters) = self.entry(destination) {
+            counters.transmissions = counters.transmissions.saturating_add(1);
+            if retry {
+                counters.retries = counters.retries.saturating_add(1);
+            }
+        }
+    }
+
+    /// Record a transmission to the destination deferred by a busy channel
+    pub fn record_cca_deferral(&mut self, destination: Address) {
+        if let Some(counters) = self.entry(destination) {
+            counters.cca_deferrals = counters.cca_deferrals.saturating_add(1);
+        }
+    }
+
+    /// Record a transmission to the destination which was not acknowledged
+    pub fn record_ack_failure(&mut self, destination: Address) {
+        if let Some(counters) = self.entry(destination) {
+            counters.ack_failures = counters.ack_failures.saturating_add(1);
+        }
+    }
+}
+
+impl<const N: usize> Default for LinkStatistics<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Radio counters
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Statistics {
+    /// Frames queued for transmission
+    pub transmitted: u32,
+    /// Retransmissions
+    pub retries: u32,
+    /// Transmissions deferred by a busy channel
+    pub cca_busy: u32,
+    /// Frames received with a valid CRC
+    pub received: u32,
+    /// Frames received with a CRC error
+    pub crc_errors: u32,
+    /// Received frames lost because the receive buffer or queue was full
+    pub rx_overflows: u32,
+    /// Counters for each destination
+    pub links: LinkStatistics<LINK_TABLE_LENGTH>,
+}
+
+impl Statistics {
+    /// Reset all counters
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Quality of the operating channel, over a sample period
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelQuality {
+    /// Ratio of received frames with CRC errors, in per mille
+    pub crc_error_rate: u32,
+    /// Ratio of transmissions deferred by a busy channel, in per mille
+    pub cca_busy_rate: u32,
+    /// Ratio of transmissions which were retransmissions, in per mille
+    pub retry_rate: u32,
+}
+
+impl ChannelQuality {
+    /// Score in per mille, 1000 is a perfect channel
+    ///
+    /// The score is reduced by the mean of the three rates.
+    pub fn score(&self) -> u32 {
+        let penalty = (self.crc_error_rate + self.cca_busy_rate + self.retry_rate) / 3;
+        1000u32.saturating_sub(penalty)
+    }
+}
+
+/// Score of the operating channel, smoothed over sample periods
+///
+/// Call `ChannelScore::update` periodically with the radio counters. Call
+/// `ChannelScore::reset` when changing channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChannelScore {
+    /// Counters at the previous update
+    transmitted: u32,
+    retries: u32,
+    cca_busy: u32,
+    received: u32,
+    crc_errors: u32,
+    /// Smoothed score in per mille, None before the first sample with traffic
+    score: Option<u32>,
+}
+
+impl ChannelScore {
+    /// Create a channel score without samples
+    pub const fn new() -> Self {
+        Self {
+            transmitted: 0,
+            retries: 0,
+            cca_busy: 0,
+            received: 0,
+            crc_errors: 0,
+            score: None,
+        }
+    }
+
+    /// Forget the samples, keeping the current counters as the reference
+    pub fn reset(&mut self, statistics: &Statistics) {
+        *self = Self::new();
+        self.remember(statistics);
+    }
+
+    fn remember(&mut self, statistics: &Statistics) {
+        self.transmitted = statistics.transmitted;
+        self.retries = statistics.retries;
+        self.cca_busy = statistics.cca_busy;
+        self.received = statistics.received;
+        self.crc_errors = statistics.crc_errors;
+    }
+
+    /// Sample the counters since the previous update
+    ///
+    /// # Return
+    ///
+    /// Returns the quality of the sample period, or None if there was no
+    /// traffic.
+    ///
+    pub fn update(&mut self, statistics: &Statistics) -> Option<ChannelQuality> {
+        let transmitted = statistics.transmitted.wrapping_sub(self.transmitted);
+        let retries = statistics.retries.wrapping_sub(self.retries);
+        let cca_busy = statistics.cca_busy.wrapping_sub(self.cca_busy);
+        let received = statistics.received.wrapping_sub(self.received);
+        let crc_errors = statistics.crc_errors.wrapping_sub(self.crc_errors);
+        self.remember(statistics);
+        if transmitted == 0 && received == 0 && crc_errors == 0 {
+            return None;
+        }
+        let quality = ChannelQuality {
+            crc_error_rate: per_mille(crc_errors, received.saturating_add(crc_errors)),
+            cca_busy_rate: per_mille(cca_busy.min(transmitted), transmitted),
+            retry_rate: per_mille(retries.min(transmitted), transmitted),
+        };
+        let sample = quality.score();
+        // Exponential moving average, each sample weighs one quarter
+        self.score = Some(match self.score {
+            Some(score) => (score * 3 + sample) / 4,
+            None => sample,
+        });
+        Some(quality)
+    }
+
+    /// Smoothed score in per mille, 1000 is a perfect channel
+    ///
+    /// Returns None until a sample period with traffic has been seen.
+    pub fn score(&self) -> Option<u32> {
+        self.score
+    }
+}
@@ -0,0 +1,153 @@
+//! # Persistent storage
+//!
+//! Word addressed storage organised in pages, with the semantics of NOR
+//! flash. A erased word reads as `ERASED` and can be written once, a page is
+//! erased as a whole.
+//!
+//! The `Nvmc` backend uses the internal flash through the NVMC peripheral.
+//! With the `mock` feature `MockStorage` keeps the pages in RAM, for
+//! host-side testing.
+//!
+
+#[cfg(feature = "device")]
+use core::sync::atomic::{compiler_fence, Ordering};
+
+#[cfg(feature = "device")]
+use crate::pac::NVMC;
+
+#[cfg(feature = "mock")]
+mod mock;
+
+#[cfg(feature = "mock")]
+pub use mock::{MockStorage, MockStorageError};
+
+/// Value of a erased word
+pub const ERASED: u32 = 0xffff_ffff;
+
+/// Page and word addressed storage
+pub trait WordStorage {
+    /// Storage error
+    type Error;
+
+    /// Number of pages
+    fn pages(&self) -> usize;
+    /// Number of words in a page
+    fn page_words(&self) -> usize;
+    /// Read the word at `index` in `page`
+    fn read(&self, page: usize, index: usize) -> u32;
+    /// Write a erased word at `index` in `page`
+    fn write(&mut self, page: usize, index: usize, value: u32) -> Result<(), Self::Error>;
+    /// Erase `page`
+    fn erase(&mut self, page: usize) -> Result<(), Self::Error>;
+}
+
+/// Internal flash page size in octets
+pub const FLASH_PAGE_SIZE: u32 = 4096;
+
+/// Errors returned by the internal flash storage
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NvmcError {
+    /// The page or word is outside the storage region
+    OutOfBounds,
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for NvmcError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            NvmcError::OutOfBounds => f.write_str("outside storage region"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::error::Error for NvmcError {}
+
+/// Storage in a region of the internal flash
+#[cfg(feature = "device")]
+pub struct Nvmc {
+    nvmc: NVMC,
+    address: u32,
+    pages: usize,
+}
+
+#[cfg(feature = "device")]
+impl Nvmc {
+    /// Use `pages` flash pages starting at `address` as storage
+    ///
+    /// Returns None if `address` is not page aligned.
+    ///
+    /// # Safety
+    ///
+    /// The flash region must not hold code or data used by the program.
+    pub unsafe fn new(nvmc: NVMC, address: u32, pages: usize) -> Option<Self> {
+        if !address.is_multiple_of(FLASH_PAGE_SIZE) {
+            return None;
+        }
+        Some(Self {
+            nvmc,
+            address,
+            pages,
+        })
+    }
+
+    /// Release the NVMC peripheral
+    pub fn free(self) -> NVMC {
+        self.nvmc
+    }
+
+    fn word_address(&self, page: usize, index: usize) -> Result<u32, NvmcError> {
+        if page >= self.pages || index >= self.page_words() {
+            return Err(NvmcError::OutOfBounds);
+        }
+        Ok(self.address + page as u32 * FLASH_PAGE_SIZE + index as u32 * 4)
+    }
+
+    fn wait_ready(&self) {
+        while self.nvmc.ready.read().ready().is_busy() {}
+    }
+}
+
+#[cfg(feature = "device")]
+impl WordStorage for Nvmc {
+    type Error = NvmcError;
+
+    fn pages(&self) -> usize {
+        self.pages
+    }
+
+    fn page_words(&self) -> usize {
+        (FLASH_PAGE_SIZE / 4) as usize
+    }
+
+    fn read(&self, page: usize, index: usize) -> u32 {
+        match self.word_address(page, index) {
+            Ok(address) => unsafe { core::ptr::read_volatile(address as *const u32) },
+            Err(_) => ERASED,
+        }
+    }
+
+    fn write(&mut self, page: usize, index: usize, value: u32) -> Result<(), Self::Error> {
+        let address = self.word_address(page, index)?;
+        self.nvmc.config.write(|w| w.wen().wen());
+        self.wait_ready();
+        compiler_fence(Ordering::SeqCst);
+        unsafe { core::ptr::write_volatile(address as *mut u32, value) };
+        self.wait_ready();
+        self.nvmc.config.write(|w| w.wen().ren());
+        Ok(())
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), Self::Error> {
+        let address = self.word_address(page, 0)?;
+        self.nvmc.config.write(|w| w.wen().een());
+        self.wait_ready();
+        self.nvmc
+            .erasepage()
+            .write(|w| unsafe { w.erasepage().bits(address) });
+        self.wait_ready();
+        self.nvmc.config.write(|w| w.wen().ren());
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! Storage in RAM, for host-side testing
+
+use super::{WordStorage, ERASED};
+
+/// Errors returned by the RAM storage
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MockStorageError {
+    /// The page or word is outside the storage
+    OutOfBounds,
+    /// The word has already been written since the page was erased
+    NotErased,
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for MockStorageError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MockStorageError::OutOfBounds => f.write_str("outside storage"),
+            MockStorageError::NotErased => f.write_str("word not erased"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::error::Error for MockStorageError {}
+
+/// A `WordStorage` of `P` pages of `W` words in RAM
+///
+/// Writes follow the flash semantics, a word can only be written once after
+/// the page has been erased. Moving the storage to a new user simulates a
+/// reboot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockStorage<const P: usize, const W: usize> {
+    pages: [[u32; W]; P],
+    erases: [u32; P],
+}
+
+impl<const P: usize, const W: usize> MockStorage<P, W> {
+    /// Create a storage with all pages erased
+    pub const fn new() -> Self {
+        Self {
+            pages: [[ERASED; W]; P],
+            erases: [0; P],
+        }
+    }
+
+    /// Number of times `page` has been erased
+    pub fn erases(&self, page: usize) -> u32 {
+        self.erases[page]
+    }
+}
+
+impl<const P: usize, const W: usize> Default for MockStorage<P, W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const P: usize, const W: usize> WordStorage for MockStorage<P, W> {
+    type Error = MockStorageError;
+
+    fn pages(&self) -> usize {
+        P
+    }
+
+    fn page_words(&self) -> usize {
+        W
+    }
+
+    fn read(&self, page: usize, index: usize) -> u32 {
+        match self.pages.get(page).and_then(|page| page.get(index)) {
+            Some(word) => *word,
+            None => ERASED,
+        }
+    }
+
+    fn write(&mut self, page: usize, index: usize, value: u32) -> Result<(), Self::Error> {
+        let word = self
+            .pages
+            .get_mut(page)
+            .and_then(|page| page.get_mut(index))
+            .ok_or(MockStorageError::OutOfBounds)?;
+        if *word != ERASED {
+            return Err(MockStorageError::NotErased);
+        }
+        *word = value;
+        Ok(())
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), Self::Error> {
+        let words = self
+            .pages
+            .get_mut(page)
+            .ok_or(MockStorageError::OutOfBounds)?;
+        words.iter_mut().for_each(|word| *word = ERASED);
+        self.erases[page] += 1;
+        Ok(())
+    }
+}
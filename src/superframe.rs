@@ -0,0 +1,358 @@
+//! # Superframe scheduler
+//!
+//! Timing core for beacon-enabled operation. The scheduler keeps the
+//! superframe boundaries, from the beacon order (BO) and superframe order
+//! (SO), and reports the start of the beacon, each of the 16 slots and the
+//! inactive period.
+//!
+//! ```notrust
+//! |<---------------------- beacon interval ---------------------->|
+//! |<------- superframe duration ------->|                          |
+//! | B | 1 | 2 | ... | CAP | GTS | ... | 15 |        inactive         | B |
+//! ```
+//!
+//! The scheduler is driven by a compare channel of a `Timer`, call
+//! `Scheduler::on_timer` from the timer interrupt. Slots can be assigned a
+//! transmit or receive window, reported with the start of the slot.
+//!
+//! The scheduler must be the only user of the compare channel.
+//!
+//! ## Beacon-enabled PAN
+//!
+//! A device tracks the beacons of its coordinator with `Scheduler::track`,
+//! and resynchronises on each received beacon with
+//! `Scheduler::beacon_received`. When `MAX_LOST_BEACONS` consecutive beacons
+//! are missed `SlotEvent::BeaconLost` is reported and the scheduler stops.
+//!
+//! CSMA transmissions are only allowed in the contention access period
+//! (CAP), `Scheduler::queue_transmission` queues a transmission only if the
+//! transaction, including the acknowledgement, ends before the CAP does.
+//!
+
+use crate::radio::{Error, Radio};
+use crate::timer::Timer;
+
+/// Symbol duration in microseconds
+pub const SYMBOL_DURATION: u32 = 16;
+/// Duration of a slot with superframe order 0, in symbols
+pub const BASE_SLOT_DURATION: u32 = 60;
+/// Number of slots in a superframe
+pub const SLOT_COUNT: u8 = 16;
+/// Duration of a superframe with superframe order 0, in symbols
+pub const BASE_SUPERFRAME_DURATION: u32 = BASE_SLOT_DURATION * SLOT_COUNT as u32;
+/// Largest beacon order of beacon-enabled operation
+pub const MAX_BEACON_ORDER: u8 = 14;
+/// Number of consecutive missed beacons before the beacon is lost,
+/// aMaxLostBeacons
+pub const MAX_LOST_BEACONS: u8 = 4;
+
+/// Clear channel assessment and turnaround to transmit, in symbols
+const CCA_DURATION: u32 = 8 + 12;
+/// Synchronisation header and PHR, in symbols
+const SHR_PHR_DURATION: u32 = 12;
+/// Turnaround and acknowledgement frame, in symbols
+const ACK_DURATION: u32 = 12 + SHR_PHR_DURATION + 10;
+
+/// Duration of a CSMA transaction of a frame with `length` octets,
+/// including FCS, in microseconds
+fn transaction_duration(length: usize) -> u32 {
+    (CCA_DURATION + SHR_PHR_DURATION + length as u32 * 2 + ACK_DURATION) * SYMBOL_DURATION
+}
+
+/// Superframe specification
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SuperframeConfig {
+    /// Beacon order, 0 to 14
+    pub beacon_order: u8,
+    /// Superframe order, 0 to the beacon order
+    pub superframe_order: u8,
+    /// Last slot of the contention access period
+    pub final_cap_slot: u8,
+}
+
+impl SuperframeConfig {
+    /// Check the orders and final CAP slot
+    pub fn is_valid(&self) -> bool {
+        self.beacon_order <= MAX_BEACON_ORDER
+            && self.superframe_order <= self.beacon_order
+            && self.final_cap_slot < SLOT_COUNT
+    }
+
+    /// Beacon interval in microseconds
+    pub fn beacon_interval(&self) -> u32 {
+        (BASE_SUPERFRAME_DURATION * SYMBOL_DURATION) << self.beacon_order
+    }
+
+    /// Superframe duration in microseconds
+    pub fn superframe_duration(&self) -> u32 {
+        (BASE_SUPERFRAME_DURATION * SYMBOL_DURATION) << self.superframe_order
+    }
+
+    /// Slot duration in microseconds
+    pub fn slot_duration(&self) -> u32 {
+        (BASE_SLOT_DURATION * SYMBOL_DURATION) << self.superframe_order
+    }
+}
+
+impl Default for SuperframeConfig {
+    fn default() -> Self {
+        Self {
+            beacon_order: 6,
+            superframe_order: 6,
+            final_cap_slot: SLOT_COUNT - 1,
+        }
+    }
+}
+
+/// Window scheduled in a slot
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Window {
+    /// Transmit in the slot
+    Transmit,
+    /// Receive in the slot
+    Receive,
+}
+
+/// Superframe boundary reported by the scheduler
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SlotEvent {
+    /// Start of the beacon, and of slot 0
+    Beacon,
+    /// Start of a slot
+    Slot {
+        /// Slot index, 1 to 15
+        index: u8,
+        /// The slot is in the contention access period
+        contention: bool,
+        /// Window scheduled in the slot
+        window: Option<Window>,
+    },
+    /// Start of the inactive period, the radio may be turned off
+    Inactive,
+    /// `MAX_LOST_BEACONS` consecutive beacons were missed, the scheduler
+    /// has stopped
+    BeaconLost,
+}
+
+/// Superframe scheduler
+pub struct Scheduler {
+    /// Timer compare channel
+    channel: usize,
+    config: SuperframeConfig,
+    /// Time of the current beacon in microseconds
+    beacon: u32,
+    /// Next boundary, slot index or `SLOT_COUNT` for the inactive period
+    next: u8,
+    /// Compare value of the channel
+    compare: u32,
+    windows: [Option<Window>; SLOT_COUNT as usize],
+    /// Tracking the beacons of a coordinator
+    tracking: bool,
+    /// A beacon was received in the current beacon interval
+    received: bool,
+    /// Number of consecutive missed beacons
+    lost: u8,
+}
+
+impl Scheduler {
+    /// Create a scheduler using the timer compare channel `channel`, 1 to 3
+    ///
+    /// Returns None if the configuration is not valid for beacon-enabled
+    /// operation.
+    pub fn new(channel: usize, config: SuperframeConfig) -> Option<Self> {
+        if !config.is_valid() {
+            return None;
+        }
+        Some(Self {
+            channel,
+            config,
+            beacon: 0,
+            next: 0,
+            compare: 0,
+            windows: [None; SLOT_COUNT as usize],
+            tracking: false,
+            received: false,
+            lost: 0,
+        })
+    }
+
+    /// Superframe configuration
+    pub fn config(&self) -> SuperframeConfig {
+        self.config
+    }
+
+    /// Time of the current beacon in microseconds
+    pub fn beacon(&self) -> u32 {
+        self.beacon
+    }
+
+    /// Start time of slot `index` in the current superframe, in microseconds
+    pub fn slot_start(&self, index: u8) -> u32 {
+        self.beacon
+            .wrapping_add(u32::from(index) * self.config.slot_duration())
+    }
+
+    /// Schedule a window in slot `index`, 1 to 15, for every superframe
+    ///
+    /// Returns false if the slot index is out of range.
+    pub fn set_window(&mut self, index: u8, window: Option<Window>) -> bool {
+        if index == 0 || index >= SLOT_COUNT {
+            return false;
+        }
+        self.windows[usize::from(index)] = window;
+        true
+    }
+
+    /// Start, or synchronise, with a beacon at `beacon` microseconds
+    ///
+    /// A coordinator starts with a beacon slightly in the future, a device
+    /// synchronises to the start of a received beacon. The next reported
+    /// boundary is the first one after the current time.
+    pub fn start<T: Timer>(&mut self, timer: &mut T, beacon: u32) {
+        let now = timer.now();
+        self.beacon = beacon;
+        self.next = 0;
+        // Skip boundaries which have passed, the difference is signed
+        while (self.boundary(self.next).wrapping_sub(now) as i32) < 0 {
+            self.advance();
+        }
+        self.schedule(timer);
+    }
+
+    /// Stop reporting boundaries
+    pub fn stop<T: Timer>(&mut self, timer: &mut T) {
+        self.tracking = false;
+        timer.stop(self.channel);
+    }
+
+    /// Start tracking the beacons of a coordinator, from a beacon received
+    /// at `beacon` microseconds
+    pub fn track<T: Timer>(&mut self, timer: &mut T, beacon: u32) {
+        self.tracking = true;
+        self.received = true;
+        self.lost = 0;
+        self.start(timer, beacon);
+    }
+
+    /// Resynchronise to a beacon received at `beacon` microseconds, the start
+    /// of the beacon frame
+    pub fn beacon_received<T: Timer>(&mut self, timer: &mut T, beacon: u32) {
+        if self.tracking {
+            self.received = true;
+            self.lost = 0;
+            self.start(timer, beacon);
+        }
+    }
+
+    /// Number of consecutive missed beacons
+    pub fn lost_beacons(&self) -> u8 {
+        self.lost
+    }
+
+    /// Time left of the contention access period at `now` microseconds
+    ///
+    /// Returns None outside of the contention access period.
+    pub fn cap_remaining(&self, now: u32) -> Option<u32> {
+        let end = self.slot_start(self.config.final_cap_slot + 1);
+        let elapsed = now.wrapping_sub(self.beacon);
+        let length = end.wrapping_sub(self.beacon);
+        if elapsed < length {
+            Some(length - elapsed)
+        } else {
+            None
+        }
+    }
+
+    /// Queue a transmission with clear channel assessment, if it fits in the
+    /// contention access period
+    ///
+    /// `data` should contain the packet payload to be sent without the PHR
+    /// and FCS.
+    ///
+    /// # Return
+    ///
+    /// Returns the number of bytes queued for transmission, or
+    /// `Error::InvalidState` if the transaction does not end before the
+    /// contention access period.
+    ///
+    pub fn queue_transmission<const RX: usize>(
+        &self,
+        radio: &mut Radio<RX>,
+        now: u32,
+        data: &[u8],
+    ) -> Result<usize, Error> {
+        match self.cap_remaining(now) {
+            Some(remaining) if transaction_duration(data.len() + 2) <= remaining => {
+                radio.queue_transmission(data)
+            }
+            _ => Err(Error::InvalidState),
+        }
+    }
+
+    /// Handle the timer compare event, call this from the timer interrupt
+    ///
+    /// # Return
+    ///
+    /// Returns the boundary which has been reached.
+    ///
+    pub fn on_timer<T: Timer>(&mut self, timer: &mut T) -> Option<SlotEvent> {
+        if !timer.is_compare_event(self.channel) {
+            return None;
+        }
+        timer.ack_compare_event(self.channel);
+        if self.next == 0 && self.tracking {
+            if self.received {
+                self.received = false;
+            } else {
+                self.lost += 1;
+                if self.lost >= MAX_LOST_BEACONS {
+                    self.stop(timer);
+                    return Some(SlotEvent::BeaconLost);
+                }
+            }
+        }
+        let event = match self.next {
+            0 => SlotEvent::Beacon,
+            SLOT_COUNT => SlotEvent::Inactive,
+            index => SlotEvent::Slot {
+                index,
+                contention: index <= self.config.final_cap_slot,
+                window: self.windows[usize::from(index)],
+            },
+        };
+        self.advance();
+        self.schedule(timer);
+        Some(event)
+    }
+
+    /// Time of the boundary `next` in microseconds
+    fn boundary(&self, next: u8) -> u32 {
+        match next {
+            SLOT_COUNT => self.beacon.wrapping_add(self.config.superframe_duration()),
+            index => self.slot_start(index),
+        }
+    }
+
+    /// Move to the following boundary
+    fn advance(&mut self) {
+        let inactive = self.config.superframe_order < self.config.beacon_order;
+        self.next = match self.next {
+            index if index < SLOT_COUNT - 1 => index + 1,
+            index if index == SLOT_COUNT - 1 && inactive => SLOT_COUNT,
+            _ => {
+                self.beacon = self.beacon.wrapping_add(self.config.beacon_interval());
+                0
+            }
+        };
+    }
+
+    fn schedule<T: Timer>(&mut self, timer: &mut T) {
+        let target = self.boundary(self.next);
+        // fire_in counts from the previous compare value
+        timer.fire_in(self.channel, target.wrapping_sub(self.compare));
+        self.compare = target;
+    }
+}
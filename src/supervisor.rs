@@ -0,0 +1,157 @@
+//! # Radio deadline supervisor
+//!
+//! Detects the radio remaining in a transient state, such as ramping up or
+//! transmitting, beyond a deadline. The radio is then recovered, put back in
+//! receive mode, and a diagnostic event is reported.
+//!
+//! The supervisor is driven by a compare channel of a `Timer`, call
+//! `Supervisor::on_timer` from the timer interrupt. Alternatively call
+//! `Supervisor::poll` periodically with a microsecond timestamp.
+//!
+
+use crate::event_log::Event;
+use crate::pac::radio::state::STATE_A;
+use crate::radio::state::{RX_DISABLE, RX_IDLE, RX_RU, TX_DISABLE, TX_IDLE, TX_RU};
+use crate::radio::Radio;
+use crate::timer::{is_valid_channel, Timer, TimerError};
+
+/// Deadlines in microseconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Deadlines {
+    /// Ramp up, ramp down and idle states
+    pub transition: u32,
+    /// Transmitting a frame
+    pub transmit: u32,
+    /// Transmission queued, from queueing until back in receive
+    pub send: u32,
+}
+
+impl Default for Deadlines {
+    fn default() -> Self {
+        Self {
+            // Ramp up is 40 μs or 140 μs, CCA is 128 μs
+            transition: 2_000,
+            // Longest frame with preamble is 133 octets or 4256 μs
+            transmit: 10_000,
+            send: 20_000,
+        }
+    }
+}
+
+/// Deadline supervisor
+pub struct Supervisor {
+    /// Timer compare channel
+    channel: usize,
+    /// Interval between checks in microseconds
+    interval: u32,
+    deadlines: Deadlines,
+    /// Observed state, busy sending flag and the time it was first observed
+    observed: Option<(STATE_A, bool, u32)>,
+    recoveries: u32,
+}
+
+impl Supervisor {
+    /// Create a supervisor using the timer compare channel `channel`, 1 to 3,
+    /// checking every `interval` microseconds
+    ///
+    /// Returns `TimerError::InvalidChannel` if the channel is not a compare
+    /// channel.
+    pub fn new(channel: usize, interval: u32, deadlines: Deadlines) -> Result<Self, TimerError> {
+        if !is_valid_channel(channel) {
+            return Err(TimerError::InvalidChannel);
+        }
+        Ok(Self {
+            channel,
+            interval,
+            deadlines,
+            observed: None,
+            recoveries: 0,
+        })
+    }
+
+    /// Start the periodic checks
+    pub fn start<T: Timer>(&mut self, timer: &mut T) {
+        self.observed = None;
+        let scheduled = timer.fire_in(self.channel, self.interval);
+        // The channel was checked by `new`
+        debug_assert!(scheduled);
+    }
+
+    /// Stop the periodic checks
+    pub fn stop<T: Timer>(&mut self, timer: &mut T) {
+        let stopped = timer.stop(self.channel);
+        debug_assert!(stopped);
+    }
+
+    /// Number of times the radio has been recovered
+    pub fn recoveries(&self) -> u32 {
+        self.recoveries
+    }
+
+    /// Handle the timer compare event, call this from the timer interrupt
+    ///
+    /// # Return
+    ///
+    /// Returns a `Event::Stuck` if the radio was recovered.
+    ///
+    pub fn on_timer<T: Timer, const RX: usize>(
+        &mut self,
+        timer: &mut T,
+        radio: &mut Radio<RX>,
+    ) -> Option<Event> {
+        if !timer.is_compare_event(self.channel) {
+            return None;
+        }
+        timer.ack_compare_event(self.channel);
+        let scheduled = timer.fire_in(self.channel, self.interval);
+        debug_assert!(scheduled);
+        self.poll(radio, timer.now())
+    }
+
+    /// Deadline for the state, or None if the radio may stay in the state
+    fn deadline(&self, state: STATE_A, sending: bool) -> Option<u32> {
+        match state {
+            STATE_A::TX => Some(self.deadlines.transmit),
+            RX_RU | TX_RU | TX_IDLE | RX_DISABLE | TX_DISABLE => Some(self.deadlines.transition),
+            STATE_A::DISABLED | RX_IDLE | STATE_A::RX if sending => Some(self.deadlines.send),
+            STATE_A::DISABLED | RX_IDLE | STATE_A::RX => None,
+        }
+    }
+
+    /// Check the radio state, `now` is a timestamp in microseconds
+    ///
+    /// # Return
+    ///
+    /// Returns a `Event::Stuck` if the radio was recovered.
+    ///
+    pub fn poll<const RX: usize>(&mut self, radio: &mut Radio<RX>, now: u32) -> Option<Event> {
+        let state = radio.state();
+        let sending = radio.is_tx_busy();
+        let since = match self.observed {
+            Some((observed, observed_sending, since))
+                if observed == state && observed_sending == sending =>
+            {
+                since
+            }
+            _ => {
+                self.observed = Some((state, sending, now));
+                now
+            }
+        };
+        let elapsed = now.wrapping_sub(since);
+        match self.deadline(state, sending) {
+            Some(deadline) if elapsed > deadline => {
+                radio.recover();
+                self.observed = None;
+                self.recoveries = self.recoveries.wrapping_add(1);
+                let elapsed = (elapsed / 1000).min(u32::from(u16::MAX)) as u16;
+                Some(Event::Stuck {
+                    state: u8::from(state),
+                    elapsed,
+                })
+            }
+            _ => None,
+        }
+    }
+}
@@ -1,7 +1,59 @@
 //! Timer functions for the nRF52 TIMER peripheral
+//!
+//! With the `mock` feature `MockTimer` provides a timer for host-side
+//! testing, where time is advanced by the test.
+//!
+//! `HybridClock` keeps time on the RTC and starts a TIMER only around radio
+//! activity, so that long sleeps don't need the HFCLK.
 
+#[cfg(feature = "device")]
+mod hybrid;
+#[cfg(feature = "mock")]
+mod mock;
+
+#[cfg(feature = "device")]
 use crate::pac::{TIMER0, TIMER1};
 
+#[cfg(feature = "device")]
+pub use hybrid::HybridClock;
+#[cfg(feature = "mock")]
+pub use mock::MockTimer;
+
+/// Number of compare channels of TIMER0 and TIMER1, CC0 is used for
+/// capturing the time
+const CHANNELS: usize = 4;
+
+/// Check that `id` is a compare channel usable for events, 1 to 3
+///
+/// Panics on a invalid channel with the `defensive-panic` feature.
+pub(crate) fn is_valid_channel(id: usize) -> bool {
+    let valid = id > 0 && id < CHANNELS;
+    if !valid && cfg!(feature = "defensive-panic") {
+        panic!("Bad compare channel");
+    }
+    valid
+}
+
+/// Errors returned by the clocks
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimerError {
+    /// The compare channel does not exist
+    InvalidChannel,
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Display for TimerError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TimerError::InvalidChannel => f.write_str("invalid compare channel"),
+        }
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::error::Error for TimerError {}
+
 /// Timer trait
 pub trait Timer {
     /// Initialise and start the TIMER.
@@ -10,10 +62,22 @@ pub trait Timer {
     /// CC0 is used as a free-running timer.
     /// CC1 to CC3 can be used to trigger events when time has elapsed.
     fn init(&mut self);
+    /// Stop the TIMER to save power, `init` starts it again from zero.
+    ///
+    /// The default implementation only disables the compare events.
+    fn shutdown(&mut self) {
+        for id in 1..4 {
+            self.stop(id);
+        }
+    }
     /// Configure compare CC[`id`] to fire after `elapsed` microseconds.
-    fn fire_in(&mut self, id: usize, elapsed: u32);
+    ///
+    /// Returns false if `id` is not a compare channel, 1 to 3.
+    fn fire_in(&mut self, id: usize, elapsed: u32) -> bool;
     /// Disable events for compare CC[`id`].
-    fn stop(&mut self, id: usize);
+    ///
+    /// Returns false if `id` is not a compare channel, 1 to 3.
+    fn stop(&mut self, id: usize) -> bool;
     /// Get the current calue of the free-running timer.
     fn now(&self) -> u32;
     /// Acknowledge a event on CC[`id`].
@@ -22,6 +86,134 @@ pub trait Timer {
     fn is_compare_event(&self, id: usize) -> bool;
 }
 
+/// Take the TIMER peripheral from a nrf-hal timer
+///
+/// The returned peripheral should be initialised using `Timer::init`.
+#[cfg(feature = "nrf-hal")]
+pub fn from_hal_timer<T, M>(timer: crate::hal::Timer<T, M>) -> T
+where
+    T: crate::hal::timer::Instance + Timer,
+{
+    timer.free()
+}
+
+/// Millisecond clock with delay scheduling, built on a `Timer`
+///
+/// Provides the millisecond timestamps and timeouts used by the network
+/// layers. The 32-bit microsecond timer wraps after about 71 minutes, the
+/// clock extends it to 64 bits as long as it is read at least once per wrap.
+pub struct Clock<T: Timer> {
+    timer: T,
+    /// Compare channel used for scheduling
+    channel: usize,
+    /// Upper 32 bits of the microsecond count
+    high: u32,
+    /// Last read timer value
+    last: u32,
+    /// Compare value of the scheduling channel
+    compare: u32,
+    /// A event is scheduled and not yet due
+    scheduled: bool,
+}
+
+impl<T: Timer> Clock<T> {
+    /// Initialise the timer and create the clock, `channel` is the compare
+    /// channel, 1 to 3, used for scheduling
+    ///
+    /// Returns `TimerError::InvalidChannel` if the channel is not a compare
+    /// channel.
+    pub fn new(mut timer: T, channel: usize) -> Result<Self, TimerError> {
+        if !is_valid_channel(channel) {
+            return Err(TimerError::InvalidChannel);
+        }
+        timer.init();
+        Ok(Self {
+            timer,
+            channel,
+            high: 0,
+            last: 0,
+            compare: 0,
+            scheduled: false,
+        })
+    }
+
+    /// Microseconds since the clock was created
+    pub fn microseconds(&mut self) -> u64 {
+        let now = self.timer.now();
+        if now < self.last {
+            self.high = self.high.wrapping_add(1);
+        }
+        self.last = now;
+        (u64::from(self.high) << 32) | u64::from(now)
+    }
+
+    /// Milliseconds since the clock was created
+    pub fn milliseconds(&mut self) -> u64 {
+        self.microseconds() / 1000
+    }
+
+    /// Timestamp in milliseconds, wrapping
+    pub fn timestamp(&mut self) -> u32 {
+        self.milliseconds() as u32
+    }
+
+    /// Signal the compare event in `milliseconds` milliseconds
+    pub fn schedule_in(&mut self, milliseconds: u32) {
+        let target = self
+            .timer
+            .now()
+            .wrapping_add(milliseconds.saturating_mul(1000));
+        // fire_in counts from the previous compare value
+        let scheduled = self
+            .timer
+            .fire_in(self.channel, target.wrapping_sub(self.compare));
+        // The channel was checked by `new`
+        debug_assert!(scheduled);
+        self.compare = target;
+        self.scheduled = true;
+    }
+
+    /// Check if the scheduled time has passed, acknowledges the event
+    pub fn is_due(&mut self) -> bool {
+        if self.timer.is_compare_event(self.channel) {
+            self.timer.ack_compare_event(self.channel);
+            // The compare event repeats when the timer wraps
+            core::mem::replace(&mut self.scheduled, false)
+        } else {
+            false
+        }
+    }
+
+    /// Cancel the scheduled event
+    pub fn cancel(&mut self) {
+        let stopped = self.timer.stop(self.channel);
+        debug_assert!(stopped);
+        self.scheduled = false;
+    }
+
+    /// Release the timer
+    pub fn free(self) -> T {
+        self.timer
+    }
+}
+
+/// The clock as a `embedded-time` clock, with 1 μs ticks
+///
+/// The 32-bit instants wrap after about 71 minutes, durations must be
+/// shorter than half of that.
+#[cfg(feature = "embedded-time")]
+impl<T: Timer> embedded_time::Clock for Clock<T> {
+    type T = u32;
+
+    const SCALING_FACTOR: embedded_time::fraction::Fraction =
+        embedded_time::fraction::Fraction::new(1, 1_000_000);
+
+    fn try_now(&self) -> Result<embedded_time::Instant<Self>, embedded_time::clock::Error> {
+        Ok(embedded_time::Instant::new(self.timer.now()))
+    }
+}
+
+#[cfg(feature = "device")]
 macro_rules! impl_timer {
     ($ty:ident) => {
         impl Timer for $ty {
@@ -38,8 +230,16 @@ macro_rules! impl_timer {
                 self.tasks_start.write(|w| w.tasks_start().set_bit());
             }
 
-            fn fire_in(&mut self, id: usize, elapsed: u32) {
-                assert!(id > 0 && id <= 5);
+            fn shutdown(&mut self) {
+                self.intenclr.write(|w| unsafe { w.bits(0xffff_ffff) });
+                self.tasks_stop.write(|w| w.tasks_stop().set_bit());
+                self.tasks_clear.write(|w| w.tasks_clear().set_bit());
+            }
+
+            fn fire_in(&mut self, id: usize, elapsed: u32) -> bool {
+                if !is_valid_channel(id) {
+                    return false;
+                }
                 let current = self.cc[id].read().bits();
                 let later = current.wrapping_add(elapsed);
                 self.cc[id].write(|w| unsafe { w.bits(later) });
@@ -56,10 +256,13 @@ macro_rules! impl_timer {
                     }
                     _ => (),
                 }
+                true
             }
 
-            fn stop(&mut self, id: usize) {
-                assert!(id > 0 && id <= 5);
+            fn stop(&mut self, id: usize) -> bool {
+                if !is_valid_channel(id) {
+                    return false;
+                }
                 match id {
                     1 => {
                         self.intenclr.write(|w| w.compare1().clear_bit());
@@ -73,6 +276,7 @@ macro_rules! impl_timer {
                     _ => (),
                 }
                 self.events_compare[id].reset();
+                true
             }
 
             fn now(&self) -> u32 {
@@ -81,15 +285,85 @@ macro_rules! impl_timer {
             }
 
             fn ack_compare_event(&mut self, id: usize) {
-                self.events_compare[id].reset();
+                if id < CHANNELS {
+                    self.events_compare[id].reset();
+                }
             }
 
             fn is_compare_event(&self, id: usize) -> bool {
-                self.events_compare[id].read().events_compare().bit_is_set()
+                id < CHANNELS && self.events_compare[id].read().events_compare().bit_is_set()
             }
         }
     };
 }
 
+#[cfg(feature = "device")]
 impl_timer!(TIMER0);
+#[cfg(feature = "device")]
 impl_timer!(TIMER1);
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_channel() {
+        assert!(matches!(
+            Clock::new(MockTimer::new(), 0),
+            Err(TimerError::InvalidChannel)
+        ));
+        assert!(matches!(
+            Clock::new(MockTimer::new(), CHANNELS),
+            Err(TimerError::InvalidChannel)
+        ));
+    }
+
+    #[test]
+    fn extends_across_wrap() {
+        let mut clock = Clock::new(MockTimer::new(), 1).unwrap();
+        clock.timer.advance(u32::MAX - 10);
+        assert_eq!(clock.microseconds(), u64::from(u32::MAX - 10));
+        clock.timer.advance(20);
+        assert_eq!(clock.microseconds(), (1 << 32) + 9);
+        assert_eq!(clock.milliseconds(), ((1 << 32) + 9) / 1000);
+        clock.timer.advance(u32::MAX);
+        assert_eq!(clock.microseconds(), (2 << 32) + 8);
+    }
+
+    #[test]
+    fn schedule_in() {
+        let mut clock = Clock::new(MockTimer::new(), 2).unwrap();
+        clock.timer.advance(500);
+        clock.schedule_in(5);
+        clock.timer.advance(4_999);
+        assert!(!clock.is_due());
+        clock.timer.advance(1);
+        assert!(clock.is_due());
+        // The event is acknowledged
+        assert!(!clock.is_due());
+        // Scheduling again counts from now, not from the previous target
+        clock.timer.advance(1_000);
+        clock.schedule_in(2);
+        assert_eq!(clock.timer.compare(2), 500 + 5_000 + 1_000 + 2_000);
+        clock.timer.advance(2_000);
+        assert!(clock.is_due());
+        // Not due again when the timer wraps
+        clock.timer.advance(1);
+        clock.timer.advance(u32::MAX);
+        assert!(clock.timer.is_compare_event(2));
+        assert!(!clock.is_due());
+    }
+
+    #[test]
+    fn cancel() {
+        let mut clock = Clock::new(MockTimer::new(), 3).unwrap();
+        clock.schedule_in(1);
+        clock.cancel();
+        clock.timer.advance(2_000);
+        assert!(!clock.timer.is_interrupt_pending(3));
+        assert!(!clock.is_due());
+        clock.schedule_in(1);
+        clock.timer.advance(1_000);
+        assert!(clock.is_due());
+    }
+}
@@ -0,0 +1,210 @@
+//! Hybrid timebase on the RTC and a TIMER
+
+use core::ops::Deref;
+
+use super::{Timer, TimerError};
+use crate::pac::rtc0;
+
+/// RTC frequency with prescaler zero
+const RTC_FREQUENCY: u64 = 32768;
+/// The RTC counter is 24 bits
+const RTC_COUNTER_MASK: u32 = 0x00ff_ffff;
+/// Bits of the RTC counter
+const RTC_COUNTER_BITS: u32 = 24;
+/// Compare channels present on every RTC, RTC0 has three
+const RTC_CHANNELS: usize = 3;
+/// The compare value shall be at least two ticks ahead of the counter
+const RTC_MIN_TICKS: u32 = 2;
+/// Bit of the COMPARE0 event in INTENSET and INTENCLR
+const RTC_INTEN_COMPARE0: u32 = 16;
+/// Microseconds waiting for a RTC tick in `wake`, a tick is about 31 μs
+const RTC_TICK_TIMEOUT: u32 = 100;
+
+/// Convert RTC ticks to microseconds
+fn ticks_to_microseconds(ticks: u64) -> u64 {
+    ticks * 1_000_000 / RTC_FREQUENCY
+}
+
+/// Microsecond clock sleeping on the RTC, with a TIMER around radio activity
+///
+/// The RTC, clocked by the LFCLK, keeps time at all times with a resolution
+/// of about 31 μs. Around radio activity `wake` starts the TIMER, aligned to
+/// a RTC tick, and the clock has microsecond resolution until `sleep` stops
+/// the TIMER again. The HFCLK is thus not needed for timekeeping during long
+/// sleeps, such as the poll interval of a sleepy end device.
+///
+/// The clock is continuous and never goes backwards across the switches.
+/// The 24-bit RTC counter wraps after 512 seconds, the clock extends it to
+/// 64 bits as long as it is read at least once per wrap.
+///
+/// Wakeups are scheduled on a RTC compare channel, the clock must be the
+/// only user of the compare channel.
+pub struct HybridClock<T: Timer, R: Deref<Target = rtc0::RegisterBlock>> {
+    timer: T,
+    rtc: R,
+    /// RTC compare channel used for scheduling
+    channel: usize,
+    /// Number of RTC counter wraps
+    wraps: u64,
+    /// Last read RTC counter value
+    counter: u32,
+    /// Clock value in microseconds when the TIMER was started
+    awake: Option<u64>,
+    /// Last value of the clock, in microseconds
+    last: u64,
+}
+
+impl<T, R> HybridClock<T, R>
+where
+    T: Timer,
+    R: Deref<Target = rtc0::RegisterBlock>,
+{
+    /// Create the clock, `channel` is the RTC compare channel, 0 to 2, used
+    /// for scheduling
+    ///
+    /// The RTC is started with prescaler zero, the LFCLK must be running. The
+    /// TIMER is started by `wake`.
+    ///
+    /// Returns `TimerError::InvalidChannel` if the channel does not exist.
+    pub fn new(timer: T, rtc: R, channel: usize) -> Result<Self, TimerError> {
+        if channel >= RTC_CHANNELS {
+            return Err(TimerError::InvalidChannel);
+        }
+        rtc.tasks_stop.write(|w| w.tasks_stop().set_bit());
+        rtc.prescaler.write(|w| unsafe { w.prescaler().bits(0) });
+        rtc.tasks_clear.write(|w| w.tasks_clear().set_bit());
+        rtc.tasks_start.write(|w| w.tasks_start().set_bit());
+        Ok(Self {
+            timer,
+            rtc,
+            channel,
+            wraps: 0,
+            counter: 0,
+            awake: None,
+            last: 0,
+        })
+    }
+
+    /// Start the TIMER for microsecond resolution, before radio activity
+    ///
+    /// Waits for the next RTC tick, at most about 31 μs, so that the TIMER
+    /// starts in step with the RTC.
+    ///
+    /// # Return
+    ///
+    /// Returns false if the RTC did not tick in time, when the LFCLK is not
+    /// running. The TIMER is then started without being aligned.
+    ///
+    pub fn wake(&mut self) -> bool {
+        if self.awake.is_some() {
+            return true;
+        }
+        // The TIMER bounds the wait and is started again at the tick
+        self.timer.init();
+        let from = self.rtc.counter.read().counter().bits();
+        let start = self.timer.now();
+        let ticked = loop {
+            if self.rtc.counter.read().counter().bits() != from {
+                break true;
+            }
+            if self.timer.now().wrapping_sub(start) > RTC_TICK_TIMEOUT {
+                break false;
+            }
+        };
+        self.timer.init();
+        let ticks = self.ticks();
+        self.awake = Some(ticks_to_microseconds(ticks));
+        ticked
+    }
+
+    /// Stop the TIMER, the clock continues on the RTC
+    pub fn sleep(&mut self) {
+        if self.awake.is_some() {
+            // Keep the clock continuous
+            self.microseconds();
+            self.timer.shutdown();
+            self.awake = None;
+        }
+    }
+
+    /// The TIMER is running
+    pub fn is_awake(&self) -> bool {
+        self.awake.is_some()
+    }
+
+    /// The TIMER, for timing radio activity while awake
+    pub fn timer(&mut self) -> &mut T {
+        &mut self.timer
+    }
+
+    /// Microseconds since the clock was created
+    pub fn microseconds(&mut self) -> u64 {
+        let ticks = self.ticks();
+        let now = match self.awake {
+            Some(start) => start + u64::from(self.timer.now()),
+            None => ticks_to_microseconds(ticks),
+        };
+        // The TIMER and the RTC drift apart, the clock never goes backwards
+        self.last = self.last.max(now);
+        self.last
+    }
+
+    /// Milliseconds since the clock was created
+    pub fn milliseconds(&mut self) -> u64 {
+        self.microseconds() / 1000
+    }
+
+    /// Timestamp in milliseconds, wrapping
+    pub fn timestamp(&mut self) -> u32 {
+        self.milliseconds() as u32
+    }
+
+    /// Signal the RTC compare event in `milliseconds` milliseconds, at most
+    /// 256 seconds
+    pub fn schedule_in(&mut self, milliseconds: u32) {
+        let ticks = (u64::from(milliseconds) * RTC_FREQUENCY / 1000) as u32;
+        let ticks = ticks.clamp(RTC_MIN_TICKS, RTC_COUNTER_MASK >> 1);
+        let counter = self.rtc.counter.read().counter().bits();
+        let target = counter.wrapping_add(ticks) & RTC_COUNTER_MASK;
+        self.rtc.events_compare[self.channel].reset();
+        self.rtc.cc[self.channel].write(|w| unsafe { w.compare().bits(target) });
+        self.rtc
+            .intenset
+            .write(|w| unsafe { w.bits(1 << (RTC_INTEN_COMPARE0 + self.channel as u32)) });
+    }
+
+    /// Check if the scheduled time has passed, acknowledges the event
+    pub fn is_due(&mut self) -> bool {
+        let compare = &self.rtc.events_compare[self.channel];
+        if compare.read().events_compare().bit_is_set() {
+            compare.reset();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Cancel the scheduled event
+    pub fn cancel(&mut self) {
+        self.rtc
+            .intenclr
+            .write(|w| unsafe { w.bits(1 << (RTC_INTEN_COMPARE0 + self.channel as u32)) });
+        self.rtc.events_compare[self.channel].reset();
+    }
+
+    /// Release the TIMER and the RTC, the TIMER is stopped
+    pub fn free(mut self) -> (T, R) {
+        self.sleep();
+        (self.timer, self.rtc)
+    }
+
+    /// RTC ticks since the clock was created
+    fn ticks(&mut self) -> u64 {
+        let counter = self.rtc.counter.read().counter().bits();
+        if counter < self.counter {
+            self.wraps += 1;
+        }
+        self.counter = counter;
+        (self.wraps << RTC_COUNTER_BITS) | u64::from(counter)
+    }
+}
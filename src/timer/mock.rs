@@ -0,0 +1,93 @@
+//! Timer with manually advanced time, for host-side testing
+
+use super::{is_valid_channel, Timer, CHANNELS};
+
+/// A `Timer` where time is advanced by the test
+///
+/// Time only moves when `advance` is called. A compare event is set when the
+/// time passes the compare value and `fire_in` counts from the previous
+/// compare value, as with the TIMER peripheral.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MockTimer {
+    now: u32,
+    compare: [u32; CHANNELS],
+    events: [bool; CHANNELS],
+    interrupts: [bool; CHANNELS],
+}
+
+impl MockTimer {
+    /// Create a timer at time zero
+    pub const fn new() -> Self {
+        Self {
+            now: 0,
+            compare: [0; CHANNELS],
+            events: [false; CHANNELS],
+            interrupts: [false; CHANNELS],
+        }
+    }
+
+    /// Advance the time by `elapsed` microseconds, setting the compare
+    /// events passed
+    pub fn advance(&mut self, elapsed: u32) {
+        for id in 1..CHANNELS {
+            let until = self.compare[id].wrapping_sub(self.now);
+            if until != 0 && until <= elapsed {
+                self.events[id] = true;
+            }
+        }
+        self.now = self.now.wrapping_add(elapsed);
+    }
+
+    /// Compare value of CC[`id`]
+    pub fn compare(&self, id: usize) -> u32 {
+        self.compare[id]
+    }
+
+    /// Check if the compare event on CC[`id`] would interrupt
+    pub fn is_interrupt_pending(&self, id: usize) -> bool {
+        self.events[id] && self.interrupts[id]
+    }
+}
+
+impl Timer for MockTimer {
+    fn init(&mut self) {
+        *self = Self::new();
+    }
+
+    fn shutdown(&mut self) {
+        *self = Self::new();
+    }
+
+    fn fire_in(&mut self, id: usize, elapsed: u32) -> bool {
+        if !is_valid_channel(id) {
+            return false;
+        }
+        self.compare[id] = self.compare[id].wrapping_add(elapsed);
+        self.events[id] = false;
+        self.interrupts[id] = true;
+        true
+    }
+
+    fn stop(&mut self, id: usize) -> bool {
+        if !is_valid_channel(id) {
+            return false;
+        }
+        self.interrupts[id] = false;
+        self.events[id] = false;
+        true
+    }
+
+    fn now(&self) -> u32 {
+        self.now
+    }
+
+    fn ack_compare_event(&mut self, id: usize) {
+        if id < CHANNELS {
+            self.events[id] = false;
+        }
+    }
+
+    fn is_compare_event(&self, id: usize) -> bool {
+        id < CHANNELS && self.events[id]
+    }
+}
@@ -0,0 +1,293 @@
+//! # wpanusb USB radio interface
+//!
+//! A `usb-device` class implementing the wpanusb protocol, which makes a
+//! nRF52840 dongle appear to a Linux host as a IEEE 802.15.4 device of the
+//! kernel wpan stack.
+//!
+//! The host controls the radio with vendor control requests on the
+//! interface. Received frames are sent to the host on a bulk IN endpoint,
+//! followed by the LQI octet.
+//!
+//! | Request | Name                   | Data                          |
+//! |---------|------------------------|-------------------------------|
+//! | 0       | Reset                  | none                          |
+//! | 1       | Transmit               | sequence, frame without FCS   |
+//! | 2       | Transmit asynchronous  | sequence, frame without FCS   |
+//! | 3       | Energy detect          | rejected                      |
+//! | 4       | Set channel            | page, channel (1 octet each)  |
+//! | 5       | Start                  | none                          |
+//! | 6       | Stop                   | none                          |
+//! | 7       | Set short address      | short address (2 octets)      |
+//! | 8       | Set PAN identifier     | PAN identifier (2 octets)     |
+//! | 9       | Set extended address   | extended address (8 octets)   |
+//! | 10      | Set transmission power | power in dBm (1 octet)        |
+//! | 17      | Get extended address   | extended address (8 octets)   |
+//! | 18      | Get supported channels | channel mask (4 octets)       |
+//!
+//! Multi-octet fields are little endian. Other requests are accepted and
+//! ignored. The requests are executed on the radio by `WpanUsb::process`,
+//! outside of the USB interrupt.
+//!
+//! The completion of a transmission is reported on the bulk IN endpoint as
+//! a single octet, the sequence of the transmit request if the frame was
+//! sent, or zero if it could not be sent.
+//!
+
+use usb_device::class_prelude::{
+    ControlIn, ControlOut, DescriptorWriter, EndpointIn, InterfaceNumber, UsbBus,
+    UsbBusAllocator, UsbClass, UsbError,
+};
+use usb_device::control::RequestType;
+
+use crate::phy::MAX_FRAME_LENGTH;
+use crate::radio::{is_valid_channel, is_valid_transmission_power, DeviceAddress, Radio};
+
+/// Reset request
+pub const REQUEST_RESET: u8 = 0;
+/// Transmit request
+pub const REQUEST_TX: u8 = 1;
+/// Asynchronous transmit request
+pub const REQUEST_XMIT_ASYNC: u8 = 2;
+/// Energy detect request
+pub const REQUEST_ED: u8 = 3;
+/// Set channel request
+pub const REQUEST_SET_CHANNEL: u8 = 4;
+/// Start request
+pub const REQUEST_START: u8 = 5;
+/// Stop request
+pub const REQUEST_STOP: u8 = 6;
+/// Set short address request
+pub const REQUEST_SET_SHORT_ADDR: u8 = 7;
+/// Set PAN identifier request
+pub const REQUEST_SET_PAN_ID: u8 = 8;
+/// Set extended address request
+pub const REQUEST_SET_IEEE_ADDR: u8 = 9;
+/// Set transmission power request
+pub const REQUEST_SET_TXPOWER: u8 = 10;
+/// Get extended address request
+pub const REQUEST_GET_EXTENDED_ADDR: u8 = 17;
+/// Get supported channels request
+pub const REQUEST_GET_SUPPORTED_CHANNELS: u8 = 18;
+
+/// Channels 11 to 26 of page 0
+const SUPPORTED_CHANNELS: u32 = 0x07ff_f800;
+
+/// Largest request data, a transmit request with the sequence octet
+const MAX_REQUEST_LENGTH: usize = MAX_FRAME_LENGTH + 1;
+/// Completion status of a failed transmission
+const STATUS_FAILED: u8 = 0;
+
+/// Bulk endpoint packet size
+const PACKET_SIZE: u16 = 64;
+
+/// A request waiting to be executed on the radio
+struct PendingRequest {
+    request: u8,
+    data: [u8; MAX_REQUEST_LENGTH],
+    length: usize,
+}
+
+impl PendingRequest {
+    fn data(&self) -> &[u8] {
+        &self.data[..self.length]
+    }
+}
+
+/// wpanusb class
+pub struct WpanUsb<'a, B: UsbBus> {
+    interface: InterfaceNumber,
+    endpoint: EndpointIn<'a, B>,
+    address: DeviceAddress,
+    pending: Option<PendingRequest>,
+    /// Sequence of the ongoing transmission
+    transmission: Option<u8>,
+    /// Completion status not yet sent to the host
+    status: Option<u8>,
+}
+
+impl<'a, B: UsbBus> WpanUsb<'a, B> {
+    /// Allocate the class, `extended_address` is reported to the host
+    pub fn new(allocator: &'a UsbBusAllocator<B>, extended_address: u64) -> Self {
+        Self {
+            interface: allocator.interface(),
+            endpoint: allocator.bulk(PACKET_SIZE),
+            address: DeviceAddress {
+                extended_address,
+                ..DeviceAddress::default()
+            },
+            pending: None,
+            transmission: None,
+            status: None,
+        }
+    }
+
+    /// Execute a pending host request on the radio and report completed
+    /// transmissions
+    ///
+    /// # Return
+    ///
+    /// Returns true if a request was executed.
+    ///
+    pub fn process<const RX: usize>(&mut self, radio: &mut Radio<RX>) -> bool {
+        self.complete_transmission(radio);
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return false,
+        };
+        let data = pending.data();
+        match pending.request {
+            REQUEST_RESET | REQUEST_STOP => radio.disable(),
+            REQUEST_START => radio.receive_prepare(),
+            REQUEST_TX | REQUEST_XMIT_ASYNC => {
+                if let Some((sequence, frame)) = data.split_first() {
+                    if radio.queue_transmission(frame).is_ok() {
+                        self.transmission = Some(*sequence);
+                    } else {
+                        self.status = Some(STATUS_FAILED);
+                        self.send_status();
+                    }
+                }
+            }
+            REQUEST_SET_CHANNEL if data.len() == 2 && data[0] == 0 && is_valid_channel(data[1]) => {
+                let _ = radio.set_channel(data[1]);
+            }
+            REQUEST_SET_TXPOWER
+                if data.len() == 1 && is_valid_transmission_power(data[0] as i8) =>
+            {
+                let _ = radio.set_transmission_power(data[0] as i8);
+            }
+            REQUEST_SET_SHORT_ADDR | REQUEST_SET_PAN_ID | REQUEST_SET_IEEE_ADDR => {
+                radio.set_address(self.address);
+            }
+            _ => (),
+        }
+        true
+    }
+
+    /// Report the ongoing transmission once the radio is done with it
+    fn complete_transmission<const RX: usize>(&mut self, radio: &mut Radio<RX>) {
+        if let Some(sequence) = self.transmission {
+            if !radio.is_tx_busy() {
+                self.transmission = None;
+                // The CCA level is only set when the CCA failed
+                self.status = match radio.cca_level() {
+                    Some(_) => Some(STATUS_FAILED),
+                    None => Some(sequence),
+                };
+            }
+        }
+        self.send_status();
+    }
+
+    /// Send the completion status, it is kept if the endpoint is busy
+    fn send_status(&mut self) {
+        if let Some(status) = self.status {
+            if self.endpoint.write(&[status]).is_ok() {
+                self.status = None;
+            }
+        }
+    }
+
+    /// Send a received frame to the host
+    ///
+    /// `data` is the frame without FCS.
+    ///
+    /// # Return
+    ///
+    /// Returns false if the endpoint was busy and the frame was dropped.
+    ///
+    pub fn send_frame(&mut self, data: &[u8], lqi: u8) -> Result<bool, UsbError> {
+        if data.len() > MAX_FRAME_LENGTH {
+            return Ok(false);
+        }
+        let mut packet = [0u8; MAX_FRAME_LENGTH + 1];
+        packet[..data.len()].copy_from_slice(data);
+        packet[data.len()] = lqi;
+        let mut packet = &packet[..=data.len()];
+        // Frames longer than a USB packet are written in several packets
+        while !packet.is_empty() {
+            let length = packet.len().min(usize::from(PACKET_SIZE));
+            match self.endpoint.write(&packet[..length]) {
+                Ok(_) => packet = &packet[length..],
+                Err(UsbError::WouldBlock) => return Ok(false),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Update the address from a request, the radio is updated by `process`
+    fn update_address(&mut self, request: u8, data: &[u8]) -> bool {
+        match (request, data.len()) {
+            (REQUEST_SET_SHORT_ADDR, 2) => {
+                self.address.short_address = u16::from_le_bytes([data[0], data[1]]);
+            }
+            (REQUEST_SET_PAN_ID, 2) => {
+                self.address.pan_id = u16::from_le_bytes([data[0], data[1]]);
+            }
+            (REQUEST_SET_IEEE_ADDR, 8) => {
+                let mut address = [0u8; 8];
+                address.copy_from_slice(data);
+                self.address.extended_address = u64::from_le_bytes(address);
+            }
+            (REQUEST_SET_SHORT_ADDR, _)
+            | (REQUEST_SET_PAN_ID, _)
+            | (REQUEST_SET_IEEE_ADDR, _) => return false,
+            _ => (),
+        }
+        true
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for WpanUsb<'_, B> {
+    fn get_configuration_descriptors(
+        &self,
+        writer: &mut DescriptorWriter,
+    ) -> usb_device::Result<()> {
+        writer.interface(self.interface, 0xff, 0, 0)?;
+        writer.endpoint(&self.endpoint)?;
+        Ok(())
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let request = xfer.request();
+        if request.request_type != RequestType::Vendor {
+            return;
+        }
+        let data = xfer.data();
+        if data.len() > MAX_REQUEST_LENGTH
+            || request.request == REQUEST_ED
+            || self.pending.is_some()
+            || !self.update_address(request.request, data)
+        {
+            xfer.reject().ok();
+            return;
+        }
+        let mut pending = PendingRequest {
+            request: request.request,
+            data: [0u8; MAX_REQUEST_LENGTH],
+            length: data.len(),
+        };
+        pending.data[..data.len()].copy_from_slice(data);
+        self.pending = Some(pending);
+        xfer.accept().ok();
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let request = xfer.request();
+        if request.request_type != RequestType::Vendor {
+            return;
+        }
+        match request.request {
+            REQUEST_GET_EXTENDED_ADDR => {
+                xfer.accept_with(&self.address.extended_address.to_le_bytes()).ok();
+            }
+            REQUEST_GET_SUPPORTED_CHANNELS => {
+                xfer.accept_with(&SUPPORTED_CHANNELS.to_le_bytes()).ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+}